@@ -15,35 +15,60 @@ limitations under the License.
 </copyright>*/
 
 pub mod client {
-    use std::net::{TcpStream, UdpSocket};
-    use std::{io, str, thread, process, time};
-    use std::time::{Instant, SystemTime, Duration, UNIX_EPOCH};
+    use std::{io, thread, process, time};
+    use std::time::{Instant, SystemTime};
     use std::sync::{Arc};
+    use std::collections::BTreeMap;
     use std::ops::Add;
     use std::fs::File;
-    use std::io::{Write, Read, ErrorKind};
-    use std::str::from_utf8;
 
-    use csv::Writer;
+    use tokio::net::TcpStream;
+    use tokio::sync::oneshot;
 
-    use crate::messages::InitMessage;
+    use crate::control::{ControlMessage, ServerStatistics, CAPABILITIES, CAPABILITY_SERVER_STATISTICS, CONTROL_PROTOCOL_VERSION, HANDSHAKE_TIMEOUT, read_message, write_message};
+    use crate::framing::{read_frame, write_frame};
+    use crate::rate_limited_log::RateLimitedLog;
+    use crate::histogram::{LatencyHistograms, ReceivedIndices};
+    use crate::packet_csv;
     use crate::test_result::TestResult;
-    use crate::packet::{SentPacket, ReceivedPacket};
-    use crate::test_parameters::TestParameters;
+    use crate::packet::{SentPacket, ReceivedPacket, PacketHeader, TestClock, HEADER_LENGTH, FLAG_PROBE, encode_packet, system_time_ns};
+    use crate::test_parameters::{TestParameters, RecordingMode};
+    use crate::config::ClientConfig;
+    use crate::error::{RperfError, Result};
+    use crate::event_stream::EventSink;
+    use crate::interval_report::{IntervalReport, IntervalRecorder, SentPacketsCounter, DEFAULT_REPORT_INTERVAL};
+
+    /// Probes sent within the handshake timeout before the server is considered unreachable over UDP
+    const PROBE_ATTEMPTS: u32 = 5;
 
     pub struct Client {
         pub test_parameters: TestParameters,
         server_address: String,
+        session_id: u32,
         sent_packets: Vec<SentPacket>,
         received_packets: Vec<ReceivedPacket>,
+        histograms: Option<LatencyHistograms>,
+        histogram_received_packets_count: u64,
+        histogram_duplicate_packets_count: u64,
+        /// Tells the lost packets in histogram mode
+        histogram_received_indices: ReceivedIndices,
         message_interval: f64,
         expected_packet_count_warmup: u64,
         expected_packet_count_total: u64,
-        log_path: String
+        log_path: String,
+        ndjson_path: String,
+        event_sink: Option<EventSink>,
+        interval_reports: Vec<IntervalReport>,
+        /// Capabilities negotiated on the control connection
+        capabilities: Vec<String>,
+        server_statistics: Option<ServerStatistics>,
+        #[cfg(feature = "prometheus")]
+        metrics: crate::metrics::ClientMetrics
     }
 
     impl Client {
-        pub fn new(server_ip: &str, server_port: u16, protocol: &str, test_duration: Duration, packets_per_second: u32, packet_size: usize, warmup_duration: Duration, log_path: &str, output_rtt: bool, measure_owl: bool) -> Client {
+        pub fn from_config(config: ClientConfig) -> Client {
+            let ClientConfig { server_ip, server_port, protocol, test_duration, packets_per_second, packet_size, warmup_duration, log_path, ndjson_path, output_rtt, measure_owl, recording_mode, deadlines, max_deadline_miss_ratio, report_interval } = config;
             if warmup_duration.as_secs() > 0
             {
                 println!("Warmup Time [s]: {}", warmup_duration.as_secs());
@@ -51,138 +76,230 @@ pub mod client {
             else {
                 println!("No warmup");
             }
-            let expected_packet_count_warmup = warmup_duration.as_millis() as u64 / 1000_u64 * packets_per_second.clone() as u64;
-            let expected_packet_count_valid = test_duration.as_millis() as u64 / 1000_u64 * packets_per_second.clone() as u64;
+            let expected_packet_count_warmup = warmup_duration.as_millis() as u64 / 1000_u64 * packets_per_second as u64;
+            let expected_packet_count_valid = test_duration.as_millis() as u64 / 1000_u64 * packets_per_second as u64;
+            let session_id = Client::generate_session_id();
+            let server_address = format!("{}:{}", &server_ip, &server_port);
             Client {
                 test_parameters: TestParameters {
-                    server_ip: server_ip.to_string(),
-                    server_port: server_port.clone(),
-                    protocol: protocol.to_string(),
+                    server_ip,
+                    server_port,
+                    protocol: protocol.to_lowercase(),
                     test_duration_valid: test_duration,
                     test_duration_total: test_duration.add(warmup_duration),
                     warmup_duration,
                     packets_per_second,
                     packet_size,
                     output_rtt,
-                    measure_owl
+                    measure_owl,
+                    recording_mode,
+                    deadlines,
+                    max_deadline_miss_ratio,
+                    report_interval
                 },
-                server_address: format!("{}:{}", &server_ip, &server_port),
+                #[cfg(feature = "prometheus")]
+                metrics: crate::metrics::registry().client(session_id, &server_address, &protocol),
+                server_address,
+                session_id,
                 sent_packets: Vec::new(),
                 received_packets: Vec::new(),
-                message_interval: 1_f64 / packets_per_second.clone() as f64 * 1000_000_f64,
+                histograms: None,
+                histogram_received_packets_count: 0,
+                histogram_duplicate_packets_count: 0,
+                histogram_received_indices: ReceivedIndices::new(0),
+                message_interval: 1_f64 / packets_per_second as f64 * 1_000_000_f64,
                 expected_packet_count_warmup,
                 expected_packet_count_total: expected_packet_count_warmup + expected_packet_count_valid,
-                log_path: String::from(log_path)
+                log_path: log_path.unwrap_or_default(),
+                ndjson_path: ndjson_path.unwrap_or_default(),
+                event_sink: None,
+                interval_reports: Vec::new(),
+                capabilities: Vec::new(),
+                server_statistics: None
             }
         }
 
-        pub async fn run_test(&mut self) -> std::io::Result<TestResult> {
-            match &self.test_parameters.protocol.as_ref() {
-                &"udp" => {
-                    let test_result = self.run_udp_test().await.unwrap_or_else(|error| {
-                       panic!("Problem starting test: {:?}", error);
-                    });
-                    Ok(test_result)
-                },
-                &"tcp" => {
-                    let test_result = self.run_tcp_test().unwrap_or_else(|error| {
-                        panic!("Problem starting test: {:?}", error);
-                    });
-                    Ok(test_result)
-                },
-                _ => {
-                    println!("Unsupported protocol '' ");
-                    Err(std::io::Error::new(ErrorKind::Unsupported, format!{"Unsupported protocol '{}'", self.test_parameters.protocol}))
-                },
+        pub async fn run_test(&mut self) -> Result<TestResult> {
+            if !self.ndjson_path.is_empty() {
+                self.event_sink = Some(EventSink::create(&self.ndjson_path)?);
+            }
+
+            match self.test_parameters.protocol.as_ref() {
+                "udp" => self.run_udp_test().await,
+                "tcp" => self.run_tcp_test().await,
+                _ => Err(RperfError::InvalidConfig(format!("Unsupported protocol '{}'", self.test_parameters.protocol)))
             }
         }
 
-        pub fn generate_sent_packet(packet_index: &u64, packet_size: usize, sent_duration: Duration) -> (SentPacket, Vec<u8>) {
-            let packet_index_bytes = packet_index.to_be_bytes();
-            let mut payload = vec![1u8; packet_size];
-            payload[0..=7].copy_from_slice(&packet_index_bytes);
-            let current_system_time_unix_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-            let current_system_time_unix_epoch_ms = current_system_time_unix_epoch.as_secs() as f64
-                + current_system_time_unix_epoch.subsec_nanos() as f64 * 1e-9;
-            payload[8..=15].copy_from_slice(&current_system_time_unix_epoch_ms.to_be_bytes());
+        fn generate_session_id() -> u32 {
+            let time_ns = system_time_ns();
+            (time_ns ^ (time_ns >> 32)) as u32 ^ process::id().rotate_left(16)
+        }
+
+        pub fn generate_sent_packet(session_id: u32, packet_index: u64, packet_size: usize, clock: &TestClock) -> (SentPacket, Vec<u8>) {
+            let sent_duration = clock.elapsed();
+            let mut header = PacketHeader::new(session_id, packet_index, 0);
+            header.client_timestamp_ns = clock.timestamp_ns(sent_duration);
+            let payload = encode_packet(&header, packet_size);
 
             let sent_packet = SentPacket {
-                index: *packet_index,
+                index: packet_index,
                 sent_duration,
-                sent_timestamp: current_system_time_unix_epoch,
+                sent_timestamp_ns: header.client_timestamp_ns,
                 is_warmup: false
             };
 
-            return (sent_packet, payload);
+            (sent_packet, payload)
         }
 
-        pub fn generate_received_packet(buf: Vec<u8>, received_duration: Duration) -> ReceivedPacket {
-            let mut packet_index_bytes = [0; 8];
-            packet_index_bytes[..8].copy_from_slice(&buf[0..=7]);
-            let received_packet_index = u64::from_be_bytes(packet_index_bytes);
+        pub fn generate_received_packet(buf: &[u8], session_id: u32, clock: &TestClock) -> std::io::Result<ReceivedPacket> {
+            let received_duration = clock.elapsed();
+            let received_timestamp_ns = clock.timestamp_ns(received_duration);
 
-            let mut timestamp_bytes = [0; 8];
-            timestamp_bytes[..8].copy_from_slice(&buf[8..=15]);
-            let server_timestamp_unix_epoch = Duration::from_secs_f64(f64::from_be_bytes(timestamp_bytes));
-
-            let receive_timestamp_unix_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            let header = PacketHeader::decode(buf)?;
+            // Replies to repeated probes may arrive after the test started
+            if !header.is_pong() || header.is_probe() || header.session_id != session_id {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected packet (session {}, flags {:#04x})", header.session_id, header.flags)));
+            }
 
             let received_packet = ReceivedPacket {
-                index: received_packet_index,
+                index: header.sequence,
                 received_duration,
-                received_timestamp: receive_timestamp_unix_epoch,
-                server_timestamp: server_timestamp_unix_epoch,
+                sent_timestamp_ns: header.client_timestamp_ns,
+                received_timestamp_ns,
+                server_rx_timestamp_ns: header.server_rx_timestamp_ns,
+                server_tx_timestamp_ns: header.server_tx_timestamp_ns,
+            };
+
+            Ok(received_packet)
+        }
+
+        /// Opens the control connection and negotiates the session with the server
+        async fn open_session(&mut self) -> Result<TcpStream> {
+            let mut control = TcpStream::connect(&self.server_address).await.map_err(|e| RperfError::from_connection_error(&self.server_address, e))?;
+            control.set_nodelay(true)?;
+
+            let hello = ControlMessage::Hello {
+                version: CONTROL_PROTOCOL_VERSION,
+                session_id: self.session_id,
+                protocol: self.test_parameters.protocol.clone(),
+                packet_size: self.test_parameters.packet_size,
+                capabilities: CAPABILITIES.iter().map(|capability| capability.to_string()).collect()
+            };
+            write_message(&mut control, &hello).await?;
+            let response = match tokio::time::timeout(HANDSHAKE_TIMEOUT, read_message(&mut control)).await {
+                Ok(response) => response.map_err(|e| RperfError::ProtocolMismatch { server: self.server_address.clone(), reason: format!("Invalid response to hello: {}", e) })?,
+                Err(_) => return Err(RperfError::Timeout { server: self.server_address.clone() })
             };
+            match response {
+                ControlMessage::Accept { version, .. } | ControlMessage::Reject { version, .. } if version != CONTROL_PROTOCOL_VERSION => {
+                    Err(RperfError::ProtocolMismatch { server: self.server_address.clone(), reason: format!("Server uses control protocol version {} (expected {})", version, CONTROL_PROTOCOL_VERSION) })
+                }
+                ControlMessage::Accept { capabilities, .. } => {
+                    println!("Successfully connected to server '{}'", &self.server_address);
+                    self.capabilities = capabilities;
+                    Ok(control)
+                }
+                ControlMessage::Reject { reason, .. } => Err(RperfError::Handshake { server: self.server_address.clone(), reason }),
+                message => Err(RperfError::Handshake { server: self.server_address.clone(), reason: format!("Unexpected response to hello: {:?}", message) })
+            }
+        }
 
-            return received_packet;
+        /// Ends the session, a test without statistics of the server is still valid
+        async fn stop_session(&mut self, mut control: TcpStream) {
+            if let Err(e) = write_message(&mut control, &ControlMessage::Stop).await {
+                eprintln!("Failed to end session with server '{}': {}", &self.server_address, e);
+                return;
+            }
+            if !self.capabilities.iter().any(|capability| capability == CAPABILITY_SERVER_STATISTICS) {
+                return;
+            }
+
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, read_message(&mut control)).await {
+                Ok(Ok(ControlMessage::Statistics(server_statistics))) => self.server_statistics = Some(server_statistics),
+                Ok(Ok(message)) => eprintln!("Unexpected response of server '{}' to stop: {:?}", &self.server_address, message),
+                Ok(Err(e)) => eprintln!("Failed to receive statistics of server '{}': {}", &self.server_address, e),
+                Err(_) => eprintln!("Server '{}' sent no statistics within {:?}", &self.server_address, HANDSHAKE_TIMEOUT)
+            }
         }
 
-        async fn run_udp_test(&mut self) -> std::io::Result<TestResult> {
-            let sender_socket = UdpSocket::bind("0.0.0.0:0")?;
-            sender_socket.set_read_timeout(Some(time::Duration::from_secs(3))).unwrap();
-            sender_socket.connect(&self.server_address.clone())?;
-            sender_socket.send("_client_".as_bytes())?;
-            let receiver_socket = sender_socket.try_clone().unwrap();
+        async fn run_udp_test(&mut self) -> Result<TestResult> {
+            let mut control = self.open_session().await?;
+            let socket = tokio::net::UdpSocket::bind("0.0.0.0:0").await?;
+            socket.connect(&self.server_address).await?;
+            // The probe tells the server which address the pings of the session come from, it is repeated until
+            // the server answers, so a lost probe or reply does not end the test
+            let probe = encode_packet(&PacketHeader::new(self.session_id, 0, FLAG_PROBE), HEADER_LENGTH);
             let mut buf = [0u8; 1500];
-            match sender_socket.recv_from(&mut buf) {
-                Ok((_number_of_bytes, _src_addr)) => {
+            let mut attempt = 0;
+            let number_of_bytes = loop {
+                if attempt == PROBE_ATTEMPTS {
+                    return Err(RperfError::Timeout { server: self.server_address.clone() });
+                }
+                attempt += 1;
+                socket.send(&probe).await.map_err(|e| RperfError::from_connection_error(&self.server_address, e))?;
+                if let Ok(received) = tokio::time::timeout(HANDSHAKE_TIMEOUT / PROBE_ATTEMPTS, socket.recv(&mut buf)).await {
+                    break received.map_err(|e| RperfError::from_connection_error(&self.server_address, e))?;
+                }
+            };
+            match PacketHeader::decode(&buf[..number_of_bytes]) {
+                Ok(header) if header.is_pong() && header.is_probe() && header.session_id == self.session_id => {
                     println!("Server '{}' is reachable", &self.server_address);
                 }
-                Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => {
-                    println!("Server '{}' refused connection", &self.server_address);
-                    process::exit(1);
+                Ok(_) => {
+                    return Err(RperfError::Handshake { server: self.server_address.clone(), reason: "Unexpected reply to probe".to_string() });
                 }
-                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
-                    println!("Server '{}' not reachable", &self.server_address);
-                    process::exit(1);
+                Err(e) => {
+                    return Err(RperfError::ProtocolMismatch { server: self.server_address.clone(), reason: e.to_string() });
                 }
-                Err(e) => panic!("Error communicating with server '{}': {:?}", &self.server_address, e)
             }
 
-            let instant_sender_thread = Arc::new(Instant::now());
-            let instant_receiver_thread = instant_sender_thread.clone();
+            // Pings are sent and received with blocking sockets on dedicated threads
+            let sender_socket = socket.into_std()?;
+            sender_socket.set_nonblocking(false)?;
+            let receiver_socket = sender_socket.try_clone()?;
+
+            let clock_sender_thread = Arc::new(TestClock::new());
+            let clock_receiver_thread = clock_sender_thread.clone();
 
             let _timer = howlong::HighResolutionTimer::new();
 
+            write_message(&mut control, &ControlMessage::Start).await?;
             println!("Starting test against server '{}'", &self.server_address);
             let expected_packet_count = self.expected_packet_count_total;
             let max_warmup_packet_index = self.expected_packet_count_warmup;
             let packet_size = self.test_parameters.packet_size;
             let message_interval = self.message_interval;
-            let thread_send = thread::spawn(move || -> std::io::Result<Vec<SentPacket>> {
-                let mut sent_packets: Vec<SentPacket> = Vec::with_capacity((expected_packet_count + 10) as usize);
+            let session_id = self.session_id;
+            let record_sent_packets = self.test_parameters.recording_mode == RecordingMode::PerPacket;
+            let event_sink = self.event_sink.clone();
+            let sent_packets_counter = self.sent_packets_counter();
+            let sent_packets_counter_sender_thread = sent_packets_counter.clone();
+            #[cfg(feature = "prometheus")]
+            let metrics_sender_thread = self.metrics.clone();
+            let thread_send = spawn_dedicated("rperf-sender", move || -> std::io::Result<Vec<SentPacket>> {
+                let mut sent_packets: Vec<SentPacket> = Vec::with_capacity(if record_sent_packets { (expected_packet_count + 10) as usize } else { 0 });
                 let mut packet_index = 0_u64;
                 let mut last_sent_time = SystemTime::now();
 
-                while packet_index < expected_packet_count as u64 {
+                while packet_index < expected_packet_count {
 
-                    let (sent_packet, payload) = Client::generate_sent_packet(&packet_index, packet_size, instant_sender_thread.elapsed());
-                    sent_packets.push(sent_packet);
+                    let (sent_packet, payload) = Client::generate_sent_packet(session_id, packet_index, packet_size, &clock_sender_thread);
+                    // Nothing may delay the packet after its send timestamp was taken
                     sender_socket.send(&payload)?;
+                    if let Some(event_sink) = &event_sink {
+                        event_sink.packet_sent(&sent_packet, packet_index < max_warmup_packet_index);
+                    }
+                    sent_packets_counter_sender_thread.packet_sent(sent_packet.sent_duration);
+                    if record_sent_packets {
+                        sent_packets.push(sent_packet);
+                    }
+                    #[cfg(feature = "prometheus")]
+                    metrics_sender_thread.packet_sent();
 
                     packet_index += 1;
 
-                    while last_sent_time.elapsed().unwrap().as_micros() < message_interval as u128 {
+                    while last_sent_time.elapsed().unwrap_or_default().as_micros() < message_interval as u128 {
                     }
                     last_sent_time = SystemTime::now();
                 }
@@ -197,99 +314,99 @@ pub mod client {
                 }
 
                 Ok(sent_packets)
-            });
+            })?;
 
             let test_duration = self.test_parameters.test_duration_total;
-            let thread_receive = thread::spawn(move || -> std::io::Result<Vec<ReceivedPacket>> {
-                let mut received_packets: Vec<ReceivedPacket> = Vec::with_capacity((expected_packet_count + 10) as usize);
+            let mut recorder = PacketRecorder::new(&self.test_parameters, expected_packet_count, max_warmup_packet_index, self.event_sink.clone(), sent_packets_counter, clock_receiver_thread.start())?;
+            #[cfg(feature = "prometheus")]
+            {
+                recorder.metrics = Some(self.metrics.clone());
+            }
+            let thread_receive = spawn_dedicated("rperf-receiver", move || -> std::io::Result<PacketRecorder> {
 
                 receiver_socket.set_read_timeout(Some(time::Duration::from_secs(3)))?;
-                'outer: while instant_receiver_thread.elapsed() < test_duration + time::Duration::from_millis(1000) {
+                let mut rejections_log = RateLimitedLog::new("packets rejected");
+                'outer: while clock_receiver_thread.elapsed() < test_duration + time::Duration::from_millis(1000) {
                     let mut buf = [0u8; 1500];
 
-                    let (_num_bytes_read, _) = loop {
-                        match receiver_socket.recv_from(&mut buf) {
-                            Ok(n) => break n,
-                            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
-                                break 'outer;
-                            }
-                            Err(e) => panic!("encountered IO error: {}", e),
+                    let (num_bytes_read, _) = match receiver_socket.recv_from(&mut buf) {
+                        Ok(n) => n,
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+                            break 'outer;
                         }
+                        Err(e) => return Err(e),
                     };
 
-                    let received_packet = Client::generate_received_packet(buf.to_vec(), instant_receiver_thread.elapsed());
-                    received_packets.push(received_packet);
+                    match Client::generate_received_packet(&buf[..num_bytes_read], session_id, &clock_receiver_thread) {
+                        Ok(received_packet) => recorder.record(received_packet),
+                        Err(e) => rejections_log.log(format_args!("Rejected packet: {}", e))
+                    }
                 }
 
-                Ok(received_packets)
-            });
+                Ok(recorder)
+            })?;
 
-            self.sent_packets = thread_send.join().unwrap().unwrap();
-            self.received_packets = thread_receive.join().unwrap().unwrap();
+            self.sent_packets = join_dedicated(thread_send, "sender").await?;
+            self.take_recorded_packets(join_dedicated(thread_receive, "receiver").await?);
+            self.stop_session(control).await;
 
             let test_result = self.generate_result()?;
 
-            return Ok(test_result);
+            Ok(test_result)
         }
 
-        pub fn run_tcp_test(&mut self) -> std::io::Result<TestResult> {
-            match TcpStream::connect(&self.server_address.clone()) {
+        async fn run_tcp_test(&mut self) -> Result<TestResult> {
+            let mut control = self.open_session().await?;
+            // Pings are sent on a measurement connection of the session
+            match TcpStream::connect(&self.server_address).await {
                 Ok(mut stream) => {
-                    // Send init message and wait for response
-                    let init_message = InitMessage {
-                        packet_size: self.test_parameters.packet_size
-                    };
-                    let init_message_json = serde_json::to_string(&init_message).unwrap();
-                    stream.write(init_message_json.as_bytes())?;
-                    let mut buf = [0; 1500];
-                    loop {
-                        let n = match stream.read(&mut buf) {
-                            Ok(n) if n == 0 => continue,
-                            Ok(n) => n,
-                            Err(e) => {
-                                eprintln!("failed to read from socket; err = {:?}", e);
-                                continue;
-                            }
-                        };
-                        let server_response = from_utf8(&buf[..n]).unwrap();
-                        if server_response.eq("OK")
-                        {
-                            println!("Successfully connected to server '{}'", &self.server_address);
-                            break;
-                        }
-                        else
-                        {
-                            panic!("Invalid server response: {}", server_response);
-                        }
-                    }
+                    write_message(&mut stream, &ControlMessage::Measurement { session_id: self.session_id }).await?;
+                    write_message(&mut control, &ControlMessage::Start).await?;
 
                     println!("Starting test against server '{}'", &self.server_address);
-                    let mut stream_clone = stream.try_clone().unwrap();
-                    let instant_sender_thread = Arc::new(Instant::now());
-                    let instant_receiver_thread = instant_sender_thread.clone();
-
+                    // Pings are sent and received with a blocking stream on dedicated threads
+                    let mut stream = stream.into_std()?;
+                    stream.set_nonblocking(false)?;
+                    let mut stream_clone = stream.try_clone()?;
+                    let clock_sender_thread = Arc::new(TestClock::new());
+                    let clock_receiver_thread = clock_sender_thread.clone();
+        
                     let expected_packet_count = self.expected_packet_count_total;
                     let max_warmup_packet_index = self.expected_packet_count_warmup;
                     let packet_size = self.test_parameters.packet_size;
                     let message_interval = self.message_interval;
-                    let thread_send = thread::spawn(move || {
+                    let session_id = self.session_id;
+                    let record_sent_packets = self.test_parameters.recording_mode == RecordingMode::PerPacket;
+                    let event_sink = self.event_sink.clone();
+                    let sent_packets_counter = self.sent_packets_counter();
+                    let sent_packets_counter_sender_thread = sent_packets_counter.clone();
+                    #[cfg(feature = "prometheus")]
+                    let metrics_sender_thread = self.metrics.clone();
+                    let thread_send = spawn_dedicated("rperf-sender", move || -> std::io::Result<Vec<SentPacket>> {
                         // Configure stream
-                        stream.set_nodelay(true).unwrap();
+                        stream.set_nodelay(true)?;
 
-                        let mut sent_packets: Vec<SentPacket> = Vec::with_capacity((expected_packet_count + 10) as usize);
+                        let mut sent_packets: Vec<SentPacket> = Vec::with_capacity(if record_sent_packets { (expected_packet_count + 10) as usize } else { 0 });
                         let mut packet_index = 0_u64;
                         let mut last_sent_time = SystemTime::now();
-                        while packet_index < expected_packet_count as u64 {
+                        while packet_index < expected_packet_count {
 
-                            let (sent_packet, payload) = Client::generate_sent_packet(&packet_index, packet_size, instant_sender_thread.elapsed());
-
-                            sent_packets.push(sent_packet);
-                            stream.write(&payload).unwrap();
-                            stream.flush().unwrap();
+                            let (sent_packet, payload) = Client::generate_sent_packet(session_id, packet_index, packet_size, &clock_sender_thread);
+                            // Nothing may delay the packet after its send timestamp was taken
+                            write_frame(&mut stream, &payload)?;
+                            if let Some(event_sink) = &event_sink {
+                                event_sink.packet_sent(&sent_packet, packet_index < max_warmup_packet_index);
+                            }
+                            sent_packets_counter_sender_thread.packet_sent(sent_packet.sent_duration);
+                            if record_sent_packets {
+                                sent_packets.push(sent_packet);
+                            }
+                            #[cfg(feature = "prometheus")]
+                            metrics_sender_thread.packet_sent();
 
                             packet_index += 1;
 
-                            while last_sent_time.elapsed().unwrap().as_micros() < message_interval as u128 {
+                            while last_sent_time.elapsed().unwrap_or_default().as_micros() < message_interval as u128 {
                             }
                             last_sent_time = SystemTime::now();
                         }
@@ -303,57 +420,123 @@ pub mod client {
                             }
                         }
 
-                        sent_packets
-                    });
+                        Ok(sent_packets)
+                    })?;
 
                     let test_duration = self.test_parameters.test_duration_total;
-                    let thread_receive = thread::spawn(move || -> Result<Vec<ReceivedPacket>, std::io::Error> {
+                    let mut recorder = PacketRecorder::new(&self.test_parameters, expected_packet_count, max_warmup_packet_index, self.event_sink.clone(), sent_packets_counter, clock_receiver_thread.start())?;
+                    #[cfg(feature = "prometheus")]
+                    {
+                        recorder.metrics = Some(self.metrics.clone());
+                    }
+                    let thread_receive = spawn_dedicated("rperf-receiver", move || -> std::io::Result<PacketRecorder> {
                         // Configure stream
-                        stream_clone.set_read_timeout(Some(time::Duration::from_secs(10)))?;
+                        stream_clone.set_read_timeout(Some(time::Duration::from_secs(3)))?;
+                        let mut rejections_log = RateLimitedLog::new("packets rejected");
 
-                        let mut received_packets: Vec<ReceivedPacket> = Vec::with_capacity((expected_packet_count + 10) as usize);
-                        'outer: while instant_receiver_thread.elapsed() < test_duration + time::Duration::from_millis(1000) {
+                        let mut buf = Vec::new();
+                        'outer: while clock_receiver_thread.elapsed() < test_duration + time::Duration::from_millis(1000) {
 
-                            let mut buf = [0u8; 16];
-                            match stream_clone.read_exact(&mut buf) {
+                            match read_frame(&mut stream_clone, &mut buf) {
                                 Ok(_) => {
-                                    let received_packet = Client::generate_received_packet(buf.to_vec(), instant_receiver_thread.elapsed());
-                                    received_packets.push(received_packet);
+                                    match Client::generate_received_packet(&buf, session_id, &clock_receiver_thread) {
+                                        Ok(received_packet) => recorder.record(received_packet),
+                                        Err(e) => rejections_log.log(format_args!("Rejected packet: {}", e))
+                                    }
                                 },
                                 Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
                                     break 'outer;
                                 }
                                 Err(e) => {
                                     println!("Failed to receive data: {}", e);
+                                    break 'outer;
                                 }
                             }
                         }
 
-                        return Ok(received_packets);
-                    });
+                        Ok(recorder)
+                    })?;
 
-                    self.sent_packets = thread_send.join().unwrap();
-                    self.received_packets = thread_receive.join().unwrap().unwrap();
+                    self.sent_packets = join_dedicated(thread_send, "sender").await?;
+                    self.take_recorded_packets(join_dedicated(thread_receive, "receiver").await?);
+                    self.stop_session(control).await;
 
                     let test_result = self.generate_result()?;
                     println!("Terminated.");
 
-                    return Ok(test_result);
+                    Ok(test_result)
                 },
-                Err(e) => {
-                    println!("Failed to connect: {}", e);
-                    return Err(e);
-                }
+                Err(e) => Err(RperfError::from_connection_error(&self.server_address, e))
             }
         }
 
+        /// Counts the packets of the sender thread per report interval
+        fn sent_packets_counter(&self) -> Arc<SentPacketsCounter> {
+            Arc::new(SentPacketsCounter::new(self.test_parameters.report_interval.unwrap_or(DEFAULT_REPORT_INTERVAL)))
+        }
+
+        fn take_recorded_packets(&mut self, recorder: PacketRecorder) {
+            if let Some(interval_recorder) = recorder.interval_recorder {
+                self.interval_reports = interval_recorder.finish(self.test_parameters.test_duration_total);
+            }
+            self.received_packets = recorder.received_packets;
+            self.histograms = recorder.histograms;
+            self.histogram_received_packets_count = recorder.histogram_received_packets_count;
+            self.histogram_duplicate_packets_count = recorder.histogram_duplicate_packets_count;
+            self.histogram_received_indices = recorder.received_indices;
+        }
+
         fn generate_result(&mut self) -> std::io::Result<TestResult> {
-            let test_result = TestResult::from_tx_rx_times(self.test_parameters.clone(), &self.sent_packets, &self.received_packets, self.test_parameters.output_rtt);
+            let mut test_result = match self.histograms.take() {
+                Some(histograms) => {
+                    let sent_packets_count = self.expected_packet_count_total - self.expected_packet_count_warmup;
+                    let mut test_result = TestResult::from_histograms(self.test_parameters.clone(), histograms, sent_packets_count, self.histogram_received_packets_count);
+                    test_result.duplicate_packets_count = self.histogram_duplicate_packets_count;
+                    test_result
+                }
+                None => TestResult::from_tx_rx_times(self.test_parameters.clone(), &self.sent_packets, &self.received_packets, self.test_parameters.output_rtt)
+            };
 
-            println!("Sent Duration: {:.3} ms", test_result.sent_duration_millis);
+            test_result.interval_reports = std::mem::take(&mut self.interval_reports);
+            test_result.server_statistics = self.server_statistics.take();
+
+            if let Some(event_sink) = self.event_sink.take() {
+                match self.test_parameters.recording_mode {
+                    RecordingMode::PerPacket => {
+                        for lost_packet_index in &test_result.lost_packet_indices {
+                            event_sink.packet_lost(*lost_packet_index);
+                        }
+                    }
+                    RecordingMode::Histogram { .. } => {
+                        for index in self.expected_packet_count_warmup..self.expected_packet_count_total {
+                            if !self.histogram_received_indices.contains(index) {
+                                event_sink.packet_lost(index);
+                            }
+                        }
+                    }
+                }
+                event_sink.finish()?;
+                println!("Packet events streamed to '{}'", &self.ndjson_path);
+                if event_sink.dropped_events_count() > 0 {
+                    eprintln!("{} packet events were dropped because they could not be written fast enough", event_sink.dropped_events_count());
+                }
+            }
+
+            println!("Sent Duration: {:.3} s", test_result.sent_duration_seconds);
             println!("Sent Packets: {}", test_result.sent_packets_count);
             println!("Received Packets: {}", test_result.received_packets_count);
             println!("Lost Packets: {}", test_result.lost_packets_count);
+            println!("Duplicate Packets: {}", test_result.duplicate_packets_count);
+            if let Some(reordering) = &test_result.reordering {
+                println!("Reordering: {}", reordering);
+            }
+            if let Some(loss) = test_result.loss_statistics() {
+                println!("Loss Pattern: {}", loss);
+                if !loss.burst_lengths.is_empty() {
+                    println!("Loss Burst Lengths: {}", format_distribution(&loss.burst_lengths));
+                    println!("Consecutive Losses: {}", format_distribution(&loss.consecutive_losses));
+                }
+            }
             if self.test_parameters.output_rtt
             {
                 println!("Average Round Trip Time: {} ms", test_result.average_latency());
@@ -361,44 +544,93 @@ pub mod client {
                 println!("Average Latency: {} ms", test_result.average_latency());
             }
 
+            println!("Average Server Processing Time: {} ms", test_result.average_server_processing_time());
+            println!("Average Round Trip Time without Server Processing: {} ms", test_result.average_network_round_trip_time());
+
             if self.test_parameters.measure_owl
             {
                 println!("Average Latency Client -> Server: {} ms", test_result.average_latency_client_to_server());
                 println!("Average Latency Server -> Client: {} ms", test_result.average_latency_server_to_client());
             }
 
-            if !&self.log_path.is_empty() {
-                let mut file = File::create(&self.log_path)?;
-                file.write_all(format!("Test Parameters: --ip {} -p {} --time {} --mps {}, --size {}\n\
-                                    Test Results: Sent Duration [s]: {:.3} | Sent Packets: {}, Received Packets: {}, Lost Packets: {}, Average Latency [ms]: {}\n",
-                                       test_result.test_parameters.server_ip,
-                                       test_result.test_parameters.server_port,
-                                       test_result.test_parameters.test_duration_valid.as_secs(),
-                                       test_result.test_parameters.packets_per_second,
-                                       test_result.test_parameters.packet_size,
-                                       test_result.sent_duration_millis,
-                                       test_result.sent_packets_count,
-                                       test_result.received_packets_count,
-                                       test_result.lost_packets_count,
-                                       test_result.average_latency()).as_bytes())?;
-
-                let mut csv_writer = Writer::from_writer(file);
-
+            if let Some(statistics) = test_result.latency_statistics() {
                 if self.test_parameters.output_rtt {
-                    csv_writer.write_record(&["Packet", "TxTime[s]", "RxTime[s]", "RoundTripTime[ms]", "OneWayLatency_ClientToServer[ms]", "OneWayLatency_ServerToClient[ms]"])?;
+                    println!("Round Trip Time [ms]: {}", statistics);
+                } else {
+                    println!("Latency [ms]: {}", statistics);
+                }
+            }
+            if let Some(statistics) = test_result.latency_client_to_server_statistics() {
+                println!("Latency Client -> Server [ms]: {}", statistics);
+            }
+            if let Some(statistics) = test_result.latency_server_to_client_statistics() {
+                println!("Latency Server -> Client [ms]: {}", statistics);
+            }
+            if let Some(statistics) = test_result.server_processing_time_statistics() {
+                println!("Server Processing Time [ms]: {}", statistics);
+            }
+            if let Some(max_gap) = test_result.max_gap_millis() {
+                println!("Maximum Gap between Received Packets: {:.6} ms", max_gap);
+            }
+            for deadline in test_result.deadline_statistics() {
+                println!("{}", deadline);
+            }
+            if test_result.deadline_miss_ratio_exceeded() {
+                println!("Maximum deadline miss ratio exceeded");
+            }
+            if let Some(jitter) = test_result.round_trip_time_jitter() {
+                println!("Round Trip Time Jitter [ms]: {}", jitter);
+            }
+            if let Some(jitter) = test_result.latency_client_to_server_jitter() {
+                println!("Latency Client -> Server Jitter [ms]: {}", jitter);
+            }
+            if let Some(jitter) = test_result.latency_server_to_client_jitter() {
+                println!("Latency Server -> Client Jitter [ms]: {}", jitter);
+            }
+            if let Some(server_statistics) = &test_result.server_statistics {
+                println!("Server Received Packets: {}", server_statistics.received_packets_count);
+                println!("Server Sent Packets: {}", server_statistics.sent_packets_count);
+                println!("Server Rejected Packets: {}", server_statistics.rejected_packets_count);
+            }
+
+            if !&self.log_path.is_empty() {
+                // The summary is written as comments, so the file can be read as CSV
+                let mut comments = vec![
+                    format!("Test Parameters: --ip {} -p {} --time {} --mps {}, --size {}",
+                            test_result.test_parameters.server_ip,
+                            test_result.test_parameters.server_port,
+                            test_result.test_parameters.test_duration_valid.as_secs(),
+                            test_result.test_parameters.packets_per_second,
+                            test_result.test_parameters.packet_size),
+                    format!("Test Results: Sent Duration [s]: {:.3} | Sent Packets: {}, Received Packets: {}, Lost Packets: {}, Average Latency [ms]: {}",
+                            test_result.sent_duration_seconds,
+                            test_result.sent_packets_count,
+                            test_result.received_packets_count,
+                            test_result.lost_packets_count,
+                            test_result.average_latency()),
+                    format!("Duplicate Packets: {}", test_result.duplicate_packets_count)
+                ];
+                if let Some(reordering) = &test_result.reordering {
+                    comments.push(format!("Reordering: {}", reordering));
                 }
-                else {
-                    csv_writer.write_record(&["Packet", "TxTime[s]", "RxTime[s]", "Latency[ms]", "OneWayLatency_ClientToServer[ms]", "OneWayLatency_ServerToClient[ms]"])?;
+                if let Some(loss) = test_result.loss_statistics() {
+                    comments.push(format!("Loss Pattern: {}", loss));
+                    comments.push(format!("Loss Burst Lengths: {}", format_distribution(&loss.burst_lengths)));
+                    comments.push(format!("Consecutive Losses: {}", format_distribution(&loss.consecutive_losses)));
                 }
-                for packet_result in test_result.packet_results.clone() {
-                    csv_writer.write_record(&[packet_result.index.to_string(),
-                        format!("{:.20}", packet_result.tx_time),
-                        format!("{:.20}", packet_result.rx_time),
-                        format!("{:.9}", packet_result.latency),
-                        format!("{:.9}", packet_result.latency_client_to_server),
-                        format!("{:.9}", packet_result.latency_server_to_client)])?;
+                for deadline in test_result.deadline_statistics() {
+                    comments.push(deadline.to_string());
+                }
+                let jitters = [("Round Trip Time", test_result.round_trip_time_jitter()),
+                    ("Latency Client -> Server", test_result.latency_client_to_server_jitter()),
+                    ("Latency Server -> Client", test_result.latency_server_to_client_jitter())];
+                for (name, jitter) in &jitters {
+                    if let Some(jitter) = jitter {
+                        comments.push(format!("{} Jitter [ms]: {}", name, jitter));
+                    }
                 }
-                csv_writer.flush()?;
+
+                packet_csv::write_with_comments(&test_result, &comments, File::create(&self.log_path)?)?;
 
                 println!("Test results logged in '{}'", &self.log_path);
             }
@@ -408,4 +640,106 @@ pub mod client {
             Ok(test_result)
         }
     }
+
+    /// Runs the latency critical `task` on a dedicated thread, its result is awaited with `join_dedicated`
+    fn spawn_dedicated<T, F>(name: &str, task: F) -> std::io::Result<oneshot::Receiver<std::io::Result<T>>>
+        where T: Send + 'static, F: FnOnce() -> std::io::Result<T> + Send + 'static {
+        let (sender, receiver) = oneshot::channel();
+        thread::Builder::new().name(name.to_string()).spawn(move || {
+            let _ = sender.send(task());
+        })?;
+
+        Ok(receiver)
+    }
+
+    /// Waits for a dedicated thread without blocking the runtime, a panic of the thread is returned as error
+    async fn join_dedicated<T>(receiver: oneshot::Receiver<std::io::Result<T>>, name: &str) -> Result<T> {
+        match receiver.await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(RperfError::Io(io::Error::other(format!("{} thread panicked", name))))
+        }
+    }
+
+    /// Formats a distribution as "key: count" pairs, e.g. "1: 12 | 2: 3"
+    fn format_distribution(distribution: &BTreeMap<u64, u64>) -> String {
+        distribution.iter().map(|(key, count)| format!("{}: {}", key, count)).collect::<Vec<_>>().join(" | ")
+    }
+
+    /// Collects the pongs of the receiver thread, either per packet or only in histograms
+    struct PacketRecorder {
+        received_packets: Vec<ReceivedPacket>,
+        histograms: Option<LatencyHistograms>,
+        histogram_received_packets_count: u64,
+        histogram_duplicate_packets_count: u64,
+        received_indices: ReceivedIndices,
+        max_warmup_packet_index: u64,
+        output_rtt: bool,
+        measure_owl: bool,
+        event_sink: Option<EventSink>,
+        interval_recorder: Option<IntervalRecorder>,
+        #[cfg(feature = "prometheus")]
+        metrics: Option<crate::metrics::ClientMetrics>
+    }
+
+    impl PacketRecorder {
+        fn new(test_parameters: &TestParameters, expected_packet_count: u64, max_warmup_packet_index: u64, event_sink: Option<EventSink>,
+               sent_packets_counter: Arc<SentPacketsCounter>, start: Instant) -> std::io::Result<PacketRecorder> {
+            // Duplicates are found in the packet results, only histograms need the received indices
+            let (received_packets, histograms, received_indices) = match test_parameters.recording_mode {
+                RecordingMode::PerPacket => (Vec::with_capacity((expected_packet_count + 10) as usize), None, ReceivedIndices::new(0)),
+                RecordingMode::Histogram { significant_digits } => (Vec::new(), Some(LatencyHistograms::new(significant_digits)?), ReceivedIndices::new(expected_packet_count))
+            };
+            // Intervals are also recorded for the summaries in the packet event stream
+            let interval_recorder = if test_parameters.report_interval.is_some() || event_sink.is_some() {
+                let mut interval_recorder = IntervalRecorder::new(test_parameters.report_interval.is_some(), test_parameters.output_rtt, expected_packet_count,
+                                                                  sent_packets_counter, event_sink.clone());
+                interval_recorder.start_timer(start)?;
+                Some(interval_recorder)
+            } else {
+                None
+            };
+
+            Ok(PacketRecorder {
+                received_packets,
+                histograms,
+                histogram_received_packets_count: 0,
+                histogram_duplicate_packets_count: 0,
+                received_indices,
+                max_warmup_packet_index,
+                output_rtt: test_parameters.output_rtt,
+                measure_owl: test_parameters.measure_owl,
+                interval_recorder,
+                event_sink,
+                #[cfg(feature = "prometheus")]
+                metrics: None
+            })
+        }
+
+        fn record(&mut self, received_packet: ReceivedPacket) {
+            if let Some(event_sink) = &self.event_sink {
+                event_sink.packet_received(&received_packet, received_packet.index < self.max_warmup_packet_index, self.measure_owl);
+            }
+            if let Some(interval_recorder) = &mut self.interval_recorder {
+                interval_recorder.record(&received_packet);
+            }
+            #[cfg(feature = "prometheus")]
+            if let Some(metrics) = &self.metrics {
+                metrics.packet_received(&received_packet, self.measure_owl);
+            }
+            match &mut self.histograms {
+                Some(histograms) => {
+                    let first_arrival = self.received_indices.insert(received_packet.index);
+                    if received_packet.index >= self.max_warmup_packet_index {
+                        if first_arrival {
+                            histograms.record(&received_packet, self.output_rtt, self.measure_owl);
+                            self.histogram_received_packets_count += 1;
+                        } else {
+                            self.histogram_duplicate_packets_count += 1;
+                        }
+                    }
+                }
+                None => self.received_packets.push(received_packet)
+            }
+        }
+    }
 }
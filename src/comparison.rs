@@ -0,0 +1,256 @@
+/*<copyright file="comparison.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+// Comparison of saved results, e.g. before and after a firmware update. The first result is the baseline, every
+// other result is compared against it. Latencies are in milliseconds.
+
+use std::fmt;
+use std::io::{Error, ErrorKind};
+
+use crate::json_result::JsonResult;
+use crate::statistics::{LatencyStatistics, MannWhitneyU};
+
+pub const DEFAULT_TOLERANCE_PERCENT: f64 = 10.0;
+pub const DEFAULT_SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+type Statistic = fn(&LatencyStatistics) -> f64;
+
+const STATISTICS: [(&str, Statistic); 7] = [("Min", |latency| latency.min), ("Mean", |latency| latency.mean),
+    ("Median", |latency| latency.median), ("P90", |latency| latency.p90), ("P99", |latency| latency.p99),
+    ("P99.9", |latency| latency.p99_9), ("Max", |latency| latency.max)];
+/// Statistics a regression is detected on
+const REGRESSION_STATISTICS: [&str; 2] = ["Median", "P99"];
+
+pub struct ComparedResult {
+    pub label: String,
+    pub sent_packets_count: u64,
+    pub lost_packets_count: u64,
+    pub latency: Option<LatencyStatistics>,
+    /// RFC 3550 interarrival jitter of the round trip time
+    pub jitter: Option<f64>
+}
+
+pub struct LatencyDelta {
+    pub name: &'static str,
+    pub baseline: f64,
+    pub candidate: f64
+}
+
+impl ComparedResult {
+    fn from_json(label: String, result: &JsonResult) -> ComparedResult {
+        ComparedResult {
+            label,
+            sent_packets_count: result.summary.sent_packets_count,
+            lost_packets_count: result.summary.lost_packets_count,
+            latency: result.summary.latency,
+            jitter: result.summary.round_trip_time_jitter.map(|jitter| jitter.interarrival_jitter)
+        }
+    }
+
+    /// Lost packets relative to the sent packets in percent
+    pub fn loss_percent(&self) -> f64 {
+        if self.sent_packets_count == 0 {
+            return 0_f64;
+        }
+
+        self.lost_packets_count as f64 / self.sent_packets_count as f64 * 100_f64
+    }
+}
+
+impl LatencyDelta {
+    /// Change relative to the baseline in percent
+    pub fn relative_change_percent(&self) -> f64 {
+        relative_change_percent(self.baseline, self.candidate)
+    }
+}
+
+/// Loss ratios in percent
+pub struct LossDelta {
+    pub baseline: f64,
+    pub candidate: f64
+}
+
+impl LossDelta {
+    /// Change relative to the baseline in percent, infinite if the baseline lost no packets but the candidate did
+    pub fn relative_change_percent(&self) -> f64 {
+        relative_change_percent(self.baseline, self.candidate)
+    }
+}
+
+fn relative_change_percent(baseline: f64, candidate: f64) -> f64 {
+    if baseline == 0_f64 {
+        return if candidate == 0_f64 { 0_f64 } else { f64::INFINITY };
+    }
+
+    (candidate - baseline) / baseline * 100_f64
+}
+
+pub struct CandidateComparison {
+    pub label: String,
+    /// Empty if baseline or candidate have no latency statistics
+    pub deltas: Vec<LatencyDelta>,
+    /// False if the candidate has no latency statistics, e.g. because all packets were lost
+    pub has_latency: bool,
+    pub loss: LossDelta,
+    /// Only available if both results contain per packet results
+    pub mann_whitney: Option<MannWhitneyU>
+}
+
+pub struct Comparison {
+    pub latency_name: &'static str,
+    /// Baseline first
+    pub results: Vec<ComparedResult>,
+    pub candidates: Vec<CandidateComparison>
+}
+
+impl Comparison {
+    /// Compares labeled results, the first one is the baseline
+    pub fn new(results: &[(String, JsonResult)]) -> std::io::Result<Comparison> {
+        let (baseline_label, baseline) = match results {
+            [baseline, _, ..] => baseline,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, "At least two results are needed for a comparison"))
+        };
+        if let Some((label, _)) = results.iter().find(|(_, result)| result.test_parameters.output_rtt != baseline.test_parameters.output_rtt) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("'{}' and '{}' cannot be compared, only one of them contains round trip times", baseline_label, label)));
+        }
+
+        let compared_results: Vec<_> = results.iter().map(|(label, result)| ComparedResult::from_json(label.clone(), result)).collect();
+        let latencies_ns = |result: &JsonResult| result.packet_results.as_ref()
+            .map(|packet_results| packet_results.iter().map(|packet_result| packet_result.latency_ns).collect::<Vec<_>>());
+        let baseline_latencies_ns = latencies_ns(baseline);
+
+        let candidates = results[1..].iter().zip(&compared_results[1..]).map(|((label, result), compared_result)| {
+            let deltas = match (&baseline.summary.latency, &result.summary.latency) {
+                (Some(baseline), Some(candidate)) => STATISTICS.iter()
+                    .map(|(name, statistic)| LatencyDelta { name, baseline: statistic(baseline), candidate: statistic(candidate) }).collect(),
+                _ => Vec::new()
+            };
+            let mann_whitney = match (&baseline_latencies_ns, latencies_ns(result)) {
+                (Some(baseline_latencies_ns), Some(latencies_ns)) => MannWhitneyU::from_samples(baseline_latencies_ns, &latencies_ns),
+                _ => None
+            };
+
+            CandidateComparison {
+                label: label.clone(),
+                deltas,
+                has_latency: result.summary.latency.is_some(),
+                loss: LossDelta { baseline: compared_results[0].loss_percent(), candidate: compared_result.loss_percent() },
+                mann_whitney
+            }
+        }).collect();
+
+        Ok(Comparison {
+            latency_name: if baseline.test_parameters.output_rtt { "Round Trip Time" } else { "Latency" },
+            results: compared_results,
+            candidates
+        })
+    }
+
+    /// Compares results saved with `--json`, labeled with their paths
+    pub fn from_files(paths: &[&str]) -> std::io::Result<Comparison> {
+        let results = paths.iter().map(|path| Ok((path.to_string(), JsonResult::read_file(path)?))).collect::<std::io::Result<Vec<_>>>()?;
+
+        Comparison::new(&results)
+    }
+
+    /// Descriptions of the regressions of the candidates: the loss ratio is more than `tolerance_percent` above the
+    /// baseline, there are no latency statistics, or the median or 99th percentile is more than `tolerance_percent`
+    /// above the baseline and, if per packet results are available, the latencies are significantly larger
+    /// according to the Mann-Whitney U test
+    pub fn regressions(&self, tolerance_percent: f64, significance_level: f64) -> Vec<String> {
+        let mut regressions = Vec::new();
+        for candidate in &self.candidates {
+            if candidate.loss.relative_change_percent() > tolerance_percent {
+                regressions.push(format!("{}: Loss ratio {:.3} % is {:+.2} % above baseline {:.3} % (tolerance {} %)",
+                                         candidate.label, candidate.loss.candidate, candidate.loss.relative_change_percent(), candidate.loss.baseline, tolerance_percent));
+            }
+            if !candidate.has_latency {
+                regressions.push(format!("{}: no {} statistics, no packet was received", candidate.label, self.latency_name));
+                continue;
+            }
+
+            if let Some(mann_whitney) = &candidate.mann_whitney {
+                if mann_whitney.p_value >= significance_level || mann_whitney.probability_greater <= 0.5 {
+                    continue;
+                }
+            }
+
+            for delta in candidate.deltas.iter().filter(|delta| REGRESSION_STATISTICS.contains(&delta.name)) {
+                if delta.relative_change_percent() > tolerance_percent {
+                    regressions.push(format!("{}: {} {} {:.6} ms is {:+.2} % above baseline {:.6} ms (tolerance {} %)",
+                                             candidate.label, self.latency_name, delta.name, delta.candidate, delta.relative_change_percent(), delta.baseline, tolerance_percent));
+                }
+            }
+        }
+
+        regressions
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.results.iter().map(|result| result.label.len()).max().unwrap_or(0).max(28);
+
+        write!(f, "{:<32}", "")?;
+        for result in &self.results {
+            write!(f, " {:>width$}", result.label, width = width)?;
+        }
+        writeln!(f)?;
+
+        let row = |f: &mut fmt::Formatter<'_>, name: &str, values: Vec<String>| -> fmt::Result {
+            write!(f, "{:<32}", name)?;
+            for value in values {
+                write!(f, " {:>width$}", value, width = width)?;
+            }
+            writeln!(f)
+        };
+        row(f, "Sent Packets", self.results.iter().map(|result| result.sent_packets_count.to_string()).collect())?;
+        row(f, "Lost Packets", self.results.iter().map(|result| result.lost_packets_count.to_string()).collect())?;
+        row(f, "Loss Ratio [%]", self.results.iter().enumerate().map(|(position, result)| match position.checked_sub(1) {
+            Some(candidate) => format!("{:.3} ({:+.2} %)", result.loss_percent(), self.candidates[candidate].loss.relative_change_percent()),
+            None => format!("{:.3}", result.loss_percent())
+        }).collect())?;
+
+        for (name, statistic) in STATISTICS.iter() {
+            row(f, &format!("{} {} [ms]", self.latency_name, name), self.results.iter().enumerate().map(|(position, result)| {
+                let value = match &result.latency {
+                    Some(latency) => statistic(latency),
+                    None => return "-".to_string()
+                };
+                // Candidates show their change relative to the baseline
+                let delta = position.checked_sub(1)
+                    .and_then(|candidate| self.candidates[candidate].deltas.iter().find(|delta| delta.name == *name));
+                match delta {
+                    Some(delta) => format!("{:.6} ({:+.2} %)", value, delta.relative_change_percent()),
+                    None => format!("{:.6}", value)
+                }
+            }).collect())?;
+        }
+        row(f, "Round Trip Time Jitter [ms]", self.results.iter().map(|result| result.jitter.map_or_else(|| "-".to_string(), |jitter| format!("{:.6}", jitter))).collect())?;
+
+        let baseline_label = &self.results[0].label;
+        for candidate in &self.candidates {
+            match &candidate.mann_whitney {
+                Some(mann_whitney) => writeln!(f, "{} vs {}: Mann-Whitney U {:.1} | z {:.3} | p {:.6} | P(latency of {} > {}) {:.3}",
+                                               candidate.label, baseline_label, mann_whitney.u, mann_whitney.z, mann_whitney.p_value,
+                                               candidate.label, baseline_label, mann_whitney.probability_greater)?,
+                None => writeln!(f, "{} vs {}: no significance test, both results need per packet results (--json-packets)", candidate.label, baseline_label)?
+            }
+        }
+
+        Ok(())
+    }
+}
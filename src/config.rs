@@ -0,0 +1,307 @@
+/*<copyright file="config.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+// Configuration of clients and servers. Configs are created with their builders or deserialized (missing fields
+// take their defaults) and are validated before a client or server is started from them. New options get a
+// default, so adding them does not break existing callers or config files.
+
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{RperfError, Result};
+use crate::packet::HEADER_LENGTH;
+use crate::test_parameters::RecordingMode;
+
+pub const SUPPORTED_PROTOCOLS: [&str; 2] = ["udp", "tcp"];
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+pub const MIN_REPORT_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct ClientConfig {
+    pub server_ip: String,
+    pub server_port: u16,
+    /// "udp" or "tcp"
+    pub protocol: String,
+    /// Duration of the test after the warm-up
+    pub test_duration: Duration,
+    pub packets_per_second: u32,
+    /// Size of the pings in bytes, at least `HEADER_LENGTH`
+    pub packet_size: usize,
+    pub warmup_duration: Duration,
+    /// Path of the CSV log of the results
+    pub log_path: Option<String>,
+    /// Path of the packet events streamed as newline delimited JSON
+    pub ndjson_path: Option<String>,
+    /// Report round trip times instead of latencies (half the round trip time)
+    pub output_rtt: bool,
+    /// Measure one way latencies, requires synchronized clocks of client and server
+    pub measure_owl: bool,
+    pub recording_mode: RecordingMode,
+    /// Latency budgets every packet is evaluated against
+    pub deadlines: Vec<Duration>,
+    /// Share of packets allowed to miss a deadline before the test is considered failed
+    pub max_deadline_miss_ratio: Option<f64>,
+    /// Interval of the reports printed while the test is running
+    pub report_interval: Option<Duration>
+}
+
+impl Default for ClientConfig {
+    fn default() -> ClientConfig {
+        ClientConfig {
+            server_ip: "127.0.0.1".to_string(),
+            server_port: 5555,
+            protocol: "udp".to_string(),
+            test_duration: Duration::from_secs(10),
+            packets_per_second: 1000,
+            packet_size: HEADER_LENGTH,
+            warmup_duration: Duration::from_secs(0),
+            log_path: None,
+            ndjson_path: None,
+            output_rtt: false,
+            measure_owl: false,
+            recording_mode: RecordingMode::PerPacket,
+            deadlines: Vec::new(),
+            max_deadline_miss_ratio: None,
+            report_interval: None
+        }
+    }
+}
+
+impl ClientConfig {
+    pub fn builder(server_ip: &str, server_port: u16) -> ClientConfigBuilder {
+        ClientConfigBuilder {
+            config: ClientConfig {
+                server_ip: server_ip.to_string(),
+                server_port,
+                ..ClientConfig::default()
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_protocol(&self.protocol)?;
+        if self.packets_per_second == 0 {
+            return Err(RperfError::InvalidConfig("At least one packet per second must be sent".to_string()));
+        }
+        if self.packet_size < HEADER_LENGTH {
+            return Err(RperfError::InvalidConfig(format!("Packet size must be at least {} bytes", HEADER_LENGTH)));
+        }
+        if let RecordingMode::Histogram { significant_digits } = self.recording_mode {
+            if significant_digits > 5 {
+                return Err(RperfError::InvalidConfig(format!("Histograms support 0 to 5 significant digits, not {}", significant_digits)));
+            }
+        }
+        if let Some(max_deadline_miss_ratio) = self.max_deadline_miss_ratio {
+            if self.deadlines.is_empty() {
+                return Err(RperfError::InvalidConfig("A deadline miss ratio requires at least one deadline".to_string()));
+            }
+            if !(0_f64..=1_f64).contains(&max_deadline_miss_ratio) {
+                return Err(RperfError::InvalidConfig(format!("Deadline miss ratio {} is not between 0 and 1", max_deadline_miss_ratio)));
+            }
+        }
+        if let Some(report_interval) = self.report_interval {
+            if report_interval < MIN_REPORT_INTERVAL {
+                return Err(RperfError::InvalidConfig(format!("Report interval must be at least {:?}", MIN_REPORT_INTERVAL)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub struct ClientConfigBuilder {
+    config: ClientConfig
+}
+
+impl ClientConfigBuilder {
+    pub fn protocol(mut self, protocol: &str) -> ClientConfigBuilder {
+        self.config.protocol = protocol.to_lowercase();
+        self
+    }
+
+    pub fn test_duration(mut self, test_duration: Duration) -> ClientConfigBuilder {
+        self.config.test_duration = test_duration;
+        self
+    }
+
+    pub fn packets_per_second(mut self, packets_per_second: u32) -> ClientConfigBuilder {
+        self.config.packets_per_second = packets_per_second;
+        self
+    }
+
+    pub fn packet_size(mut self, packet_size: usize) -> ClientConfigBuilder {
+        self.config.packet_size = packet_size;
+        self
+    }
+
+    pub fn warmup_duration(mut self, warmup_duration: Duration) -> ClientConfigBuilder {
+        self.config.warmup_duration = warmup_duration;
+        self
+    }
+
+    pub fn log_path(mut self, log_path: &str) -> ClientConfigBuilder {
+        self.config.log_path = Some(log_path.to_string());
+        self
+    }
+
+    pub fn ndjson_path(mut self, ndjson_path: &str) -> ClientConfigBuilder {
+        self.config.ndjson_path = Some(ndjson_path.to_string());
+        self
+    }
+
+    pub fn output_rtt(mut self, output_rtt: bool) -> ClientConfigBuilder {
+        self.config.output_rtt = output_rtt;
+        self
+    }
+
+    pub fn measure_owl(mut self, measure_owl: bool) -> ClientConfigBuilder {
+        self.config.measure_owl = measure_owl;
+        self
+    }
+
+    pub fn recording_mode(mut self, recording_mode: RecordingMode) -> ClientConfigBuilder {
+        self.config.recording_mode = recording_mode;
+        self
+    }
+
+    pub fn deadline(mut self, deadline: Duration) -> ClientConfigBuilder {
+        self.config.deadlines.push(deadline);
+        self
+    }
+
+    pub fn max_deadline_miss_ratio(mut self, max_deadline_miss_ratio: f64) -> ClientConfigBuilder {
+        self.config.max_deadline_miss_ratio = Some(max_deadline_miss_ratio);
+        self
+    }
+
+    pub fn report_interval(mut self, report_interval: Duration) -> ClientConfigBuilder {
+        self.config.report_interval = Some(report_interval);
+        self
+    }
+
+    pub fn build(self) -> Result<ClientConfig> {
+        self.config.validate()?;
+
+        Ok(self.config)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct ServerConfig {
+    pub port: u16,
+    /// "udp" or "tcp"
+    pub protocol: String,
+    /// Pongs have the size of the pings instead of the minimal packet size
+    pub symmetric_network_load: bool,
+    /// Time active sessions get to finish on shutdown before they are closed
+    pub drain_timeout: Duration,
+    /// Sessions opened on a control connection, for UDP and TCP tests alike, are closed if no packet arrived for this
+    /// time since they were opened, started or last received a packet
+    pub session_idle_timeout: Duration,
+    /// Number of UDP worker threads sharing the port with SO_REUSEPORT, 0 starts one per CPU
+    pub udp_workers: usize,
+    /// CPUs the UDP workers are pinned to, worker i runs on `cpu_affinity[i % len]`. Workers are not pinned if empty.
+    pub cpu_affinity: Vec<usize>
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            port: 5555,
+            protocol: "udp".to_string(),
+            symmetric_network_load: false,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            session_idle_timeout: DEFAULT_SESSION_IDLE_TIMEOUT,
+            udp_workers: 1,
+            cpu_affinity: Vec::new()
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn builder(port: u16) -> ServerConfigBuilder {
+        ServerConfigBuilder {
+            config: ServerConfig {
+                port,
+                ..ServerConfig::default()
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_protocol(&self.protocol)?;
+        if self.session_idle_timeout == Duration::from_secs(0) {
+            return Err(RperfError::InvalidConfig("Session idle timeout must not be zero".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+pub struct ServerConfigBuilder {
+    config: ServerConfig
+}
+
+impl ServerConfigBuilder {
+    pub fn protocol(mut self, protocol: &str) -> ServerConfigBuilder {
+        self.config.protocol = protocol.to_lowercase();
+        self
+    }
+
+    pub fn symmetric_network_load(mut self, symmetric_network_load: bool) -> ServerConfigBuilder {
+        self.config.symmetric_network_load = symmetric_network_load;
+        self
+    }
+
+    pub fn drain_timeout(mut self, drain_timeout: Duration) -> ServerConfigBuilder {
+        self.config.drain_timeout = drain_timeout;
+        self
+    }
+
+    pub fn session_idle_timeout(mut self, session_idle_timeout: Duration) -> ServerConfigBuilder {
+        self.config.session_idle_timeout = session_idle_timeout;
+        self
+    }
+
+    pub fn udp_workers(mut self, udp_workers: usize) -> ServerConfigBuilder {
+        self.config.udp_workers = udp_workers;
+        self
+    }
+
+    pub fn cpu_affinity(mut self, cpu_affinity: Vec<usize>) -> ServerConfigBuilder {
+        self.config.cpu_affinity = cpu_affinity;
+        self
+    }
+
+    pub fn build(self) -> Result<ServerConfig> {
+        self.config.validate()?;
+
+        Ok(self.config)
+    }
+}
+
+fn validate_protocol(protocol: &str) -> Result<()> {
+    if !SUPPORTED_PROTOCOLS.contains(&protocol.to_lowercase().as_str()) {
+        return Err(RperfError::InvalidConfig(format!("Unsupported protocol '{}'", protocol)));
+    }
+
+    Ok(())
+}
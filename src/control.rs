@@ -0,0 +1,103 @@
+/*<copyright file="control.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+// Before a test the client opens a control connection to the TCP port of the server. It negotiates the parameters of
+// the session, starts and stops the test and receives the statistics of the server, independent of the protocol the
+// pings are measured with. Messages are JSON documents sent as frames.
+
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::framing::{read_frame_async, write_frame_async};
+
+/// Version of the messages on the control connection, both sides must use the same version
+pub const CONTROL_PROTOCOL_VERSION: u32 = 1;
+/// Server returns its statistics in reply to `Stop`
+pub const CAPABILITY_SERVER_STATISTICS: &str = "server_statistics";
+/// Capabilities of this implementation, a session uses those supported by client and server
+pub const CAPABILITIES: [&str; 1] = [CAPABILITY_SERVER_STATISTICS];
+/// Time the other side gets for its reply during the handshake
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ControlMessage {
+    /// First message of the client with the parameters of the session
+    Hello {
+        version: u32,
+        session_id: u32,
+        protocol: String,
+        packet_size: usize,
+        capabilities: Vec<String>
+    },
+    /// Server accepted the session, `capabilities` are those both sides support
+    Accept {
+        version: u32,
+        capabilities: Vec<String>,
+        symmetric_network_load: bool
+    },
+    Reject {
+        version: u32,
+        reason: String
+    },
+    /// Client starts sending pings
+    Start,
+    /// Client sent its last ping and ends the session
+    Stop,
+    /// Reply of the server to `Stop`
+    Statistics(ServerStatistics),
+    /// First message on a TCP connection that carries the pings of a session instead of control messages
+    Measurement {
+        session_id: u32
+    }
+}
+
+/// Counters of a session on the server
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerStatistics {
+    pub received_packets_count: u64,
+    pub sent_packets_count: u64,
+    /// Packets of the client the server could not decode
+    pub rejected_packets_count: u64,
+    /// Time between `Start` and `Stop` on the server
+    pub duration_seconds: f64
+}
+
+pub async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &ControlMessage) -> std::io::Result<()> {
+    write_frame_async(writer, &serde_json::to_vec(message).map_err(Error::other)?).await
+}
+
+pub async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<ControlMessage> {
+    let mut buf = Vec::new();
+    read_frame_async(reader, &mut buf).await?;
+
+    serde_json::from_slice(&buf).map_err(|e| Error::new(ErrorKind::InvalidData, format!("Invalid control message: {}", e)))
+}
+
+impl ServerStatistics {
+    /// Sums the counters of two parts of a test, e.g. of results that are merged. The parts run at the same time, so
+    /// the duration is the longer one.
+    pub fn combine(&self, other: &ServerStatistics) -> ServerStatistics {
+        ServerStatistics {
+            received_packets_count: self.received_packets_count + other.received_packets_count,
+            sent_packets_count: self.sent_packets_count + other.sent_packets_count,
+            rejected_packets_count: self.rejected_packets_count + other.rejected_packets_count,
+            duration_seconds: self.duration_seconds.max(other.duration_seconds)
+        }
+    }
+}
@@ -0,0 +1,76 @@
+/*<copyright file="error.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+// Errors returned by clients and servers. The library never exits the process, the binary decides how to
+// react to them.
+
+use std::fmt;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RperfError {
+    /// The server actively refused the connection or the probe
+    ConnectionRefused { server: String },
+    /// The server did not answer in time
+    Timeout { server: String },
+    /// The server answered, but the test could not be set up
+    Handshake { server: String, reason: String },
+    /// The peer does not speak a compatible version of the rperf protocol
+    ProtocolMismatch { server: String, reason: String },
+    InvalidConfig(String),
+    Io(std::io::Error)
+}
+
+impl RperfError {
+    /// Classifies an error on the connection to `server`
+    pub fn from_connection_error(server: &str, error: std::io::Error) -> RperfError {
+        match error.kind() {
+            std::io::ErrorKind::ConnectionRefused => RperfError::ConnectionRefused { server: server.to_string() },
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => RperfError::Timeout { server: server.to_string() },
+            _ => RperfError::Io(error)
+        }
+    }
+}
+
+impl fmt::Display for RperfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RperfError::ConnectionRefused { server } => write!(f, "Server '{}' refused connection", server),
+            RperfError::Timeout { server } => write!(f, "Server '{}' not reachable", server),
+            RperfError::Handshake { server, reason } => write!(f, "Handshake with server '{}' failed: {}", server, reason),
+            RperfError::ProtocolMismatch { server, reason } => write!(f, "Server '{}' is not a compatible rperf server: {}", server, reason),
+            RperfError::InvalidConfig(reason) => write!(f, "Invalid configuration: {}", reason),
+            RperfError::Io(error) => write!(f, "I/O error: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for RperfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RperfError::Io(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for RperfError {
+    fn from(error: std::io::Error) -> RperfError {
+        RperfError::Io(error)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, RperfError>;
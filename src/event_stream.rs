@@ -0,0 +1,205 @@
+/*<copyright file="event_stream.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+
+// Live output of a running test as newline delimited JSON (NDJSON). Every line is one event, flushed as soon as
+// it is written so that the output can be followed with e.g. `tail -f` or piped into other tools. Events are
+// serialized and written on a separate thread, so the sender and receiver threads never wait for the output. If the
+// output cannot keep up, events are dropped and counted instead of queuing up without limit.
+
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use serde::{Deserialize, Serialize};
+
+use crate::interval_report::IntervalReport;
+use crate::packet::{SentPacket, ReceivedPacket};
+
+/// Events queued for the writer thread, about 10 s of a test with 1000 packets per second
+pub const EVENT_QUEUE_CAPACITY: usize = 32_768;
+
+/// Times are nanoseconds since the start of the test, timestamps nanoseconds since the unix epoch
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PacketEvent {
+    Sent {
+        index: u64,
+        time_ns: u64,
+        timestamp_ns: u64,
+        warmup: bool
+    },
+    Received {
+        index: u64,
+        time_ns: u64,
+        timestamp_ns: u64,
+        round_trip_time_ns: u64,
+        server_processing_time_ns: Option<u64>,
+        latency_client_to_server_ns: Option<u64>,
+        latency_server_to_client_ns: Option<u64>,
+        warmup: bool
+    },
+    /// Written at the end of the test for every packet without pong, except for the warm-up packets
+    Lost {
+        index: u64
+    },
+    Interval(IntervalReport)
+}
+
+/// Shared handle to the output, can be cloned into the sender and receiver threads
+#[derive(Clone)]
+pub struct EventSink {
+    messages: mpsc::SyncSender<SinkMessage>,
+    dropped_events_count: Arc<AtomicU64>
+}
+
+enum SinkMessage {
+    Event(PacketEvent),
+    /// Reports the first write error once all events sent before are written
+    Finish(mpsc::Sender<std::io::Result<()>>)
+}
+
+impl EventSink {
+    pub fn new<W: Write + Send + 'static>(writer: W) -> EventSink {
+        EventSink::with_capacity(writer, EVENT_QUEUE_CAPACITY)
+    }
+
+    /// Sink queuing at most `capacity` events for the writer, further events are dropped until it caught up
+    pub fn with_capacity<W: Write + Send + 'static>(writer: W, capacity: usize) -> EventSink {
+        let (messages, received_messages) = mpsc::sync_channel(capacity);
+        thread::spawn(move || write_events(writer, received_messages));
+
+        EventSink {
+            messages,
+            dropped_events_count: Arc::new(AtomicU64::new(0))
+        }
+    }
+
+    pub fn create(path: &str) -> std::io::Result<EventSink> {
+        Ok(EventSink::new(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn packet_sent(&self, sent_packet: &SentPacket, warmup: bool) {
+        self.write(PacketEvent::Sent {
+            index: sent_packet.index,
+            time_ns: sent_packet.sent_duration.as_nanos() as u64,
+            timestamp_ns: sent_packet.sent_timestamp_ns,
+            warmup
+        });
+    }
+
+    pub fn packet_received(&self, received_packet: &ReceivedPacket, warmup: bool, measure_owl: bool) {
+        let round_trip_time_ns = received_packet.received_timestamp_ns.saturating_sub(received_packet.sent_timestamp_ns);
+        let (latency_client_to_server_ns, latency_server_to_client_ns) = if measure_owl {
+            (received_packet.server_rx_timestamp_ns.checked_sub(received_packet.sent_timestamp_ns),
+             received_packet.received_timestamp_ns.checked_sub(received_packet.server_tx_timestamp_ns))
+        } else {
+            (None, None)
+        };
+
+        self.write(PacketEvent::Received {
+            index: received_packet.index,
+            time_ns: received_packet.received_duration.as_nanos() as u64,
+            timestamp_ns: received_packet.received_timestamp_ns,
+            round_trip_time_ns,
+            server_processing_time_ns: received_packet.server_tx_timestamp_ns.checked_sub(received_packet.server_rx_timestamp_ns),
+            latency_client_to_server_ns,
+            latency_server_to_client_ns,
+            warmup
+        });
+    }
+
+    /// Only called at the end of the test, so it waits for the writer instead of dropping the event
+    pub fn packet_lost(&self, index: u64) {
+        let _ = self.messages.send(SinkMessage::Event(PacketEvent::Lost { index }));
+    }
+
+    pub fn interval_report(&self, interval_report: &IntervalReport) {
+        self.write(PacketEvent::Interval(interval_report.clone()));
+    }
+
+    /// Events dropped so far because the writer could not keep up
+    pub fn dropped_events_count(&self) -> u64 {
+        self.dropped_events_count.load(Ordering::Relaxed)
+    }
+
+    /// Waits until all events are written and returns the first error that occurred while writing
+    pub fn finish(&self) -> std::io::Result<()> {
+        let (result_sender, result) = mpsc::channel();
+        self.messages.send(SinkMessage::Finish(result_sender)).map_err(|_| Error::other("Event writer stopped"))?;
+
+        result.recv().unwrap_or_else(|_| Err(Error::other("Event writer stopped")))
+    }
+
+    fn write(&self, event: PacketEvent) {
+        // The writer only stops if it panicked, the error is returned by `finish`
+        if let Err(mpsc::TrySendError::Full(_)) = self.messages.try_send(SinkMessage::Event(event)) {
+            self.dropped_events_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Writes the events until all sinks are dropped, the output is flushed whenever no further events are queued
+fn write_events<W: Write>(mut writer: W, messages: mpsc::Receiver<SinkMessage>) {
+    // First write error, nothing is written afterwards
+    let mut error: Option<Error> = None;
+    let mut unflushed = false;
+
+    loop {
+        let message = match messages.try_recv() {
+            Ok(message) => message,
+            Err(mpsc::TryRecvError::Empty) => {
+                if unflushed && error.is_none() {
+                    if let Err(e) = writer.flush() {
+                        error = Some(e);
+                    }
+                }
+                unflushed = false;
+                match messages.recv() {
+                    Ok(message) => message,
+                    Err(_) => return
+                }
+            }
+            Err(mpsc::TryRecvError::Disconnected) => break
+        };
+
+        match message {
+            SinkMessage::Event(event) => {
+                if error.is_none() {
+                    let result = serde_json::to_writer(&mut writer, &event).map_err(Error::from)
+                        .and_then(|_| writer.write_all(b"\n"));
+                    if let Err(e) = result {
+                        error = Some(e);
+                    }
+                    unflushed = true;
+                }
+            }
+            SinkMessage::Finish(result) => {
+                let flushed = match error.take() {
+                    Some(e) => Err(e),
+                    None => writer.flush()
+                };
+                unflushed = false;
+                let _ = result.send(flushed);
+            }
+        }
+    }
+
+    if unflushed && error.is_none() {
+        let _ = writer.flush();
+    }
+}
@@ -0,0 +1,73 @@
+/*<copyright file="framing.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+// Messages on a TCP stream are sent as frames: a big-endian u32 length followed by the message itself.
+
+use std::io::{Read, Write, Error, ErrorKind};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt};
+
+pub const FRAME_PREFIX_LENGTH: usize = 4;
+/// Largest message accepted in a single frame
+pub const MAX_FRAME_LENGTH: usize = 1 << 20;
+
+fn encode_frame(message: &[u8]) -> std::io::Result<Vec<u8>> {
+    if message.len() > MAX_FRAME_LENGTH {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Message of {} bytes exceeds maximum frame length ({} bytes)", message.len(), MAX_FRAME_LENGTH)));
+    }
+
+    // Prefix and message are written at once so that both end up in the same segment
+    let mut frame = Vec::with_capacity(FRAME_PREFIX_LENGTH + message.len());
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+
+    Ok(frame)
+}
+
+fn decode_frame_length(prefix: [u8; FRAME_PREFIX_LENGTH]) -> std::io::Result<usize> {
+    let length = u32::from_be_bytes(prefix) as usize;
+    if length > MAX_FRAME_LENGTH {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Frame of {} bytes exceeds maximum frame length ({} bytes)", length, MAX_FRAME_LENGTH)));
+    }
+
+    Ok(length)
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, message: &[u8]) -> std::io::Result<()> {
+    writer.write_all(&encode_frame(message)?)?;
+    writer.flush()
+}
+
+/// Reads the next frame into `buf`, which is resized to the length of the message
+pub fn read_frame<R: Read>(reader: &mut R, buf: &mut Vec<u8>) -> std::io::Result<()> {
+    let mut prefix = [0u8; FRAME_PREFIX_LENGTH];
+    reader.read_exact(&mut prefix)?;
+    buf.resize(decode_frame_length(prefix)?, 0);
+    reader.read_exact(buf)
+}
+
+pub async fn write_frame_async<W: AsyncWrite + Unpin>(writer: &mut W, message: &[u8]) -> std::io::Result<()> {
+    writer.write_all(&encode_frame(message)?).await?;
+    writer.flush().await
+}
+
+pub async fn read_frame_async<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut Vec<u8>) -> std::io::Result<()> {
+    let mut prefix = [0u8; FRAME_PREFIX_LENGTH];
+    reader.read_exact(&mut prefix).await?;
+    buf.resize(decode_frame_length(prefix)?, 0);
+    reader.read_exact(buf).await?;
+
+    Ok(())
+}
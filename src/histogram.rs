@@ -0,0 +1,173 @@
+/*<copyright file="histogram.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+use std::io::{Error, ErrorKind, Write};
+use std::time::{Duration, SystemTime};
+
+use base64::Engine;
+use hdrhistogram::Histogram;
+use hdrhistogram::serialization::{Deserializer, V2DeflateSerializer};
+use hdrhistogram::serialization::interval_log::{IntervalLogIterator, IntervalLogWriterBuilder, LogEntry, Tag};
+
+use crate::packet::ReceivedPacket;
+
+pub const DEFAULT_SIGNIFICANT_DIGITS: u8 = 3;
+/// Latencies above one hour are recorded as one hour
+pub const MAX_TRACKABLE_LATENCY_NS: u64 = 3_600_000_000_000;
+
+const LATENCY_TAG: &str = "latency";
+const LATENCY_CLIENT_TO_SERVER_TAG: &str = "latency_client_to_server";
+const LATENCY_SERVER_TO_CLIENT_TAG: &str = "latency_server_to_client";
+const SERVER_PROCESSING_TIME_TAG: &str = "server_processing_time";
+
+/// Latency distributions recorded in HdrHistograms (values in nanoseconds) instead of per packet.
+/// Memory usage only depends on the range of values and the number of significant digits.
+#[derive(Clone)]
+pub struct LatencyHistograms {
+    pub latency: Histogram<u64>,
+    pub latency_client_to_server: Histogram<u64>,
+    pub latency_server_to_client: Histogram<u64>,
+    pub server_processing_time: Histogram<u64>
+}
+
+impl LatencyHistograms {
+    pub fn new(significant_digits: u8) -> std::io::Result<LatencyHistograms> {
+        let create = || Histogram::<u64>::new_with_bounds(1, MAX_TRACKABLE_LATENCY_NS, significant_digits)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("Invalid histogram precision '{}': {}", significant_digits, e)));
+
+        Ok(LatencyHistograms {
+            latency: create()?,
+            latency_client_to_server: create()?,
+            latency_server_to_client: create()?,
+            server_processing_time: create()?
+        })
+    }
+
+    /// Records the latencies of a pong. The round trip time is the difference of the client timestamps, which are
+    /// taken from the monotonic `TestClock` of the test, as the sent packets are not kept in histogram mode.
+    pub fn record(&mut self, received_packet: &ReceivedPacket, output_rtt: bool, measure_owl: bool) {
+        let round_trip_time_ns = received_packet.received_timestamp_ns.saturating_sub(received_packet.sent_timestamp_ns);
+        self.latency.saturating_record(if output_rtt { round_trip_time_ns } else { round_trip_time_ns / 2 });
+
+        if let Some(server_processing_time_ns) = received_packet.server_tx_timestamp_ns.checked_sub(received_packet.server_rx_timestamp_ns) {
+            self.server_processing_time.saturating_record(server_processing_time_ns);
+        }
+
+        if measure_owl {
+            let client_to_server = received_packet.server_rx_timestamp_ns.checked_sub(received_packet.sent_timestamp_ns);
+            let server_to_client = received_packet.received_timestamp_ns.checked_sub(received_packet.server_tx_timestamp_ns);
+            if let (Some(client_to_server), Some(server_to_client)) = (client_to_server, server_to_client) {
+                self.latency_client_to_server.saturating_record(client_to_server);
+                self.latency_server_to_client.saturating_record(server_to_client);
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistograms) -> std::io::Result<()> {
+        for (histogram, other_histogram) in self.histograms_mut().iter_mut().zip(other.histograms()) {
+            histogram.add(other_histogram).map_err(|e| Error::new(ErrorKind::InvalidData, format!("Histograms cannot be merged: {}", e)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes all histograms as tagged entries of a HdrHistogram interval log
+    pub fn write_log<W: Write>(&self, writer: &mut W, duration: Duration) -> std::io::Result<()> {
+        let mut serializer = V2DeflateSerializer::new();
+        let mut log_writer = IntervalLogWriterBuilder::new()
+            .add_comment("Latencies of rperf test in nanoseconds")
+            .with_start_time(SystemTime::now())
+            .begin_log_with(writer, &mut serializer)?;
+
+        for (tag, histogram) in LatencyHistograms::tags().iter().zip(self.histograms()) {
+            log_writer.write_histogram(histogram, Duration::from_secs(0), duration, Tag::new(tag))
+                .map_err(|e| Error::other(format!("Failed to write histogram '{}': {}", tag, e)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads histograms written by `write_log`, histograms with the same tag are merged
+    pub fn read_log(input: &[u8]) -> std::io::Result<LatencyHistograms> {
+        let mut histograms = LatencyHistograms::new(DEFAULT_SIGNIFICANT_DIGITS)?;
+        let mut deserializer = Deserializer::new();
+
+        for entry in IntervalLogIterator::new(input) {
+            let interval = match entry {
+                Ok(LogEntry::Interval(interval)) => interval,
+                Ok(_) => continue,
+                Err(e) => return Err(Error::new(ErrorKind::InvalidData, format!("Invalid histogram log: {:?}", e)))
+            };
+
+            let tag = interval.tag().map(|tag| tag.as_str()).unwrap_or_default();
+            let index = match LatencyHistograms::tags().iter().position(|known_tag| *known_tag == tag) {
+                Some(index) => index,
+                None => continue
+            };
+
+            let encoded_histogram = base64::engine::general_purpose::STANDARD.decode(interval.encoded_histogram())
+                .map_err(|e| Error::new(ErrorKind::InvalidData, format!("Invalid histogram encoding: {}", e)))?;
+            let histogram: Histogram<u64> = deserializer.deserialize(&mut encoded_histogram.as_slice())
+                .map_err(|e| Error::new(ErrorKind::InvalidData, format!("Invalid histogram: {}", e)))?;
+
+            histograms.histograms_mut()[index].add(&histogram)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, format!("Histograms cannot be merged: {}", e)))?;
+        }
+
+        Ok(histograms)
+    }
+
+    fn tags() -> [&'static str; 4] {
+        [LATENCY_TAG, LATENCY_CLIENT_TO_SERVER_TAG, LATENCY_SERVER_TO_CLIENT_TAG, SERVER_PROCESSING_TIME_TAG]
+    }
+
+    fn histograms(&self) -> [&Histogram<u64>; 4] {
+        [&self.latency, &self.latency_client_to_server, &self.latency_server_to_client, &self.server_processing_time]
+    }
+
+    fn histograms_mut(&mut self) -> [&mut Histogram<u64>; 4] {
+        [&mut self.latency, &mut self.latency_client_to_server, &mut self.latency_server_to_client, &mut self.server_processing_time]
+    }
+}
+
+/// Indices of the received packets of a test in histogram mode or of its interval reports, one bit per sent packet,
+/// to detect duplicates
+pub struct ReceivedIndices {
+    words: Vec<u64>
+}
+
+impl ReceivedIndices {
+    pub fn new(packet_count: u64) -> ReceivedIndices {
+        ReceivedIndices {
+            words: vec![0; packet_count.div_ceil(64) as usize]
+        }
+    }
+
+    /// Marks `index` as received, returns false if it was received before or is not the index of a sent packet
+    pub fn insert(&mut self, index: u64) -> bool {
+        match self.words.get_mut((index / 64) as usize) {
+            Some(word) if *word & (1 << (index % 64)) == 0 => {
+                *word |= 1 << (index % 64);
+                true
+            }
+            _ => false
+        }
+    }
+
+    pub fn contains(&self, index: u64) -> bool {
+        self.words.get((index / 64) as usize).is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+}
@@ -0,0 +1,375 @@
+/*<copyright file="html_report.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+// Self-contained HTML report of a test result. All charts are inline SVG, so the report has no external
+// assets. Packets are placed on the time axis at their send timestamp, relative to the start of the test.
+// Without per packet results, e.g. in histogram mode, only the percentiles of the summary are plotted.
+
+use std::f64::consts::LOG10_2;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write;
+
+use crate::json_result::JsonResult;
+use crate::packet_result::{PacketResult, nanos_to_millis};
+use crate::statistics::LatencyStatistics;
+
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 300.0;
+const MARGIN_LEFT: f64 = 70.0;
+const MARGIN_RIGHT: f64 = 20.0;
+const MARGIN_TOP: f64 = 30.0;
+const MARGIN_BOTTOM: f64 = 45.0;
+const TICK_COUNT: usize = 5;
+/// Time series with more packets are averaged over buckets of packets
+const MAX_POINTS: usize = 2000;
+const HISTOGRAM_BINS: usize = 50;
+const CDF_POINTS: usize = 1000;
+const LOSS_BINS: usize = 200;
+/// Percentiles of the summary statistics, placed on the x axis by their number of nines (1 = p90, 2 = p99)
+const PERCENTILES: [(f64, Percentile); 5] = [
+    (LOG10_2, |statistics| statistics.median),
+    (1.0, |statistics| statistics.p90),
+    (2.0, |statistics| statistics.p99),
+    (3.0, |statistics| statistics.p99_9),
+    (4.0, |statistics| statistics.p99_99)
+];
+
+/// Position of a value on the x and y axis of a chart
+type Point = (f64, f64);
+
+type Percentile = fn(&LatencyStatistics) -> f64;
+
+const COLOR_PRIMARY: &str = "#1f77b4";
+const COLOR_SECONDARY: &str = "#ff7f0e";
+const COLOR_LOSS: &str = "#d62728";
+
+pub fn render(json_result: &JsonResult) -> String {
+    let parameters = &json_result.test_parameters;
+    let latency_name = if parameters.output_rtt { "Round trip time" } else { "Latency" };
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>rperf report</title>\n<style>\n");
+    html.push_str("body { font-family: sans-serif; margin: 2em; color: #222; }\n");
+    html.push_str("table { border-collapse: collapse; margin-bottom: 2em; }\n");
+    html.push_str("td, th { border: 1px solid #ccc; padding: 0.3em 0.8em; text-align: right; }\n");
+    html.push_str("th { text-align: left; background: #f4f4f4; }\n");
+    html.push_str("svg { display: block; margin-bottom: 2em; }\n");
+    html.push_str("</style>\n</head>\n<body>\n");
+
+    let _ = writeln!(html, "<h1>rperf report</h1>\n<p>{} test against {}:{}, {} s at {} packets per second of {} bytes (warm-up {} s)</p>",
+                     escape(&parameters.protocol.to_uppercase()), escape(&parameters.server_ip), parameters.server_port,
+                     parameters.test_duration_valid.as_secs_f64(), parameters.packets_per_second, parameters.packet_size,
+                     parameters.warmup_duration.as_secs_f64());
+    html.push_str(&summary_table(json_result, latency_name));
+
+    let packet_results = match &json_result.packet_results {
+        Some(packet_results) if !packet_results.is_empty() => packet_results,
+        _ => {
+            html.push_str(&percentiles_chart(json_result, latency_name));
+            html.push_str("<p>The result contains no per packet results, time series require a test recorded per packet and exported with --json-packets.</p>\n");
+            html.push_str("</body>\n</html>\n");
+            return html;
+        }
+    };
+
+    let send_times = SendTimes::new(packet_results, parameters.packets_per_second);
+    let send_time = |index: u64| send_times.send_time(index);
+    let test_duration = parameters.test_duration_total.as_secs_f64();
+
+    let latencies: Vec<Point> = packet_results.iter()
+        .map(|packet_result| (send_times.sent(packet_result), packet_result.latency_millis())).collect();
+    let latest_send_time = latencies.iter().map(|(time, _)| *time).fold(0_f64, f64::max);
+    let time_range = (0_f64, test_duration.max(latest_send_time));
+    let (latency_min, latency_max) = value_range(latencies.iter().map(|(_, latency)| *latency));
+
+    let (means, maxima) = downsample(&latencies);
+    let mut chart = Chart::new(&format!("{} over time", latency_name), "Time [s]", &format!("{} [ms]", latency_name), time_range, (0_f64, latency_max));
+    if maxima.len() < latencies.len() {
+        chart.polyline(&maxima, COLOR_SECONDARY);
+        chart.legend(&[("maximum", COLOR_SECONDARY), ("mean", COLOR_PRIMARY)]);
+    }
+    chart.polyline(&means, COLOR_PRIMARY);
+    html.push_str(&chart.to_svg());
+
+    let bin_width = (latency_max - latency_min).max(1e-6) / HISTOGRAM_BINS as f64;
+    let mut bin_counts = vec![0_u64; HISTOGRAM_BINS];
+    for (_, latency) in &latencies {
+        bin_counts[(((latency - latency_min) / bin_width) as usize).min(HISTOGRAM_BINS - 1)] += 1;
+    }
+    let bins: Vec<(f64, f64, f64)> = bin_counts.iter().enumerate()
+        .map(|(bin, count)| (latency_min + bin as f64 * bin_width, latency_min + (bin + 1) as f64 * bin_width, *count as f64)).collect();
+    let mut chart = Chart::new(&format!("{} histogram", latency_name), &format!("{} [ms]", latency_name), "Packets",
+                               (latency_min, latency_min + HISTOGRAM_BINS as f64 * bin_width), (0_f64, *bin_counts.iter().max().unwrap() as f64));
+    chart.bars(&bins, COLOR_PRIMARY);
+    html.push_str(&chart.to_svg());
+
+    let mut sorted_latencies: Vec<f64> = latencies.iter().map(|(_, latency)| *latency).collect();
+    sorted_latencies.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let step = (sorted_latencies.len() as f64 / CDF_POINTS as f64).max(1_f64);
+    let mut cdf: Vec<Point> = (0..sorted_latencies.len().min(CDF_POINTS))
+        .map(|point| (point as f64 * step) as usize)
+        .map(|position| (sorted_latencies[position], position as f64 / sorted_latencies.len() as f64)).collect();
+    cdf.push((latency_max, 1_f64));
+    let mut chart = Chart::new(&format!("{} CDF", latency_name), &format!("{} [ms]", latency_name), "Share of packets", (latency_min, latency_max), (0_f64, 1_f64));
+    chart.polyline(&cdf, COLOR_PRIMARY);
+    html.push_str(&chart.to_svg());
+
+    let one_way_latencies: Vec<(f64, f64, f64)> = packet_results.iter()
+        .filter_map(|packet_result| match (packet_result.latency_client_to_server_ns, packet_result.latency_server_to_client_ns) {
+            (Some(client_to_server), Some(server_to_client)) => Some((send_times.sent(packet_result), nanos_to_millis(client_to_server), nanos_to_millis(server_to_client))),
+            _ => None
+        }).collect();
+    if one_way_latencies.is_empty() {
+        html.push_str("<p>One way latencies were not measured (--owl).</p>\n");
+    } else {
+        let client_to_server: Vec<Point> = one_way_latencies.iter().map(|(time, latency, _)| (*time, *latency)).collect();
+        let server_to_client: Vec<Point> = one_way_latencies.iter().map(|(time, _, latency)| (*time, *latency)).collect();
+        let (_, max) = value_range(one_way_latencies.iter().flat_map(|(_, client_to_server, server_to_client)| vec![*client_to_server, *server_to_client]));
+        let mut chart = Chart::new("One way latency per direction", "Time [s]", "Latency [ms]", time_range, (0_f64, max));
+        chart.polyline(&downsample(&client_to_server).0, COLOR_PRIMARY);
+        chart.polyline(&downsample(&server_to_client).0, COLOR_SECONDARY);
+        chart.legend(&[("client to server", COLOR_PRIMARY), ("server to client", COLOR_SECONDARY)]);
+        html.push_str(&chart.to_svg());
+    }
+
+    let lost_packet_indices = json_result.lost_packet_indices.as_deref().unwrap_or_default();
+    let bin_width = time_range.1.max(1e-6) / LOSS_BINS as f64;
+    let mut bin_counts = vec![0_u64; LOSS_BINS];
+    for index in lost_packet_indices {
+        bin_counts[((send_time(*index) / bin_width) as usize).min(LOSS_BINS - 1)] += 1;
+    }
+    let bins: Vec<(f64, f64, f64)> = bin_counts.iter().enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(bin, count)| (bin as f64 * bin_width, (bin + 1) as f64 * bin_width, *count as f64)).collect();
+    let mut chart = Chart::new(&format!("Loss timeline ({} packets lost)", lost_packet_indices.len()), "Time [s]", "Lost packets",
+                               time_range, (0_f64, (*bin_counts.iter().max().unwrap() as f64).max(1_f64)));
+    chart.bars(&bins, COLOR_LOSS);
+    html.push_str(&chart.to_svg());
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+pub fn write_file(json_result: &JsonResult, path: &str) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(render(json_result).as_bytes())?;
+    file.flush()
+}
+
+/// Percentiles of the latencies in the summary, drawn for results without per packet results
+fn percentiles_chart(json_result: &JsonResult, latency_name: &str) -> String {
+    let summary = &json_result.summary;
+    let series: Vec<(&str, &LatencyStatistics, &str)> = [
+        (latency_name, &summary.latency, COLOR_PRIMARY),
+        ("Latency client to server", &summary.latency_client_to_server, COLOR_SECONDARY),
+        ("Latency server to client", &summary.latency_server_to_client, COLOR_LOSS)
+    ].iter().filter_map(|(name, statistics, color)| statistics.as_ref().map(|statistics| (*name, statistics, *color))).collect();
+    if series.is_empty() {
+        return String::new();
+    }
+
+    let (_, max) = value_range(series.iter().flat_map(|(_, statistics, _)| PERCENTILES.iter().map(move |(_, percentile)| percentile(statistics))));
+    let mut chart = Chart::new("Latency percentiles", "Percentile [nines: 1 = p90, 2 = p99, 3 = p99.9, 4 = p99.99]", "Latency [ms]",
+                               (0_f64, PERCENTILES[PERCENTILES.len() - 1].0), (0_f64, max));
+    for (_, statistics, color) in &series {
+        let points: Vec<Point> = PERCENTILES.iter().map(|(nines, percentile)| (*nines, percentile(statistics))).collect();
+        chart.polyline(&points, color);
+    }
+    let legend: Vec<(&str, &str)> = series.iter().map(|(name, _, color)| (*name, *color)).collect();
+    chart.legend(&legend);
+
+    chart.to_svg()
+}
+
+/// Send times in seconds since the start of the test, taken from the send timestamps. Lost packets have no
+/// timestamp, their send time is interpolated between the received packets with the neighboring indices.
+struct SendTimes<'a> {
+    packet_results: &'a [PacketResult],
+    start_timestamp_ns: u64,
+    packets_per_second: f64
+}
+
+impl<'a> SendTimes<'a> {
+    /// `packet_results` must not be empty and be ordered by index
+    fn new(packet_results: &'a [PacketResult], packets_per_second: u32) -> SendTimes<'a> {
+        let first = &packet_results[0];
+
+        SendTimes {
+            packet_results,
+            start_timestamp_ns: first.sent_timestamp_ns.saturating_sub(first.sent_time_ns),
+            packets_per_second: packets_per_second.max(1) as f64
+        }
+    }
+
+    fn sent(&self, packet_result: &PacketResult) -> f64 {
+        packet_result.sent_timestamp_ns.saturating_sub(self.start_timestamp_ns) as f64 * 1e-9
+    }
+
+    fn send_time(&self, index: u64) -> f64 {
+        let position = self.packet_results.partition_point(|packet_result| packet_result.index < index);
+        match (position.checked_sub(1).map(|previous| &self.packet_results[previous]), self.packet_results.get(position)) {
+            (Some(previous), Some(next)) => {
+                let share = (index - previous.index) as f64 / (next.index - previous.index) as f64;
+                self.sent(previous) + (self.sent(next) - self.sent(previous)) * share
+            }
+            (Some(previous), None) => self.sent(previous) + (index - previous.index) as f64 / self.packets_per_second,
+            (None, Some(next)) => (self.sent(next) - (next.index - index) as f64 / self.packets_per_second).max(0_f64),
+            (None, None) => index as f64 / self.packets_per_second
+        }
+    }
+}
+
+fn summary_table(json_result: &JsonResult, latency_name: &str) -> String {
+    let summary = &json_result.summary;
+    let mut table = String::from("<table>\n");
+    let mut row = |name: &str, value: String| {
+        let _ = writeln!(table, "<tr><th>{}</th><td>{}</td></tr>", escape(name), value);
+    };
+
+    row("Sent packets", summary.sent_packets_count.to_string());
+    row("Received packets", summary.received_packets_count.to_string());
+    row("Lost packets", summary.lost_packets_count.to_string());
+    row("Duplicate packets", summary.duplicate_packets_count.to_string());
+    let mut statistics_rows = |name: &str, statistics: &Option<LatencyStatistics>| {
+        if let Some(statistics) = statistics {
+            row(&format!("{} min | mean | median | p99 | max [ms]", name), format!("{:.6} | {:.6} | {:.6} | {:.6} | {:.6}",
+                statistics.min, statistics.mean, statistics.median, statistics.p99, statistics.max));
+        }
+    };
+    statistics_rows(latency_name, &summary.latency);
+    statistics_rows("Latency client to server", &summary.latency_client_to_server);
+    statistics_rows("Latency server to client", &summary.latency_server_to_client);
+    statistics_rows("Server processing time", &summary.server_processing_time);
+    if let Some(jitter) = &summary.round_trip_time_jitter {
+        row("Round trip time jitter [ms]", format!("{:.6}", jitter.interarrival_jitter));
+    }
+    if let Some(max_gap) = summary.max_gap {
+        row("Max gap [ms]", format!("{:.6}", max_gap));
+    }
+
+    table.push_str("</table>\n");
+    table
+}
+
+/// Averages and maxima of the values over buckets of consecutive points, if there are more than `MAX_POINTS`
+fn downsample(points: &[Point]) -> (Vec<Point>, Vec<Point>) {
+    let bucket_size = points.len().div_ceil(MAX_POINTS);
+    points.chunks(bucket_size.max(1)).map(|bucket| {
+        let time = bucket.iter().map(|(time, _)| time).sum::<f64>() / bucket.len() as f64;
+        let mean = bucket.iter().map(|(_, value)| value).sum::<f64>() / bucket.len() as f64;
+        let max = bucket.iter().map(|(_, value)| *value).fold(f64::MIN, f64::max);
+        ((time, mean), (time, max))
+    }).unzip()
+}
+
+fn value_range<I: Iterator<Item = f64>>(values: I) -> (f64, f64) {
+    values.fold((f64::MAX, f64::MIN), |(min, max), value| (min.min(value), max.max(value)))
+}
+
+struct Chart {
+    title: String,
+    x_label: String,
+    y_label: String,
+    x_range: (f64, f64),
+    y_range: (f64, f64),
+    body: String
+}
+
+impl Chart {
+    fn new(title: &str, x_label: &str, y_label: &str, x_range: (f64, f64), y_range: (f64, f64)) -> Chart {
+        let widen = |(min, max): (f64, f64)| if max > min { (min, max) } else { (min, min + 1_f64) };
+
+        Chart {
+            title: title.to_string(),
+            x_label: x_label.to_string(),
+            y_label: y_label.to_string(),
+            x_range: widen(x_range),
+            y_range: widen(y_range),
+            body: String::new()
+        }
+    }
+
+    fn x(&self, value: f64) -> f64 {
+        MARGIN_LEFT + (value - self.x_range.0) / (self.x_range.1 - self.x_range.0) * (CHART_WIDTH - MARGIN_LEFT - MARGIN_RIGHT)
+    }
+
+    fn y(&self, value: f64) -> f64 {
+        CHART_HEIGHT - MARGIN_BOTTOM - (value - self.y_range.0) / (self.y_range.1 - self.y_range.0) * (CHART_HEIGHT - MARGIN_TOP - MARGIN_BOTTOM)
+    }
+
+    fn polyline(&mut self, points: &[Point], color: &str) {
+        let points: Vec<String> = points.iter().map(|(x, y)| format!("{:.1},{:.1}", self.x(*x), self.y(*y))).collect();
+        let _ = writeln!(self.body, "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1\" points=\"{}\"/>", color, points.join(" "));
+    }
+
+    /// Bars given by start, end and height
+    fn bars(&mut self, bars: &[(f64, f64, f64)], color: &str) {
+        for (start, end, height) in bars {
+            let _ = writeln!(self.body, "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"/>",
+                             self.x(*start), self.y(*height), (self.x(*end) - self.x(*start)).max(1_f64), self.y(self.y_range.0) - self.y(*height), color);
+        }
+    }
+
+    fn legend(&mut self, entries: &[(&str, &str)]) {
+        for (position, (name, color)) in entries.iter().enumerate() {
+            let y = MARGIN_TOP + 12_f64 + position as f64 * 16_f64;
+            let _ = writeln!(self.body, "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"10\" height=\"10\" fill=\"{}\"/><text x=\"{:.1}\" y=\"{:.1}\" font-size=\"12\">{}</text>",
+                             CHART_WIDTH - MARGIN_RIGHT - 130_f64, y - 9_f64, color, CHART_WIDTH - MARGIN_RIGHT - 115_f64, y, escape(name));
+        }
+    }
+
+    fn to_svg(&self) -> String {
+        let mut svg = String::new();
+        let _ = writeln!(svg, "<svg width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" font-family=\"sans-serif\">", CHART_WIDTH, CHART_HEIGHT, CHART_WIDTH, CHART_HEIGHT);
+        let _ = writeln!(svg, "<text x=\"{}\" y=\"18\" font-size=\"14\" font-weight=\"bold\" text-anchor=\"middle\">{}</text>", CHART_WIDTH / 2_f64, escape(&self.title));
+
+        for tick in 0..=TICK_COUNT {
+            let x_value = self.x_range.0 + (self.x_range.1 - self.x_range.0) * tick as f64 / TICK_COUNT as f64;
+            let x = self.x(x_value);
+            let _ = writeln!(svg, "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#eee\"/><text x=\"{:.1}\" y=\"{:.1}\" font-size=\"11\" text-anchor=\"middle\">{}</text>",
+                             x, MARGIN_TOP, x, CHART_HEIGHT - MARGIN_BOTTOM, x, CHART_HEIGHT - MARGIN_BOTTOM + 15_f64, format_tick(x_value, self.x_range));
+
+            let y_value = self.y_range.0 + (self.y_range.1 - self.y_range.0) * tick as f64 / TICK_COUNT as f64;
+            let y = self.y(y_value);
+            let _ = writeln!(svg, "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#eee\"/><text x=\"{:.1}\" y=\"{:.1}\" font-size=\"11\" text-anchor=\"end\">{}</text>",
+                             MARGIN_LEFT, y, CHART_WIDTH - MARGIN_RIGHT, y, MARGIN_LEFT - 5_f64, y + 4_f64, format_tick(y_value, self.y_range));
+        }
+
+        svg.push_str(&self.body);
+        let _ = writeln!(svg, "<polyline fill=\"none\" stroke=\"#222\" points=\"{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}\"/>",
+                         MARGIN_LEFT, MARGIN_TOP, MARGIN_LEFT, CHART_HEIGHT - MARGIN_BOTTOM, CHART_WIDTH - MARGIN_RIGHT, CHART_HEIGHT - MARGIN_BOTTOM);
+        let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\" font-size=\"12\" text-anchor=\"middle\">{}</text>",
+                         (MARGIN_LEFT + CHART_WIDTH - MARGIN_RIGHT) / 2_f64, CHART_HEIGHT - 8_f64, escape(&self.x_label));
+        let _ = writeln!(svg, "<text x=\"15\" y=\"{}\" font-size=\"12\" text-anchor=\"middle\" transform=\"rotate(-90 15 {})\">{}</text>",
+                         (MARGIN_TOP + CHART_HEIGHT - MARGIN_BOTTOM) / 2_f64, (MARGIN_TOP + CHART_HEIGHT - MARGIN_BOTTOM) / 2_f64, escape(&self.y_label));
+        svg.push_str("</svg>\n");
+
+        svg
+    }
+}
+
+/// Enough decimals to tell the ticks of the range apart
+fn format_tick(value: f64, (min, max): (f64, f64)) -> String {
+    let step = (max - min) / TICK_COUNT as f64;
+    let decimals = (1_f64 - step.log10().floor()).clamp(0_f64, 9_f64) as usize;
+
+    format!("{:.*}", decimals, value)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,181 @@
+/*<copyright file="influxdb.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+// Export of test results in the InfluxDB line protocol, written to a file or posted to the HTTP write
+// endpoint of InfluxDB (e.g. 'http://localhost:8086/api/v2/write?org=lab&bucket=rperf' or
+// 'http://localhost:8086/write?db=rperf'). Latencies and times are in milliseconds.
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use crate::json_result::JsonResult;
+use crate::packet_result::nanos_to_millis;
+use crate::statistics::LatencyStatistics;
+use crate::test_result::TestResult;
+
+pub const SUMMARY_MEASUREMENT: &str = "rperf_summary";
+pub const PACKET_MEASUREMENT: &str = "rperf_packet";
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Summary point timestamped with `timestamp_ns` and, if requested, one point per received packet timestamped
+/// with the time it was sent
+pub fn to_line_protocol(test_result: &TestResult, include_packet_results: bool, timestamp_ns: u64) -> String {
+    let json_result = JsonResult::from_test_result(test_result, include_packet_results);
+    let summary = &json_result.summary;
+    let parameters = &json_result.test_parameters;
+    let tags = format!("protocol={},packet_size={},packets_per_second={},server={}",
+                       escape_tag(&parameters.protocol), parameters.packet_size, parameters.packets_per_second,
+                       escape_tag(&format!("{}:{}", parameters.server_ip, parameters.server_port)));
+
+    let mut fields = Fields::default();
+    fields.float("sent_duration_seconds", summary.sent_duration_seconds);
+    fields.integer("sent_packets_count", summary.sent_packets_count);
+    fields.integer("received_packets_count", summary.received_packets_count);
+    fields.integer("lost_packets_count", summary.lost_packets_count);
+    fields.integer("duplicate_packets_count", summary.duplicate_packets_count);
+    fields.latency_statistics("latency", &summary.latency);
+    fields.latency_statistics("latency_client_to_server", &summary.latency_client_to_server);
+    fields.latency_statistics("latency_server_to_client", &summary.latency_server_to_client);
+    fields.latency_statistics("server_processing_time", &summary.server_processing_time);
+    if let Some(max_gap) = summary.max_gap {
+        fields.float("max_gap_ms", max_gap);
+    }
+    if let Some(jitter) = &summary.round_trip_time_jitter {
+        fields.float("round_trip_time_jitter_ms", jitter.interarrival_jitter);
+    }
+    if let Some(jitter) = &summary.latency_client_to_server_jitter {
+        fields.float("latency_client_to_server_jitter_ms", jitter.interarrival_jitter);
+    }
+    if let Some(jitter) = &summary.latency_server_to_client_jitter {
+        fields.float("latency_server_to_client_jitter_ms", jitter.interarrival_jitter);
+    }
+    if let Some(reordering) = &summary.reordering {
+        fields.integer("reordered_packets_count", reordering.reordered_packets_count);
+    }
+    if let Some(loss) = &summary.loss {
+        fields.integer("loss_burst_count", loss.burst_count);
+        fields.integer("longest_loss_burst", loss.longest_burst);
+    }
+    fields.boolean("deadline_miss_ratio_exceeded", summary.deadline_miss_ratio_exceeded);
+
+    let mut output = String::new();
+    let _ = writeln!(output, "{},{} {} {}", SUMMARY_MEASUREMENT, tags, fields.0, timestamp_ns);
+
+    for packet_result in json_result.packet_results.iter().flatten() {
+        let mut fields = Fields::default();
+        fields.integer("index", packet_result.index);
+        fields.float("latency_ms", packet_result.latency_millis());
+        fields.float("round_trip_time_ms", nanos_to_millis(packet_result.round_trip_time_ns));
+        if let Some(server_processing_time) = packet_result.server_processing_time_millis() {
+            fields.float("server_processing_time_ms", server_processing_time);
+        }
+        if let Some(latency_client_to_server) = packet_result.latency_client_to_server_millis() {
+            fields.float("latency_client_to_server_ms", latency_client_to_server);
+        }
+        if let Some(latency_server_to_client) = packet_result.latency_server_to_client_millis() {
+            fields.float("latency_server_to_client_ms", latency_server_to_client);
+        }
+        let _ = writeln!(output, "{},{} {} {}", PACKET_MEASUREMENT, tags, fields.0, packet_result.sent_timestamp_ns);
+    }
+
+    output
+}
+
+pub fn write_file(lines: &str, path: &str) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(lines.as_bytes())?;
+    file.flush()
+}
+
+/// Posts the lines to an InfluxDB write endpoint ('http://host:port/path?query'), the token is sent as
+/// 'Authorization: Token <token>' header
+pub fn post(lines: &str, url: &str, token: Option<&str>) -> std::io::Result<()> {
+    let address = url.strip_prefix("http://")
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("Unsupported URL '{}', only 'http://' is supported", url)))?;
+    let (host, path) = match address.find('/') {
+        Some(index) => (&address[..index], &address[index..]),
+        None => (address, "/")
+    };
+    if host.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Missing host in URL '{}'", url)));
+    }
+
+    let mut stream = if host.contains(':') { TcpStream::connect(host)? } else { TcpStream::connect((host, 80))? };
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+
+    let mut request = format!("POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n",
+                              path, host, lines.len());
+    if let Some(token) = token {
+        let _ = write!(request, "Authorization: Token {}\r\n", token);
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+    stream.write_all(lines.as_bytes())?;
+    stream.flush()?;
+
+    let mut status_line = String::new();
+    BufReader::new(&stream).read_line(&mut status_line)?;
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(Error::other(format!("InfluxDB rejected results: '{}'", status_line.trim_end())))
+    }
+}
+
+#[derive(Default)]
+struct Fields(String);
+
+impl Fields {
+    fn push(&mut self, key: &str, value: &str) {
+        if !self.0.is_empty() {
+            self.0.push(',');
+        }
+        let _ = write!(self.0, "{}={}", key, value);
+    }
+
+    /// Non finite values cannot be represented and are skipped
+    fn float(&mut self, key: &str, value: f64) {
+        if value.is_finite() {
+            self.push(key, &value.to_string());
+        }
+    }
+
+    fn integer(&mut self, key: &str, value: u64) {
+        self.push(key, &format!("{}i", value));
+    }
+
+    fn boolean(&mut self, key: &str, value: bool) {
+        self.push(key, &value.to_string());
+    }
+
+    fn latency_statistics(&mut self, prefix: &str, statistics: &Option<LatencyStatistics>) {
+        if let Some(statistics) = statistics {
+            for (name, value) in [("min", statistics.min), ("mean", statistics.mean), ("max", statistics.max), ("std_dev", statistics.std_dev),
+                                  ("median", statistics.median), ("p90", statistics.p90), ("p99", statistics.p99),
+                                  ("p99_9", statistics.p99_9), ("p99_99", statistics.p99_99)].iter() {
+                self.float(&format!("{}_{}_ms", prefix, name), *value);
+            }
+        }
+    }
+}
+
+fn escape_tag(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
@@ -0,0 +1,275 @@
+/*<copyright file="interval_report.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+
+use std::{fmt, io, mem, thread};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::event_stream::EventSink;
+use crate::histogram::ReceivedIndices;
+use crate::packet::ReceivedPacket;
+use crate::statistics::LatencyStatistics;
+
+/// Interval of the summaries in the packet event stream if no report interval is configured
+pub const DEFAULT_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Statistics of one interval of a running test. Times are nanoseconds since the start of the test,
+/// the jitter is the RFC 3550 interarrival jitter of the round trip times at the end of the interval in milliseconds.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IntervalReport {
+    pub start_time_ns: u64,
+    pub end_time_ns: u64,
+    pub sent_packets_count: u64,
+    pub received_packets_count: u64,
+    /// Estimated from gaps in the indices of the received packets
+    pub lost_packets_count: u64,
+    pub latency: Option<LatencyStatistics>,
+    pub interarrival_jitter: f64
+}
+
+impl fmt::Display for IntervalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{:8.3} - {:8.3} s] sent {} | received {} | lost {}",
+               self.start_time_ns as f64 * 1e-9, self.end_time_ns as f64 * 1e-9, self.sent_packets_count, self.received_packets_count, self.lost_packets_count)?;
+        if let Some(latency) = &self.latency {
+            write!(f, " | min {:.6} | avg {:.6} | max {:.6} | p99 {:.6}", latency.min, latency.mean, latency.max, latency.p99)?;
+        }
+
+        write!(f, " | jitter {:.6} ms", self.interarrival_jitter)
+    }
+}
+
+/// Packets sent by the sender thread. The number of packets sent before every interval boundary is kept, so
+/// the sent packets of an interval are exact no matter when the interval is closed.
+pub struct SentPacketsCounter {
+    interval_ns: u64,
+    sent_packets_count: AtomicU64,
+    /// Only changed by the sender thread, which takes the lock once per interval
+    next_boundary_ns: AtomicU64,
+    counts_at_boundaries: Mutex<Vec<u64>>
+}
+
+impl SentPacketsCounter {
+    pub fn new(interval: Duration) -> SentPacketsCounter {
+        let interval_ns = (interval.as_nanos() as u64).max(1);
+        SentPacketsCounter {
+            interval_ns,
+            sent_packets_count: AtomicU64::new(0),
+            next_boundary_ns: AtomicU64::new(interval_ns),
+            counts_at_boundaries: Mutex::new(Vec::new())
+        }
+    }
+
+    /// Counts a packet sent `sent_duration` after the start of the test, called by the sender thread only
+    pub fn packet_sent(&self, sent_duration: Duration) {
+        let sent_time_ns = sent_duration.as_nanos() as u64;
+        if sent_time_ns >= self.next_boundary_ns.load(Ordering::Relaxed) {
+            let mut counts_at_boundaries = self.counts_at_boundaries.lock().unwrap();
+            let passed_boundaries = sent_time_ns / self.interval_ns;
+            let sent_packets_count = self.sent_packets_count.load(Ordering::Relaxed);
+            while (counts_at_boundaries.len() as u64) < passed_boundaries {
+                counts_at_boundaries.push(sent_packets_count);
+            }
+            self.next_boundary_ns.store((passed_boundaries + 1) * self.interval_ns, Ordering::Relaxed);
+        }
+        self.sent_packets_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Packets sent before `time_ns`. Exact at interval boundaries, otherwise all packets sent so far.
+    pub fn sent_before(&self, time_ns: u64) -> u64 {
+        // Holding the lock keeps the sender from counting packets of later intervals before their boundary is kept
+        let counts_at_boundaries = self.counts_at_boundaries.lock().unwrap();
+        if time_ns > 0 && time_ns.is_multiple_of(self.interval_ns) {
+            if let Some(sent_packets_count) = counts_at_boundaries.get((time_ns / self.interval_ns - 1) as usize) {
+                return *sent_packets_count;
+            }
+        }
+
+        self.sent_packets_count.load(Ordering::Relaxed)
+    }
+}
+
+/// Splits the received packets of the receiver thread into intervals of the interval of `sent_packets_counter`,
+/// which counts the packets of the sender thread. Intervals are closed by the first packet received after their end,
+/// and by a timer thread once started, so reports keep coming while no packets arrive. Like in the final result,
+/// only the first arrival of a packet is recorded.
+pub struct IntervalRecorder {
+    state: Arc<Mutex<IntervalState>>,
+    timer: Option<(mpsc::Sender<()>, JoinHandle<()>)>
+}
+
+impl IntervalRecorder {
+    /// `packet_count` is the number of packets the test sends, including the warm-up
+    pub fn new(print_reports: bool, output_rtt: bool, packet_count: u64, sent_packets_counter: Arc<SentPacketsCounter>, event_sink: Option<EventSink>) -> IntervalRecorder {
+        IntervalRecorder {
+            state: Arc::new(Mutex::new(IntervalState {
+                interval_ns: sent_packets_counter.interval_ns,
+                print_reports,
+                output_rtt,
+                sent_packets_counter,
+                event_sink,
+                reports: Vec::new(),
+                start_time_ns: 0,
+                sent_packets_count_at_start: 0,
+                latencies_ns: Vec::new(),
+                lost_packets_count: 0,
+                received_indices: ReceivedIndices::new(packet_count),
+                highest_index: None,
+                previous_round_trip_time_ns: None,
+                interarrival_jitter_ns: 0_f64,
+                last_received_time_ns: 0
+            })),
+            timer: None
+        }
+    }
+
+    /// Starts a thread closing the intervals of a test started at `start` shortly after their end.
+    /// Packets received before the end of an interval but recorded after its close are counted in the next one.
+    pub fn start_timer(&mut self, start: Instant) -> io::Result<()> {
+        let (stop_sender, stop_receiver) = mpsc::channel();
+        let state = self.state.clone();
+        let timer_thread = thread::Builder::new().name("rperf-intervals".to_string()).spawn(move || {
+            loop {
+                let next_end_time = {
+                    let state = state.lock().unwrap();
+                    Duration::from_nanos(state.start_time_ns + state.interval_ns) + TIMER_DELAY
+                };
+                match stop_receiver.recv_timeout(next_end_time.saturating_sub(start.elapsed())) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => state.lock().unwrap().close_until(start.elapsed().saturating_sub(TIMER_DELAY)),
+                    _ => break
+                }
+            }
+        })?;
+        self.timer = Some((stop_sender, timer_thread));
+
+        Ok(())
+    }
+
+    pub fn record(&mut self, received_packet: &ReceivedPacket) {
+        self.state.lock().unwrap().record(received_packet);
+    }
+
+    /// Closes all intervals ending at or before `time`
+    pub fn close_until(&self, time: Duration) {
+        self.state.lock().unwrap().close_until(time);
+    }
+
+    /// Closes the last interval at `end_time`, or at the last received packet if it arrived later, and returns all reports
+    pub fn finish(mut self, end_time: Duration) -> Vec<IntervalReport> {
+        if let Some((stop_sender, timer_thread)) = self.timer.take() {
+            drop(stop_sender);
+            let _ = timer_thread.join();
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let end_time_ns = (end_time.as_nanos() as u64).max(state.last_received_time_ns + 1);
+        state.close_until(Duration::from_nanos(end_time_ns));
+        if end_time_ns > state.start_time_ns {
+            state.close_interval(end_time_ns);
+        }
+
+        mem::take(&mut state.reports)
+    }
+}
+
+/// Time the timer waits after the end of an interval for packets received before it
+const TIMER_DELAY: Duration = Duration::from_millis(10);
+
+struct IntervalState {
+    interval_ns: u64,
+    print_reports: bool,
+    output_rtt: bool,
+    sent_packets_counter: Arc<SentPacketsCounter>,
+    event_sink: Option<EventSink>,
+    reports: Vec<IntervalReport>,
+    start_time_ns: u64,
+    sent_packets_count_at_start: u64,
+    latencies_ns: Vec<u64>,
+    lost_packets_count: i64,
+    received_indices: ReceivedIndices,
+    highest_index: Option<u64>,
+    previous_round_trip_time_ns: Option<u64>,
+    interarrival_jitter_ns: f64,
+    last_received_time_ns: u64
+}
+
+impl IntervalState {
+    fn record(&mut self, received_packet: &ReceivedPacket) {
+        self.close_until(received_packet.received_duration);
+        let time_ns = received_packet.received_duration.as_nanos() as u64;
+        self.last_received_time_ns = self.last_received_time_ns.max(time_ns);
+        if !self.received_indices.insert(received_packet.index) {
+            return;
+        }
+
+        match self.highest_index {
+            Some(highest_index) if received_packet.index <= highest_index => self.lost_packets_count -= 1,
+            Some(highest_index) => {
+                self.lost_packets_count += (received_packet.index - highest_index - 1) as i64;
+                self.highest_index = Some(received_packet.index);
+            }
+            None => {
+                self.lost_packets_count += received_packet.index as i64;
+                self.highest_index = Some(received_packet.index);
+            }
+        }
+
+        let round_trip_time_ns = received_packet.received_timestamp_ns.saturating_sub(received_packet.sent_timestamp_ns);
+        if let Some(previous_round_trip_time_ns) = self.previous_round_trip_time_ns {
+            let difference_ns = (round_trip_time_ns as f64 - previous_round_trip_time_ns as f64).abs();
+            self.interarrival_jitter_ns += (difference_ns - self.interarrival_jitter_ns) / 16_f64;
+        }
+        self.previous_round_trip_time_ns = Some(round_trip_time_ns);
+        self.latencies_ns.push(if self.output_rtt { round_trip_time_ns } else { round_trip_time_ns / 2 });
+    }
+
+    fn close_until(&mut self, time: Duration) {
+        let time_ns = time.as_nanos() as u64;
+        while time_ns >= self.start_time_ns + self.interval_ns {
+            self.close_interval(self.start_time_ns + self.interval_ns);
+        }
+    }
+
+    fn close_interval(&mut self, end_time_ns: u64) {
+        let sent_packets_count = self.sent_packets_counter.sent_before(end_time_ns);
+        let report = IntervalReport {
+            start_time_ns: self.start_time_ns,
+            end_time_ns,
+            sent_packets_count: sent_packets_count.saturating_sub(self.sent_packets_count_at_start),
+            received_packets_count: self.latencies_ns.len() as u64,
+            lost_packets_count: self.lost_packets_count.max(0) as u64,
+            latency: LatencyStatistics::from_nanos(&self.latencies_ns),
+            interarrival_jitter: self.interarrival_jitter_ns * 1e-6
+        };
+
+        if self.print_reports {
+            println!("{}", report);
+        }
+        if let Some(event_sink) = &self.event_sink {
+            event_sink.interval_report(&report);
+        }
+        self.reports.push(report);
+
+        self.start_time_ns = end_time_ns;
+        self.sent_packets_count_at_start = sent_packets_count;
+        self.latencies_ns.clear();
+        self.lost_packets_count = 0;
+    }
+}
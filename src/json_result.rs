@@ -0,0 +1,136 @@
+/*<copyright file="json_result.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+
+// Machine-readable result of a test, the schema is described in docs/json-schema.md. Incompatible
+// changes of the schema increase the schema version.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Write};
+use serde::{Deserialize, Serialize};
+
+use crate::control::ServerStatistics;
+use crate::interval_report::IntervalReport;
+use crate::packet_result::PacketResult;
+use crate::statistics::{LatencyStatistics, JitterStatistics, ReorderingStatistics, LossStatistics, DeadlineStatistics};
+use crate::test_parameters::TestParameters;
+use crate::test_result::TestResult;
+
+pub const JSON_SCHEMA_VERSION: u32 = 2;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JsonResult {
+    pub schema_version: u32,
+    pub test_parameters: TestParameters,
+    pub summary: ResultSummary,
+    /// Only present if requested, ordered by index
+    pub packet_results: Option<Vec<PacketResult>>,
+    pub lost_packet_indices: Option<Vec<u64>>,
+    #[serde(default)]
+    pub interval_reports: Vec<IntervalReport>,
+    #[serde(default)]
+    pub server_statistics: Option<ServerStatistics>
+}
+
+/// Summary of a test, latencies and times in milliseconds. Averages are `None` without values to average.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResultSummary {
+    pub sent_duration_seconds: f64,
+    pub sent_packets_count: u64,
+    pub received_packets_count: u64,
+    pub lost_packets_count: u64,
+    pub duplicate_packets_count: u64,
+    pub average_latency: Option<f64>,
+    pub average_latency_client_to_server: Option<f64>,
+    pub average_latency_server_to_client: Option<f64>,
+    pub average_server_processing_time: Option<f64>,
+    pub average_network_round_trip_time: Option<f64>,
+    pub latency: Option<LatencyStatistics>,
+    pub latency_client_to_server: Option<LatencyStatistics>,
+    pub latency_server_to_client: Option<LatencyStatistics>,
+    pub server_processing_time: Option<LatencyStatistics>,
+    pub max_gap: Option<f64>,
+    pub round_trip_time_jitter: Option<JitterStatistics>,
+    pub latency_client_to_server_jitter: Option<JitterStatistics>,
+    pub latency_server_to_client_jitter: Option<JitterStatistics>,
+    pub reordering: Option<ReorderingStatistics>,
+    pub loss: Option<LossStatistics>,
+    pub deadlines: Vec<DeadlineStatistics>,
+    pub deadline_miss_ratio_exceeded: bool
+}
+
+impl JsonResult {
+    pub fn from_test_result(test_result: &TestResult, include_packet_results: bool) -> JsonResult {
+        let latency = test_result.latency_statistics();
+        let latency_client_to_server = test_result.latency_client_to_server_statistics();
+        let latency_server_to_client = test_result.latency_server_to_client_statistics();
+        let server_processing_time = test_result.server_processing_time_statistics();
+
+        JsonResult {
+            schema_version: JSON_SCHEMA_VERSION,
+            test_parameters: test_result.test_parameters.clone(),
+            summary: ResultSummary {
+                sent_duration_seconds: test_result.sent_duration_seconds,
+                sent_packets_count: test_result.sent_packets_count,
+                received_packets_count: test_result.received_packets_count,
+                lost_packets_count: test_result.lost_packets_count,
+                duplicate_packets_count: test_result.duplicate_packets_count,
+                // The averages of the test result are NaN without values, which cannot be read back from JSON
+                average_latency: latency.map(|_| test_result.average_latency()),
+                average_latency_client_to_server: latency_client_to_server.map(|_| test_result.average_latency_client_to_server()),
+                average_latency_server_to_client: latency_server_to_client.map(|_| test_result.average_latency_server_to_client()),
+                average_server_processing_time: server_processing_time.map(|_| test_result.average_server_processing_time()),
+                average_network_round_trip_time: latency.and(server_processing_time).map(|_| test_result.average_network_round_trip_time()),
+                latency,
+                latency_client_to_server,
+                latency_server_to_client,
+                server_processing_time,
+                max_gap: test_result.max_gap_millis(),
+                round_trip_time_jitter: test_result.round_trip_time_jitter(),
+                latency_client_to_server_jitter: test_result.latency_client_to_server_jitter(),
+                latency_server_to_client_jitter: test_result.latency_server_to_client_jitter(),
+                reordering: test_result.reordering.clone(),
+                loss: test_result.loss_statistics(),
+                deadlines: test_result.deadline_statistics(),
+                deadline_miss_ratio_exceeded: test_result.deadline_miss_ratio_exceeded()
+            },
+            packet_results: if include_packet_results { Some(test_result.packet_results.iter().copied().collect()) } else { None },
+            lost_packet_indices: if include_packet_results { Some(test_result.lost_packet_indices.clone()) } else { None },
+            interval_reports: test_result.interval_reports.clone(),
+            server_statistics: test_result.server_statistics
+        }
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        serde_json::to_writer_pretty(writer, self).map_err(Error::from)
+    }
+
+    pub fn write_file(&self, path: &str) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Reads a result, rejecting results of other schema versions
+    pub fn read_file(path: &str) -> std::io::Result<JsonResult> {
+        let json_result: JsonResult = serde_json::from_reader(BufReader::new(File::open(path)?)).map_err(Error::from)?;
+        if json_result.schema_version != JSON_SCHEMA_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported schema version {} of '{}' (expected {})", json_result.schema_version, path, JSON_SCHEMA_VERSION)));
+        }
+
+        Ok(json_result)
+    }
+}
@@ -1,45 +1,49 @@
 mod server_udp;
 mod server_tcp;
+mod server_control;
+mod session;
+#[allow(clippy::module_inception)]
 mod client;
-mod test_parameters;
-mod test_result;
-mod messages;
-mod packet;
-mod packet_result;
+pub mod test_parameters;
+pub mod test_result;
+mod framing;
+mod rate_limited_log;
+pub mod packet;
+pub mod control;
+pub mod packet_result;
+pub mod statistics;
+pub mod histogram;
+pub mod json_result;
+pub mod event_stream;
+pub mod interval_report;
+pub mod config;
+pub mod error;
+pub mod server_handle;
+pub mod influxdb;
+pub mod html_report;
+pub mod packet_csv;
+pub mod comparison;
+#[cfg(feature = "prometheus")]
+pub mod metrics;
 
 use crate::client::client::Client;
-use std::time::Duration;
-use std::io::ErrorKind;
+use crate::config::{ClientConfig, ServerConfig};
+use crate::error::Result;
+use crate::server_handle::ServerHandle;
 use crate::test_result::TestResult;
 
-pub async fn start_server(port: u16, protocol: &str, symmetric_network_load: bool) -> std::io::Result<()>
+/// Starts a server in the background, it runs until it is shut down through the returned handle
+pub async fn start_server(config: &ServerConfig) -> Result<ServerHandle>
 {
-    match protocol.to_lowercase().as_str() {
-        "udp" => {
-            let server = server_udp::server::ServerUdp::new();
-            server.start(port, symmetric_network_load).unwrap_or_else(|error| {
-                panic!("Problem running test: {:?}", error);
-            });
-            Ok(())
-        },
-        "tcp" => {
-            let server = server_tcp::server::ServerTcp::new();
-            server.start(port, symmetric_network_load).await.unwrap_or_else(|error| {
-                panic!("Problem running test: {:?}", error);
-            });
-            Ok(())
-        },
-        _ => {
-            println!("Unsupported protocol '{}' ", protocol);
-            Err(std::io::Error::new(ErrorKind::Unsupported, format!{"Unsupported protocol '{}'", protocol}))
-        }
-    }
+    config.validate()?;
+
+    server_control::server::ServerControl::start(config.clone()).await
 }
 
-pub async fn start_test(server_ip: &str, server_port: u16, protocol: &str, test_duration: Duration, packets_per_second: u32, packet_size: usize, warmup_duration: Duration, log_path: &str, output_rtt: bool, measure_owl: bool) -> std::io::Result<TestResult>
+/// Runs a test against a server, pings are sent and received on dedicated threads while the caller's runtime is free
+pub async fn start_test(config: &ClientConfig) -> Result<TestResult>
 {
-    let mut client = Client::new(server_ip, server_port, protocol, test_duration, packets_per_second, packet_size, warmup_duration, log_path, output_rtt, measure_owl);
-    let test_result = client.run_test().await.unwrap();
+    config.validate()?;
 
-    Ok(test_result)
+    Client::from_config(config.clone()).run_test().await
 }
@@ -17,6 +17,8 @@ limitations under the License.
 use clap::{App, Arg};
 use std::time::Duration;
 use std::process;
+use rperf::test_parameters::RecordingMode;
+use rperf::config::{ClientConfig, ServerConfig};
 
 #[tokio::main]
 async fn main() {
@@ -33,21 +35,40 @@ async fn main() {
                 .short('p')
                 .long("port")
                 .value_name("Port")
-                .about("Port of the server")
+                .help("Port of the server")
                 .required(true)
                 .takes_value(true))
             .arg(Arg::new("protocol")
                 .long("protocol")
                 .value_name("Protocol")
-                .about("Protocol of the server [tpc|udp]")
+                .help("Protocol of the server [tpc|udp]")
                 .required(true)
                 .takes_value(true))
             .arg(Arg::new("sym-load")
                 .long("sym-load")
                 .value_name("sym-load")
-                .about("Creates symmetric network load between client and server using ping packet size for pong packets. If this flag is not set pong packets have minimal packet size (16 bytes).")
+                .help("Creates symmetric network load between client and server using ping packet size for pong packets. If this flag is not set pong packets have minimal packet size (44 bytes).")
                 .required(false)
                 .takes_value(false))
+            .arg(Arg::new("workers")
+                .long("workers")
+                .value_name("workers")
+                .help("Number of UDP worker threads sharing the port with SO_REUSEPORT, 0 starts one per CPU [default: 1]")
+                .required(false)
+                .takes_value(true))
+            .arg(Arg::new("cpus")
+                .long("cpus")
+                .value_name("cpus")
+                .help("Comma separated CPUs the UDP workers are pinned to, e.g. 2,3")
+                .required(false)
+                .takes_value(true)
+                .use_value_delimiter(true))
+            .arg(Arg::new("metrics")
+                .long("metrics")
+                .value_name("address")
+                .help("Serve Prometheus metrics on the given address, e.g. 0.0.0.0:9100 (requires the prometheus feature)")
+                .required(false)
+                .takes_value(true))
         )
         .subcommand(App::new("client")
             .about("Execute latency test as client against a Rperf server")
@@ -57,85 +78,274 @@ async fn main() {
                 .short('i')
                 .long("ip")
                 .value_name("IP")
-                .about("IP of Rperf server which will be used for testing")
+                .help("IP of Rperf server which will be used for testing")
                 .required(true)
                 .takes_value(true))
             .arg(Arg::new("port")
                 .short('p')
                 .long("port")
                 .value_name("Port")
-                .about("Port of Rperf server which will be used for testing")
+                .help("Port of Rperf server which will be used for testing")
                 .required(true)
                 .takes_value(true))
             .arg(Arg::new("time")
                 .short('t')
                 .long("time")
                 .value_name("time")
-                .about("Duration of test [seconds]")
+                .help("Duration of test [seconds]")
                 .required(true)
                 .takes_value(true))
             .arg(Arg::new("mps")
                 .long("mps")
                 .value_name("time")
-                .about("Messages send per second")
+                .help("Messages send per second")
                 .required(true)
                 .takes_value(true))
             .arg(Arg::new("size")
                 .long("size")
                 .value_name("size")
-                .about("Payload size of messages [bytes (min. 16)]")
+                .help("Payload size of messages [bytes (min. 44)]")
                 .required(true)
                 .takes_value(true))
             .arg(Arg::new("protocol")
                 .long("protocol")
                 .value_name("Protocol")
-                .about("Protocol used to communicate with Rperf server [tcp|udp]")
+                .help("Protocol used to communicate with Rperf server [tcp|udp]")
                 .required(true)
                 .takes_value(true))
             .arg(Arg::new("log")
                 .long("log")
                 .value_name("Log")
-                .about("Path to log test results")
+                .help("Path to log test results")
                 .required(false)
                 .takes_value(true))
             .arg(Arg::new("warmup")
                 .long("warmup")
                 .value_name("Warmup")
-                .about("Warm-up time before test")
+                .help("Warm-up time before test")
                 .required(false)
                 .takes_value(true))
             .arg(Arg::new("rtt")
                 .long("rtt")
                 .value_name("rtt")
-                .about("Output result as round trip time instead of latency (latency = round trip time /2 if --owl flag is not used)")
+                .help("Output result as round trip time instead of latency (latency = round trip time /2 if --owl flag is not used)")
                 .required(false)
                 .takes_value(false))
             .arg(Arg::new("owl")
                 .long("owl")
                 .value_name("owl")
-                .about("Measure one way latencies using timestamps of system clocks (client and server clock needs to be synchronized!)")
+                .help("Measure one way latencies using timestamps of system clocks (client and server clock needs to be synchronized!)")
                 .required(false)
                 .takes_value(false))
+            .arg(Arg::new("histogram")
+                .long("histogram")
+                .value_name("digits")
+                .help("Record latencies only in histograms with the given number of significant digits [0-5] instead of per packet (for long tests)")
+                .required(false)
+                .takes_value(true))
+            .arg(Arg::new("histogram-log")
+                .long("histogram-log")
+                .value_name("histogram-log")
+                .help("Path to export latency histograms as HdrHistogram log")
+                .required(false)
+                .takes_value(true))
+            .arg(Arg::new("json")
+                .long("json")
+                .value_name("json")
+                .help("Path to export test results as JSON (see docs/json-schema.md)")
+                .required(false)
+                .takes_value(true))
+            .arg(Arg::new("json-packets")
+                .long("json-packets")
+                .value_name("json-packets")
+                .help("Include per packet results in the JSON export")
+                .required(false)
+                .takes_value(false))
+            .arg(Arg::new("influxdb")
+                .long("influxdb")
+                .value_name("influxdb")
+                .help("Path to export test results in the InfluxDB line protocol")
+                .required(false)
+                .takes_value(true))
+            .arg(Arg::new("influxdb-url")
+                .long("influxdb-url")
+                .value_name("url")
+                .help("InfluxDB write endpoint test results are posted to in the line protocol, e.g. http://localhost:8086/api/v2/write?org=lab&bucket=rperf")
+                .required(false)
+                .takes_value(true))
+            .arg(Arg::new("influxdb-token")
+                .long("influxdb-token")
+                .value_name("token")
+                .help("API token sent with results posted to InfluxDB")
+                .required(false)
+                .takes_value(true))
+            .arg(Arg::new("influxdb-packets")
+                .long("influxdb-packets")
+                .value_name("influxdb-packets")
+                .help("Include a point per packet in the InfluxDB export")
+                .required(false)
+                .takes_value(false))
+            .arg(Arg::new("ndjson")
+                .long("ndjson")
+                .value_name("ndjson")
+                .help("Path to stream sent, received and lost packets and interval summaries as newline delimited JSON while the test is running")
+                .required(false)
+                .takes_value(true))
+            .arg(Arg::new("interval")
+                .long("interval")
+                .value_name("interval")
+                .help("Print a report of packets and latencies every interval while the test is running [seconds]")
+                .required(false)
+                .takes_value(true))
+            .arg(Arg::new("deadline")
+                .long("deadline")
+                .value_name("deadline")
+                .help("Latency budget every packet is evaluated against [ms], lost packets count as misses. Can be used multiple times.")
+                .required(false)
+                .takes_value(true)
+                .multiple_occurrences(true))
+            .arg(Arg::new("max-miss-ratio")
+                .long("max-miss-ratio")
+                .value_name("ratio")
+                .help("Maximum share of packets missing a deadline [0-1], the client exits with code 2 if it is exceeded")
+                .required(false)
+                .requires("deadline")
+                .takes_value(true))
+            .arg(Arg::new("metrics")
+                .long("metrics")
+                .value_name("address")
+                .help("Serve Prometheus metrics on the given address, e.g. 0.0.0.0:9100 (requires the prometheus feature)")
+                .required(false)
+                .takes_value(true))
+        )
+        .subcommand(App::new("report")
+            .about("Generate a HTML report with latency plots from a result exported with --json --json-packets")
+            .version("1.0.0")
+            .author("Matthias Schneider <matthias.schneider@ipa.fraunhofer.de")
+            .arg(Arg::new("result")
+                .value_name("result")
+                .help("Path to the JSON result")
+                .required(true)
+                .takes_value(true))
+            .arg(Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("output")
+                .help("Path of the HTML report [default: path of the result with extension .html]")
+                .required(false)
+                .takes_value(true))
+        )
+        .subcommand(App::new("compare")
+            .about("Compare results exported with --json against the first one, exits with code 2 on a latency or loss regression")
+            .version("1.0.0")
+            .author("Matthias Schneider <matthias.schneider@ipa.fraunhofer.de")
+            .arg(Arg::new("results")
+                .value_name("results")
+                .help("Paths to the JSON results, the first one is the baseline (with --json-packets for the significance test)")
+                .required(true)
+                .takes_value(true)
+                .min_values(2))
+            .arg(Arg::new("tolerance")
+                .long("tolerance")
+                .value_name("percent")
+                .help("Increase of median or 99th percentile latency or of the loss ratio over the baseline tolerated before a regression is reported [default: 10]")
+                .required(false)
+                .takes_value(true))
+            .arg(Arg::new("significance")
+                .long("significance")
+                .value_name("level")
+                .help("Significance level of the Mann-Whitney U test a regression must pass [default: 0.05]")
+                .required(false)
+                .takes_value(true))
         )
         .get_matches();
 
-    if let Some(ref matches) = matches.subcommand_matches("server") {
+    if let Some(matches) = matches.subcommand_matches("server") {
         println!("Server Mode");
 
         let protocol = matches.value_of("protocol").unwrap();
-        let port = matches.value_of_t("port").unwrap();
+        let port = matches.value_of_t("port").unwrap_or_else(|e| e.exit());
         let symmetric_network_load = matches.is_present("sym-load");
 
-        match rperf::start_server(port, protocol, symmetric_network_load).await {
-            Ok(_) => {
-                println!("Server successfully started")
+        let mut builder = ServerConfig::builder(port)
+            .protocol(protocol)
+            .symmetric_network_load(symmetric_network_load);
+        if matches.is_present("workers") {
+            builder = builder.udp_workers(matches.value_of_t("workers").unwrap_or_else(|e| e.exit()));
+        }
+        if matches.is_present("cpus") {
+            builder = builder.cpu_affinity(matches.values_of_t("cpus").unwrap_or_else(|e| e.exit()));
+        }
+        let config = builder
+            .build()
+            .unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            });
+
+        if let Some(metrics_address) = matches.value_of("metrics") {
+            serve_metrics(metrics_address);
+        }
+
+        let mut server = rperf::start_server(&config).await.unwrap_or_else(|e| {
+            eprintln!("Server start failed: {}", e);
+            process::exit(1);
+        });
+
+        // The server only terminates on its own on errors, otherwise it runs until SIGINT or SIGTERM
+        let result = tokio::select! {
+            result = server.wait() => result,
+            _ = shutdown_signal() => {
+                println!("Shutdown requested");
+                Ok(())
             }
+        };
+        if let Err(e) = result.and(server.shutdown().await) {
+            eprintln!("Server failed: {}", e);
+            process::exit(1);
+        }
+    }
+    else if let Some(matches) = matches.subcommand_matches("report") {
+        let result_path = matches.value_of("result").unwrap();
+        let output_path = match matches.value_of("output") {
+            Some(output_path) => output_path.to_string(),
+            None => std::path::Path::new(result_path).with_extension("html").to_string_lossy().into_owned()
+        };
+
+        match rperf::json_result::JsonResult::read_file(result_path).and_then(|json_result| rperf::html_report::write_file(&json_result, &output_path)) {
+            Ok(_) => println!("Report written to '{}'", output_path),
             Err(e) => {
-                eprintln!("Server start failed: {}", e)
+                eprintln!("Failed to generate report: {}", e);
+                process::exit(1);
             }
         }
     }
-    else if let Some(ref matches) = matches.subcommand_matches("client") {
+    else if let Some(matches) = matches.subcommand_matches("compare") {
+        let result_paths: Vec<&str> = matches.values_of("results").unwrap().collect();
+        let mut tolerance_percent = rperf::comparison::DEFAULT_TOLERANCE_PERCENT;
+        if matches.is_present("tolerance") {
+            tolerance_percent = matches.value_of_t("tolerance").unwrap_or_else(|e| e.exit());
+        }
+        let mut significance_level = rperf::comparison::DEFAULT_SIGNIFICANCE_LEVEL;
+        if matches.is_present("significance") {
+            significance_level = matches.value_of_t("significance").unwrap_or_else(|e| e.exit());
+        }
+
+        let comparison = rperf::comparison::Comparison::from_files(&result_paths).unwrap_or_else(|e| {
+            eprintln!("Failed to compare results: {}", e);
+            process::exit(1);
+        });
+        print!("{}", comparison);
+
+        let regressions = comparison.regressions(tolerance_percent, significance_level);
+        for regression in &regressions {
+            println!("Regression: {}", regression);
+        }
+        if !regressions.is_empty() {
+            process::exit(2);
+        }
+    }
+    else if let Some(matches) = matches.subcommand_matches("client") {
         println!("Client Mode");
 
         let ip = matches.value_of("ip").unwrap();
@@ -144,7 +354,8 @@ async fn main() {
         let time = matches.value_of_t("time").unwrap();
         let mps = matches.value_of_t("mps").unwrap();
         let size = matches.value_of_t("size").unwrap();
-        let log_path = matches.value_of("log").unwrap_or_default();
+        let log_path = matches.value_of("log");
+        let ndjson_path = matches.value_of("ndjson");
         let mut warmup_time = 0;
         if matches.is_present("warmup") {
             warmup_time = matches.value_of_t("warmup").unwrap();
@@ -153,34 +364,140 @@ async fn main() {
 
         let output_rtt = matches.is_present("rtt");
         let measure_owl = matches.is_present("owl");
+        let mut recording_mode = RecordingMode::PerPacket;
+        if matches.is_present("histogram") {
+            recording_mode = RecordingMode::Histogram { significant_digits: matches.value_of_t("histogram").unwrap_or_else(|e| e.exit()) };
+        }
+        let histogram_log_path = matches.value_of("histogram-log");
+        let json_path = matches.value_of("json");
+        let json_packets = matches.is_present("json-packets");
+        let influxdb_path = matches.value_of("influxdb");
+        let influxdb_url = matches.value_of("influxdb-url");
+        let influxdb_token = matches.value_of("influxdb-token");
+        let influxdb_packets = matches.is_present("influxdb-packets");
+
+        let mut builder = ClientConfig::builder(ip, port)
+            .protocol(protocol)
+            .test_duration(Duration::from_secs(time))
+            .packets_per_second(mps)
+            .packet_size(size)
+            .warmup_duration(Duration::from_secs(warmup_time))
+            .output_rtt(output_rtt)
+            .measure_owl(measure_owl)
+            .recording_mode(recording_mode);
+        if let Some(log_path) = log_path {
+            builder = builder.log_path(log_path);
+        }
+        if let Some(ndjson_path) = ndjson_path {
+            builder = builder.ndjson_path(ndjson_path);
+        }
+        if matches.is_present("deadline") {
+            let deadlines_millis: Vec<f64> = matches.values_of_t("deadline").unwrap_or_else(|e| e.exit());
+            for deadline in deadlines_millis {
+                builder = builder.deadline(duration_argument("deadline", deadline, Duration::from_millis(1)));
+            }
+        }
+        if matches.is_present("interval") {
+            let report_interval_seconds: f64 = matches.value_of_t("interval").unwrap_or_else(|e| e.exit());
+            builder = builder.report_interval(duration_argument("interval", report_interval_seconds, Duration::from_secs(1)));
+        }
+        if matches.is_present("max-miss-ratio") {
+            builder = builder.max_deadline_miss_ratio(matches.value_of_t("max-miss-ratio").unwrap_or_else(|e| e.exit()));
+        }
+        let config = builder.build().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
 
         if let Some(ip) = matches.value_of("ip") {
             println!("IP: {}", ip);
         }
 
-        if size < 16
-        {
-            eprintln!("Packet size must be at least 16 bytes!");
-            process::exit(1);
+        if let Some(metrics_address) = matches.value_of("metrics") {
+            serve_metrics(metrics_address);
         }
 
-        match rperf::start_test(
-            ip,
-            port,
-            protocol,
-            Duration::from_secs(time),
-            mps,
-            size,
-            Duration::from_secs(warmup_time),
-            log_path,
-            output_rtt,
-            measure_owl).await {
-            Ok(_) => {
-                println!("Test successfully")
+        match rperf::start_test(&config).await {
+            Ok(test_result) => {
+                if let Some(histogram_log_path) = histogram_log_path {
+                    match test_result.write_histogram_log(histogram_log_path) {
+                        Ok(_) => println!("Latency histograms logged in '{}'", histogram_log_path),
+                        Err(e) => eprintln!("Failed to log latency histograms: {}", e)
+                    }
+                }
+                if let Some(json_path) = json_path {
+                    match test_result.write_json(json_path, json_packets) {
+                        Ok(_) => println!("Test results exported as JSON in '{}'", json_path),
+                        Err(e) => eprintln!("Failed to export test results as JSON: {}", e)
+                    }
+                }
+                if let Some(influxdb_path) = influxdb_path {
+                    match test_result.write_line_protocol(influxdb_path, influxdb_packets) {
+                        Ok(_) => println!("Test results exported in InfluxDB line protocol in '{}'", influxdb_path),
+                        Err(e) => eprintln!("Failed to export test results in InfluxDB line protocol: {}", e)
+                    }
+                }
+                if let Some(influxdb_url) = influxdb_url {
+                    match test_result.post_line_protocol(influxdb_url, influxdb_token, influxdb_packets) {
+                        Ok(_) => println!("Test results posted to '{}'", influxdb_url),
+                        Err(e) => eprintln!("Failed to post test results to InfluxDB: {}", e)
+                    }
+                }
+                println!("Test successfully");
+                if test_result.deadline_miss_ratio_exceeded() {
+                    process::exit(2);
+                }
+            }
+            Err(e) => {
+                eprintln!("Test failed: {}", e);
+                process::exit(1);
             }
+        }
+    }
+}
+
+/// Converts an argument given in multiples of `unit` to a duration, exits if it is negative or not a number
+fn duration_argument(name: &str, value: f64, unit: Duration) -> Duration {
+    Duration::try_from_secs_f64(value * unit.as_secs_f64()).unwrap_or_else(|e| {
+        eprintln!("Invalid value '{}' for '--{}': {}", value, name, e);
+        process::exit(1);
+    })
+}
+
+#[cfg(feature = "prometheus")]
+fn serve_metrics(address: &str) {
+    if let Err(e) = rperf::metrics::serve(address) {
+        eprintln!("Failed to serve metrics on '{}': {}", address, e);
+        process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "prometheus"))]
+fn serve_metrics(_address: &str) {
+    eprintln!("Metrics are not available, rperf was built without the 'prometheus' feature");
+    process::exit(1);
+}
+
+/// Completes on the first SIGINT (Ctrl+C) or SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(terminate) => terminate,
             Err(e) => {
-                eprintln!("Test failed: {}", e)
+                eprintln!("Failed to listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                return;
             }
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {}
         }
     }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 }
@@ -0,0 +1,352 @@
+/*<copyright file="metrics.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+
+// Prometheus metrics of clients and servers in this process, served in the text exposition format by `serve`.
+// Clients and servers register their metrics in the global registry, so they are collected whether an
+// endpoint is running or not. The registry only holds weak references, metrics are removed once dropped.
+
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::packet::ReceivedPacket;
+
+/// Upper bounds of the latency histogram buckets in seconds
+pub const LATENCY_BUCKETS: [f64; 14] = [0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+type ServerCounter = fn(&ServerMetricsInner) -> &AtomicU64;
+
+const DIRECTIONS: [&str; 3] = ["round_trip", "client_to_server", "server_to_client"];
+
+/// Time a metrics connection may take to send its request or read the response
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Threads serving metrics requests, further connections wait in the listen backlog
+const REQUEST_THREADS: usize = 4;
+
+#[derive(Default)]
+pub struct MetricsRegistry {
+    clients: Mutex<Vec<Weak<ClientMetricsInner>>>,
+    servers: Mutex<Vec<Weak<ServerMetricsInner>>>
+}
+
+pub fn registry() -> &'static MetricsRegistry {
+    static REGISTRY: OnceLock<MetricsRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(MetricsRegistry::default)
+}
+
+impl MetricsRegistry {
+    pub fn client(&self, session_id: u32, server_address: &str, protocol: &str) -> ClientMetrics {
+        let metrics = ClientMetrics {
+            inner: Arc::new(ClientMetricsInner {
+                labels: format!("session_id=\"{}\",server=\"{}\",protocol=\"{}\"", session_id, escape_label_value(server_address), escape_label_value(protocol)),
+                sent_packets: AtomicU64::new(0),
+                state: Mutex::new(ClientMetricsState::default())
+            })
+        };
+        register(&self.clients, &metrics.inner);
+
+        metrics
+    }
+
+    pub fn server(&self, protocol: &str, port: u16) -> ServerMetrics {
+        let metrics = ServerMetrics {
+            inner: Arc::new(ServerMetricsInner {
+                labels: format!("protocol=\"{}\",port=\"{}\"", escape_label_value(protocol), port),
+                connections: AtomicU64::new(0),
+                active_connections: AtomicU64::new(0),
+                received_packets: AtomicU64::new(0),
+                sent_packets: AtomicU64::new(0),
+                rejected_packets: AtomicU64::new(0)
+            })
+        };
+        register(&self.servers, &metrics.inner);
+
+        metrics
+    }
+
+    /// All metrics in the Prometheus text exposition format (version 0.0.4)
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let clients = upgrade_registered(&self.clients);
+        let servers = upgrade_registered(&self.servers);
+
+        if !clients.is_empty() {
+            write_header(&mut output, "rperf_client_sent_packets_total", "counter", "Packets sent by the client");
+            for client in clients.iter() {
+                let _ = writeln!(output, "rperf_client_sent_packets_total{{{}}} {}", client.labels, client.sent_packets.load(Ordering::Relaxed));
+            }
+            let states: Vec<_> = clients.iter().map(|client| (&client.labels, client.state.lock().unwrap())).collect();
+            write_header(&mut output, "rperf_client_received_packets_total", "counter", "Pongs received by the client");
+            for (labels, state) in &states {
+                let _ = writeln!(output, "rperf_client_received_packets_total{{{}}} {}", labels, state.received_packets);
+            }
+            write_header(&mut output, "rperf_client_lost_packets_total", "counter", "Packets lost so far, estimated from gaps in the indices of the received pongs");
+            for (labels, state) in &states {
+                let _ = writeln!(output, "rperf_client_lost_packets_total{{{}}} {}", labels, state.lost_packets);
+            }
+            write_header(&mut output, "rperf_client_jitter_seconds", "gauge", "RFC 3550 interarrival jitter");
+            for (labels, state) in &states {
+                for (direction, jitter) in DIRECTIONS.iter().zip(&state.jitters) {
+                    if let Some(jitter) = jitter {
+                        let _ = writeln!(output, "rperf_client_jitter_seconds{{{},direction=\"{}\"}} {}", labels, direction, jitter.jitter_ns * 1e-9);
+                    }
+                }
+            }
+            write_header(&mut output, "rperf_client_latency_seconds", "histogram", "Round trip time and one way latencies of the received pongs");
+            for (labels, state) in &states {
+                for (direction, histogram) in DIRECTIONS.iter().zip(&state.histograms) {
+                    if histogram.count > 0 {
+                        histogram.render(&mut output, "rperf_client_latency_seconds", &format!("{},direction=\"{}\"", labels, direction));
+                    }
+                }
+            }
+        }
+
+        if !servers.is_empty() {
+            let counters: [(&str, &str, &str, ServerCounter); 5] = [
+                ("rperf_server_connections_total", "counter", "Accepted client connections", |server| &server.connections),
+                ("rperf_server_active_connections", "gauge", "Currently connected clients", |server| &server.active_connections),
+                ("rperf_server_received_packets_total", "counter", "Pings received by the server", |server| &server.received_packets),
+                ("rperf_server_sent_packets_total", "counter", "Pongs sent by the server", |server| &server.sent_packets),
+                ("rperf_server_rejected_packets_total", "counter", "Received packets that are no valid pings", |server| &server.rejected_packets)
+            ];
+            for (name, metric_type, help, counter) in counters.iter() {
+                write_header(&mut output, name, metric_type, help);
+                for server in servers.iter() {
+                    let _ = writeln!(output, "{}{{{}}} {}", name, server.labels, counter(server).load(Ordering::Relaxed));
+                }
+            }
+        }
+
+        output
+    }
+}
+
+/// Metrics of one client session
+#[derive(Clone)]
+pub struct ClientMetrics {
+    inner: Arc<ClientMetricsInner>
+}
+
+struct ClientMetricsInner {
+    labels: String,
+    sent_packets: AtomicU64,
+    state: Mutex<ClientMetricsState>
+}
+
+#[derive(Default)]
+struct ClientMetricsState {
+    received_packets: u64,
+    lost_packets: u64,
+    highest_index: Option<u64>,
+    histograms: [LatencyHistogram; 3],
+    jitters: [Option<Jitter>; 3]
+}
+
+struct Jitter {
+    previous_latency_ns: u64,
+    jitter_ns: f64
+}
+
+#[derive(Default)]
+struct LatencyHistogram {
+    bucket_counts: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum_seconds: f64
+}
+
+impl ClientMetrics {
+    pub fn packet_sent(&self) {
+        self.inner.sent_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn packet_received(&self, received_packet: &ReceivedPacket, measure_owl: bool) {
+        let round_trip_time_ns = received_packet.received_timestamp_ns.checked_sub(received_packet.sent_timestamp_ns);
+        let (client_to_server_ns, server_to_client_ns) = if measure_owl {
+            (received_packet.server_rx_timestamp_ns.checked_sub(received_packet.sent_timestamp_ns),
+             received_packet.received_timestamp_ns.checked_sub(received_packet.server_tx_timestamp_ns))
+        } else {
+            (None, None)
+        };
+
+        let mut state = self.inner.state.lock().unwrap();
+        state.received_packets += 1;
+        let highest_index = state.highest_index.map_or(received_packet.index, |highest_index| highest_index.max(received_packet.index));
+        state.highest_index = Some(highest_index);
+        // Late pongs lower the estimate, but the counter never decreases
+        state.lost_packets = state.lost_packets.max((highest_index + 1).saturating_sub(state.received_packets));
+
+        let ClientMetricsState { histograms, jitters, .. } = &mut *state;
+        for ((histogram, jitter), latency_ns) in histograms.iter_mut().zip(jitters.iter_mut()).zip([round_trip_time_ns, client_to_server_ns, server_to_client_ns].iter()) {
+            if let Some(latency_ns) = latency_ns {
+                histogram.observe(*latency_ns);
+                *jitter = Some(match jitter.take() {
+                    Some(Jitter { previous_latency_ns, jitter_ns }) => {
+                        let difference_ns = (*latency_ns as f64 - previous_latency_ns as f64).abs();
+                        Jitter { previous_latency_ns: *latency_ns, jitter_ns: jitter_ns + (difference_ns - jitter_ns) / 16_f64 }
+                    }
+                    None => Jitter { previous_latency_ns: *latency_ns, jitter_ns: 0_f64 }
+                });
+            }
+        }
+    }
+}
+
+impl LatencyHistogram {
+    fn observe(&mut self, latency_ns: u64) {
+        let latency_seconds = latency_ns as f64 * 1e-9;
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|upper_bound| latency_seconds <= *upper_bound) {
+            self.bucket_counts[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum_seconds += latency_seconds;
+    }
+
+    fn render(&self, output: &mut String, name: &str, labels: &str) {
+        let mut cumulative_count = 0;
+        for (upper_bound, count) in LATENCY_BUCKETS.iter().zip(self.bucket_counts.iter()) {
+            cumulative_count += count;
+            let _ = writeln!(output, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, upper_bound, cumulative_count);
+        }
+        let _ = writeln!(output, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, self.count);
+        let _ = writeln!(output, "{}_sum{{{}}} {}", name, labels, self.sum_seconds);
+        let _ = writeln!(output, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+/// Packet and connection counters of a server
+#[derive(Clone)]
+pub struct ServerMetrics {
+    inner: Arc<ServerMetricsInner>
+}
+
+struct ServerMetricsInner {
+    labels: String,
+    connections: AtomicU64,
+    active_connections: AtomicU64,
+    received_packets: AtomicU64,
+    sent_packets: AtomicU64,
+    rejected_packets: AtomicU64
+}
+
+impl ServerMetrics {
+    /// Counts a new connection, which stays active until the returned guard is dropped
+    pub fn connection_opened(&self) -> ConnectionGuard {
+        self.inner.connections.fetch_add(1, Ordering::Relaxed);
+        self.inner.active_connections.fetch_add(1, Ordering::Relaxed);
+
+        ConnectionGuard {
+            metrics: self.clone()
+        }
+    }
+
+    pub fn packet_received(&self) {
+        self.inner.received_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn packet_sent(&self) {
+        self.inner.sent_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn packet_rejected(&self) {
+        self.inner.rejected_packets.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub struct ConnectionGuard {
+    metrics: ServerMetrics
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.metrics.inner.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Serves the metrics of the global registry on `GET /metrics` in background threads and returns the bound address.
+/// Each of the `REQUEST_THREADS` threads accepts and handles one connection at a time, so a stalled scraper only blocks
+/// its thread for up to `REQUEST_TIMEOUT` and the number of threads stays bounded.
+pub fn serve(address: &str) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(address)?;
+    let local_address = listener.local_addr()?;
+    println!("Serving Prometheus metrics on 'http://{}/metrics'", local_address);
+
+    for _ in 0..REQUEST_THREADS {
+        let listener = listener.try_clone()?;
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = handle_request(stream) {
+                            eprintln!("Failed to serve metrics: {}", e);
+                        }
+                    }
+                    Err(e) => eprintln!("Failed to accept metrics connection: {}", e)
+                }
+            }
+        });
+    }
+
+    Ok(local_address)
+}
+
+fn handle_request(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request_line = String::new();
+    // Bounds the memory of a request line without a line break
+    BufReader::new(&stream).take(8192).read_line(&mut request_line)?;
+
+    let response = match request_line.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["GET", "/metrics", ..] => {
+            let body = registry().render();
+            format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+/// Adds `metrics` to `registered`, removing the dropped metrics so the registry does not grow with every test
+fn register<T>(registered: &Mutex<Vec<Weak<T>>>, metrics: &Arc<T>) {
+    let mut registered = registered.lock().unwrap();
+    registered.retain(|metrics| metrics.strong_count() > 0);
+    registered.push(Arc::downgrade(metrics));
+}
+
+/// Removes the dropped metrics from `registered` and returns the others
+fn upgrade_registered<T>(registered: &Mutex<Vec<Weak<T>>>) -> Vec<Arc<T>> {
+    let mut registered = registered.lock().unwrap();
+    registered.retain(|metrics| metrics.strong_count() > 0);
+
+    registered.iter().filter_map(Weak::upgrade).collect()
+}
+
+fn write_header(output: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, metric_type);
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
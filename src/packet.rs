@@ -14,18 +14,189 @@ See the License for the specific language governing permissions and
 limitations under the License.
 </copyright>*/
 
-use std::time::Duration;
+use std::convert::TryInto;
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Magic number at the start of every rperf packet ("RPRF")
+pub const PACKET_MAGIC: u32 = 0x5250_5246;
+/// Version of the wire format described by `PacketHeader`
+pub const PROTOCOL_VERSION: u8 = 2;
+/// Encoded size of `PacketHeader` and therefore the minimal packet size
+pub const HEADER_LENGTH: usize = 44;
+
+/// Packet is a reply of the server
+pub const FLAG_PONG: u8 = 0b0000_0001;
+/// Packet binds the address of a UDP client to the session it opened on the control connection
+pub const FLAG_PROBE: u8 = 0b0000_0010;
+
+// Durations are measured from the start of the test on the monotonic clock of the client,
+// timestamps are nanoseconds since the unix epoch. The client takes its timestamps from a `TestClock`,
+// the server from the system clock.
 
 pub struct SentPacket {
     pub index: u64,
     pub sent_duration: Duration,
-    pub sent_timestamp: Duration,
+    pub sent_timestamp_ns: u64,
     pub is_warmup: bool
 }
 
 pub struct ReceivedPacket {
     pub index: u64,
     pub received_duration: Duration,
-    pub received_timestamp: Duration,
-    pub server_timestamp: Duration,
+    pub sent_timestamp_ns: u64,
+    pub received_timestamp_ns: u64,
+    pub server_rx_timestamp_ns: u64,
+    pub server_tx_timestamp_ns: u64,
+}
+
+/// Header at the start of every ping and pong packet. All fields are encoded big-endian:
+///
+/// | Offset | Size | Field               |
+/// |--------|------|---------------------|
+/// | 0      | 4    | magic               |
+/// | 4      | 1    | protocol version    |
+/// | 5      | 1    | flags               |
+/// | 6      | 2    | reserved (zero)     |
+/// | 8      | 4    | session id          |
+/// | 12     | 8    | sequence number     |
+/// | 20     | 8    | client timestamp ns |
+/// | 28     | 8    | server rx timestamp |
+/// | 36     | 8    | server tx timestamp |
+///
+/// Timestamps are nanoseconds since the unix epoch, the remainder of a packet is padding. The server
+/// stamps the time it received the ping and the time it sends the pong, so its residence time can be
+/// separated from the network latency.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PacketHeader {
+    pub version: u8,
+    pub flags: u8,
+    pub session_id: u32,
+    pub sequence: u64,
+    pub client_timestamp_ns: u64,
+    pub server_rx_timestamp_ns: u64,
+    pub server_tx_timestamp_ns: u64
+}
+
+impl PacketHeader {
+    pub fn new(session_id: u32, sequence: u64, flags: u8) -> PacketHeader {
+        PacketHeader {
+            version: PROTOCOL_VERSION,
+            flags,
+            session_id,
+            sequence,
+            client_timestamp_ns: 0,
+            server_rx_timestamp_ns: 0,
+            server_tx_timestamp_ns: 0
+        }
+    }
+
+    pub fn is_pong(&self) -> bool {
+        self.flags & FLAG_PONG != 0
+    }
+
+    pub fn is_probe(&self) -> bool {
+        self.flags & FLAG_PROBE != 0
+    }
+
+    /// Creates the reply to this packet, keeping session, sequence and client timestamp
+    pub fn to_pong(self, server_rx_timestamp_ns: u64, server_tx_timestamp_ns: u64) -> PacketHeader {
+        PacketHeader {
+            flags: self.flags | FLAG_PONG,
+            server_rx_timestamp_ns,
+            server_tx_timestamp_ns,
+            ..self
+        }
+    }
+
+    pub fn encode(&self, buf: &mut [u8]) -> std::io::Result<()> {
+        if buf.len() < HEADER_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Buffer of {} bytes too small for packet header ({} bytes)", buf.len(), HEADER_LENGTH)));
+        }
+
+        buf[0..4].copy_from_slice(&PACKET_MAGIC.to_be_bytes());
+        buf[4] = self.version;
+        buf[5] = self.flags;
+        buf[6..8].copy_from_slice(&[0u8; 2]);
+        buf[8..12].copy_from_slice(&self.session_id.to_be_bytes());
+        buf[12..20].copy_from_slice(&self.sequence.to_be_bytes());
+        buf[20..28].copy_from_slice(&self.client_timestamp_ns.to_be_bytes());
+        buf[28..36].copy_from_slice(&self.server_rx_timestamp_ns.to_be_bytes());
+        buf[36..44].copy_from_slice(&self.server_tx_timestamp_ns.to_be_bytes());
+
+        Ok(())
+    }
+
+    pub fn decode(buf: &[u8]) -> std::io::Result<PacketHeader> {
+        if buf.len() < HEADER_LENGTH {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Packet of {} bytes is shorter than header ({} bytes)", buf.len(), HEADER_LENGTH)));
+        }
+
+        let magic = u32::from_be_bytes(buf[0..4].try_into().unwrap());
+        if magic != PACKET_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Invalid packet magic {:#010x}", magic)));
+        }
+
+        let version = buf[4];
+        if version != PROTOCOL_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported protocol version {} (expected {})", version, PROTOCOL_VERSION)));
+        }
+
+        Ok(PacketHeader {
+            version,
+            flags: buf[5],
+            session_id: u32::from_be_bytes(buf[8..12].try_into().unwrap()),
+            sequence: u64::from_be_bytes(buf[12..20].try_into().unwrap()),
+            client_timestamp_ns: u64::from_be_bytes(buf[20..28].try_into().unwrap()),
+            server_rx_timestamp_ns: u64::from_be_bytes(buf[28..36].try_into().unwrap()),
+            server_tx_timestamp_ns: u64::from_be_bytes(buf[36..44].try_into().unwrap())
+        })
+    }
+}
+
+/// Encodes the header into a packet of `packet_size` bytes (at least `HEADER_LENGTH`)
+pub fn encode_packet(header: &PacketHeader, packet_size: usize) -> Vec<u8> {
+    let mut payload = vec![1u8; packet_size.max(HEADER_LENGTH)];
+    header.encode(&mut payload).unwrap();
+
+    payload
+}
+
+/// Monotonic clock of a client test. Timestamps are the durations since the start anchored to the system clock
+/// once, so round trip times computed from the timestamps of the client are not changed by steps of the system clock.
+pub struct TestClock {
+    start: Instant,
+    start_timestamp_ns: u64
+}
+
+impl TestClock {
+    pub fn new() -> TestClock {
+        TestClock {
+            start: Instant::now(),
+            start_timestamp_ns: system_time_ns()
+        }
+    }
+
+    pub fn start(&self) -> Instant {
+        self.start
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Timestamp of the time `duration` after the start of the test
+    pub fn timestamp_ns(&self, duration: Duration) -> u64 {
+        self.start_timestamp_ns + duration.as_nanos() as u64
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> TestClock {
+        TestClock::new()
+    }
+}
+
+pub fn system_time_ns() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64
 }
@@ -0,0 +1,120 @@
+/*<copyright file="packet_csv.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+// Per packet results as CSV. The first line is a comment with the schema version, followed by optional comment lines,
+// the header and one row per sent packet in the order of the indices. Lost packets only have the index and their flags. Changed or
+// removed columns increase the schema version.
+
+use std::fs::File;
+use std::io::Write;
+
+use csv::Writer;
+
+use crate::packet_result::{PacketResult, nanos_to_millis};
+use crate::statistics::ipdv_ns;
+use crate::test_result::TestResult;
+
+/// Version 1 is the unversioned CSV of earlier releases
+pub const CSV_SCHEMA_VERSION: u32 = 2;
+
+pub const CSV_COLUMNS: [&str; 18] = ["Packet", "Warmup", "Lost", "Duplicates",
+    "SentTimestamp[ns]", "ReceivedTimestamp[ns]", "ServerRxTimestamp[ns]", "ServerTxTimestamp[ns]", "SentTime[s]", "ReceivedTime[s]",
+    "RoundTripTime[ms]", "ServerProcessingTime[ms]", "Latency[ms]", "OneWayLatency_ClientToServer[ms]", "OneWayLatency_ServerToClient[ms]",
+    "Ipdv_RoundTripTime[ms]", "Ipdv_ClientToServer[ms]", "Ipdv_ServerToClient[ms]"];
+
+/// Writes the per packet results of the test including the warm-up, not available in histogram mode
+pub fn write<W: Write>(test_result: &TestResult, writer: W) -> std::io::Result<()> {
+    write_with_comments(test_result, &[], writer)
+}
+
+/// Writes the per packet results with `comments` as comment lines between the schema version and the header
+pub fn write_with_comments<W: Write>(test_result: &TestResult, comments: &[String], mut writer: W) -> std::io::Result<()> {
+    writeln!(writer, "# rperf packet results, CSV schema version {}", CSV_SCHEMA_VERSION)?;
+    for comment in comments {
+        writeln!(writer, "# {}", comment)?;
+    }
+
+    let mut csv_writer = Writer::from_writer(writer);
+    csv_writer.write_record(CSV_COLUMNS)?;
+    write_rows(&mut csv_writer, test_result.warmup_packet_results.iter(), &test_result.lost_warmup_packet_indices, true)?;
+    write_rows(&mut csv_writer, test_result.packet_results.iter(), &test_result.lost_packet_indices, false)?;
+    csv_writer.flush()
+}
+
+pub fn write_file(test_result: &TestResult, path: &str) -> std::io::Result<()> {
+    write(test_result, File::create(path)?)
+}
+
+/// Merges received and lost packets, both ordered by index
+fn write_rows<'a, W: Write, I: Iterator<Item = &'a PacketResult>>(csv_writer: &mut Writer<W>, packet_results: I, lost_packet_indices: &[u64], warmup: bool) -> std::io::Result<()> {
+    let mut previous_packet_result: Option<&PacketResult> = None;
+    let mut lost_packet_indices = lost_packet_indices.iter().peekable();
+    for packet_result in packet_results {
+        while let Some(lost_packet_index) = lost_packet_indices.next_if(|lost_packet_index| **lost_packet_index < packet_result.index) {
+            csv_writer.write_record(lost_row(*lost_packet_index, warmup))?;
+        }
+        // IPDV is only defined for packets with consecutive indices
+        let previous = previous_packet_result.filter(|previous| previous.index + 1 == packet_result.index);
+        csv_writer.write_record(received_row(packet_result, previous, warmup))?;
+        previous_packet_result = Some(packet_result);
+    }
+    for lost_packet_index in lost_packet_indices {
+        csv_writer.write_record(lost_row(*lost_packet_index, warmup))?;
+    }
+
+    Ok(())
+}
+
+fn received_row(packet_result: &PacketResult, previous: Option<&PacketResult>, warmup: bool) -> Vec<String> {
+    let format_millis = |nanos: Option<u64>| nanos.map_or_else(String::new, |nanos| format!("{:.6}", nanos_to_millis(nanos)));
+    let format_ipdv = |previous_delay_ns: Option<u64>, delay_ns: Option<u64>| match (previous_delay_ns, delay_ns) {
+        (Some(previous_delay_ns), Some(delay_ns)) => format!("{:.6}", ipdv_ns(previous_delay_ns, delay_ns) as f64 * 1e-6),
+        _ => String::new()
+    };
+
+    vec![packet_result.index.to_string(),
+         flag(warmup),
+         flag(false),
+         packet_result.duplicates_count.to_string(),
+         packet_result.sent_timestamp_ns.to_string(),
+         packet_result.received_timestamp_ns.to_string(),
+         packet_result.server_rx_timestamp_ns.to_string(),
+         packet_result.server_tx_timestamp_ns.to_string(),
+         format!("{:.9}", packet_result.sent_time_ns as f64 * 1e-9),
+         format!("{:.9}", packet_result.received_time_ns as f64 * 1e-9),
+         format_millis(Some(packet_result.round_trip_time_ns)),
+         format_millis(packet_result.server_processing_time_ns),
+         format_millis(Some(packet_result.latency_ns)),
+         format_millis(packet_result.latency_client_to_server_ns),
+         format_millis(packet_result.latency_server_to_client_ns),
+         format_ipdv(previous.map(|previous| previous.round_trip_time_ns), Some(packet_result.round_trip_time_ns)),
+         format_ipdv(previous.and_then(|previous| previous.latency_client_to_server_ns), packet_result.latency_client_to_server_ns),
+         format_ipdv(previous.and_then(|previous| previous.latency_server_to_client_ns), packet_result.latency_server_to_client_ns)]
+}
+
+fn lost_row(index: u64, warmup: bool) -> Vec<String> {
+    let mut row = vec![String::new(); CSV_COLUMNS.len()];
+    row[0] = index.to_string();
+    row[1] = flag(warmup);
+    row[2] = flag(true);
+    row[3] = 0.to_string();
+
+    row
+}
+
+fn flag(value: bool) -> String {
+    if value { "1" } else { "0" }.to_string()
+}
@@ -14,12 +14,51 @@ See the License for the specific language governing permissions and
 limitations under the License.
 </copyright>*/
 
-#[derive(Copy, Clone)]
+use serde::{Deserialize, Serialize};
+
+/// Result of a received packet. Times are measured from the start of the test on the monotonic clock of the
+/// client, timestamps are nanoseconds since the unix epoch.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct PacketResult {
     pub index: u64,
-    pub tx_time: f64,
-    pub rx_time: f64,
-    pub latency: f64,
-    pub latency_client_to_server: f64,
-    pub latency_server_to_client: f64
+    pub sent_time_ns: u64,
+    pub received_time_ns: u64,
+    pub sent_timestamp_ns: u64,
+    pub received_timestamp_ns: u64,
+    pub server_rx_timestamp_ns: u64,
+    pub server_tx_timestamp_ns: u64,
+    pub round_trip_time_ns: u64,
+    pub server_processing_time_ns: Option<u64>,
+    pub latency_ns: u64,
+    pub latency_client_to_server_ns: Option<u64>,
+    pub latency_server_to_client_ns: Option<u64>,
+    /// Further arrivals of the packet after the first one
+    pub duplicates_count: u64
+}
+
+impl PacketResult {
+    pub fn latency_millis(&self) -> f64 {
+        nanos_to_millis(self.latency_ns)
+    }
+
+    /// Round trip time without the time the packet spent in the server
+    pub fn network_round_trip_time_ns(&self) -> u64 {
+        self.round_trip_time_ns.saturating_sub(self.server_processing_time_ns.unwrap_or(0))
+    }
+
+    pub fn server_processing_time_millis(&self) -> Option<f64> {
+        self.server_processing_time_ns.map(nanos_to_millis)
+    }
+
+    pub fn latency_client_to_server_millis(&self) -> Option<f64> {
+        self.latency_client_to_server_ns.map(nanos_to_millis)
+    }
+
+    pub fn latency_server_to_client_millis(&self) -> Option<f64> {
+        self.latency_server_to_client_ns.map(nanos_to_millis)
+    }
+}
+
+pub fn nanos_to_millis(nanos: u64) -> f64 {
+    nanos as f64 * 1e-6
 }
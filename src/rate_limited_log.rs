@@ -0,0 +1,57 @@
+/*<copyright file="rate_limited_log.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+// Logging of errors that peers can trigger for every packet, e.g. with stray or spoofed datagrams, so that they
+// cannot flood the log.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Minimal time between two messages of a log
+pub const LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Prints at most one message per `LOG_INTERVAL`, the suppressed messages are counted in the next one
+pub struct RateLimitedLog {
+    description: &'static str,
+    last_message: Option<Instant>,
+    suppressed_count: u64
+}
+
+impl RateLimitedLog {
+    /// `description` names the suppressed messages, e.g. "packets rejected"
+    pub fn new(description: &'static str) -> RateLimitedLog {
+        RateLimitedLog {
+            description,
+            last_message: None,
+            suppressed_count: 0
+        }
+    }
+
+    pub fn log(&mut self, message: fmt::Arguments) {
+        if self.last_message.is_some_and(|last_message| last_message.elapsed() < LOG_INTERVAL) {
+            self.suppressed_count += 1;
+            return;
+        }
+
+        if self.suppressed_count > 0 {
+            eprintln!("{} ({} more {} since the last message)", message, self.suppressed_count, self.description);
+        } else {
+            eprintln!("{}", message);
+        }
+        self.last_message = Some(Instant::now());
+        self.suppressed_count = 0;
+    }
+}
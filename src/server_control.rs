@@ -0,0 +1,253 @@
+/*<copyright file="server_control.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+pub mod server {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::{mpsc, watch};
+    use crate::config::ServerConfig;
+    use crate::control::{ControlMessage, CAPABILITIES, CAPABILITY_SERVER_STATISTICS, CONTROL_PROTOCOL_VERSION, HANDSHAKE_TIMEOUT, read_message, write_message};
+    use crate::error::Result;
+    use crate::packet::HEADER_LENGTH;
+    use crate::server_handle::ServerHandle;
+    use crate::server_tcp::server::ServerTcp;
+    use crate::server_udp::server::ServerUdp;
+    use crate::session::SessionRegistry;
+
+    /// Interval in which control connections check whether their session is idle
+    const SESSION_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Accepts the control connections of clients on the TCP port of the server. Pings are answered by the UDP
+    /// workers or, for TCP, on separate measurement connections accepted on the same port.
+    pub struct ServerControl {
+        config: ServerConfig,
+        protocol: String,
+        sessions: SessionRegistry,
+        server_tcp: ServerTcp
+    }
+
+    impl ServerControl {
+        /// Binds the listener, starts the UDP workers of UDP servers on the same port and accepts clients in a
+        /// background task until the server is shut down
+        pub async fn start(config: ServerConfig) -> Result<ServerHandle> {
+            let server_address = format!("{}:{}", "0.0.0.0", config.port);
+            let listener = TcpListener::bind(server_address).await?;
+            let local_address = listener.local_addr()?;
+            let protocol = config.protocol.to_lowercase();
+            #[cfg(feature = "prometheus")]
+            let sessions = SessionRegistry::new(crate::metrics::registry().server(&protocol, local_address.port()));
+            #[cfg(not(feature = "prometheus"))]
+            let sessions = SessionRegistry::new();
+
+            let server_udp = if protocol == "udp" {
+                let mut udp_config = config.clone();
+                udp_config.port = local_address.port();
+                Some(ServerUdp::from_config(udp_config, sessions.clone()).start()?)
+            } else {
+                println!("Started TCP server on port '{}'", local_address.port());
+                None
+            };
+
+            let (handle, shutdown_requested, finished) = ServerHandle::new(local_address);
+            let server = Arc::new(ServerControl {
+                server_tcp: ServerTcp::from_config(config.clone(), #[cfg(feature = "prometheus")] sessions.metrics().clone()),
+                config,
+                protocol,
+                sessions
+            });
+            tokio::spawn(async move {
+                let _ = finished.send(server.serve(listener, server_udp, shutdown_requested).await);
+            });
+
+            Ok(handle)
+        }
+
+        async fn serve(self: Arc<Self>, listener: TcpListener, mut server_udp: Option<ServerHandle>, mut shutdown_requested: watch::Receiver<bool>) -> Result<()> {
+            // Every connection holds a sender, the receiver yields None once all connections are closed
+            let (connection_open, mut connections_closed) = mpsc::channel::<()>(1);
+            let (close_connections, connections_closing) = watch::channel(false);
+
+            loop {
+                let (socket, peer_address) = tokio::select! {
+                    accepted = listener.accept() => accepted?,
+                    _ = shutdown_requested.changed() => break,
+                    result = wait_for(&mut server_udp) => {
+                        result?;
+                        break;
+                    }
+                };
+                let server = self.clone();
+                let connection_open = connection_open.clone();
+                let connection_closing = connections_closing.clone();
+
+                tokio::spawn(async move {
+                    let _connection_open = connection_open;
+                    server.handle_connection(socket, peer_address, connection_closing).await;
+                });
+            }
+
+            // Stop accepting clients and give the connected ones time to finish their tests
+            drop(listener);
+            drop(connection_open);
+            println!("Shutting down server, draining active sessions");
+            if tokio::time::timeout(self.config.drain_timeout, connections_closed.recv()).await.is_err() {
+                println!("Closing sessions still active after {:?}", self.config.drain_timeout);
+                close_connections.send_replace(true);
+                connections_closed.recv().await;
+            }
+            if let Some(server_udp) = server_udp {
+                server_udp.shutdown().await?;
+            }
+            println!("Server stopped");
+
+            Ok(())
+        }
+
+        /// The first message tells control connections from measurement connections
+        async fn handle_connection(&self, mut socket: TcpStream, peer_address: SocketAddr, mut connection_closing: watch::Receiver<bool>) {
+            if let Err(e) = socket.set_nodelay(true) {
+                eprintln!("failed to configure socket of client '{}'; err = {:?}", peer_address, e);
+                return;
+            }
+
+            let message = tokio::select! {
+                message = tokio::time::timeout(HANDSHAKE_TIMEOUT, read_message(&mut socket)) => message,
+                _ = connection_closing.changed() => return
+            };
+            match message {
+                Ok(Ok(ControlMessage::Hello { version, session_id, protocol, packet_size, capabilities })) => {
+                    let rejection = if version != CONTROL_PROTOCOL_VERSION {
+                        Some(format!("Unsupported control protocol version {} (server uses {})", version, CONTROL_PROTOCOL_VERSION))
+                    } else if protocol.to_lowercase() != self.protocol {
+                        Some(format!("Server measures over {}, not {}", self.protocol, protocol))
+                    } else if packet_size < HEADER_LENGTH {
+                        Some(format!("Packet size {} is smaller than the packet header ({} bytes)", packet_size, HEADER_LENGTH))
+                    } else {
+                        None
+                    };
+                    match rejection {
+                        Some(reason) => self.reject(socket, peer_address, reason).await,
+                        None => self.serve_session(socket, peer_address, session_id, packet_size, capabilities, connection_closing).await
+                    }
+                }
+                Ok(Ok(ControlMessage::Measurement { session_id })) => {
+                    match self.sessions.get(session_id) {
+                        Some(session) if self.protocol == "tcp" && session.bind_peer(&peer_address) => {
+                            self.server_tcp.serve_connection(socket, peer_address, session, connection_closing).await
+                        }
+                        _ => eprintln!("Rejected measurement connection of client '{}': unknown session {}", peer_address, session_id)
+                    }
+                }
+                Ok(Ok(message)) => eprintln!("Rejected connection of client '{}': unexpected message {:?}", peer_address, message),
+                Ok(Err(e)) => eprintln!("Rejected connection of client '{}': {}", peer_address, e),
+                Err(_) => eprintln!("Rejected connection of client '{}': no message within {:?}", peer_address, HANDSHAKE_TIMEOUT)
+            }
+        }
+
+        async fn reject(&self, mut socket: TcpStream, peer_address: SocketAddr, reason: String) {
+            eprintln!("Rejected session of client '{}': {}", peer_address, reason);
+            let reject = ControlMessage::Reject {
+                version: CONTROL_PROTOCOL_VERSION,
+                reason
+            };
+            if let Err(e) = write_message(&mut socket, &reject).await {
+                eprintln!("failed to reject client '{}'; err = {:?}", peer_address, e);
+            }
+        }
+
+        /// Runs a session from its handshake until the client stops it, disconnects or times out
+        async fn serve_session(&self, mut socket: TcpStream, peer_address: SocketAddr, session_id: u32, packet_size: usize, capabilities: Vec<String>, mut connection_closing: watch::Receiver<bool>) {
+            let session = match self.sessions.open(session_id, peer_address.ip(), packet_size) {
+                Some(session) => session,
+                None => return self.reject(socket, peer_address, format!("Session {} already exists", session_id)).await
+            };
+            #[cfg(feature = "prometheus")]
+            let _connection = self.sessions.metrics().connection_opened();
+
+            let capabilities: Vec<String> = capabilities.into_iter().filter(|capability| CAPABILITIES.contains(&capability.as_str())).collect();
+            let accept = ControlMessage::Accept {
+                version: CONTROL_PROTOCOL_VERSION,
+                capabilities: capabilities.clone(),
+                symmetric_network_load: self.config.symmetric_network_load
+            };
+            if let Err(e) = write_message(&mut socket, &accept).await {
+                eprintln!("failed to accept session of client '{}'; err = {:?}", peer_address, e);
+                self.sessions.close(&session);
+                return;
+            }
+            println!("Client '{}' started session {} and wants to perform test with packet size '{} byte'", peer_address, session_id, packet_size);
+
+            // Messages are read in a separate task, as reading a frame must not be cancelled by the idle check
+            let (mut reader, mut writer) = socket.into_split();
+            let (message_sender, mut messages) = mpsc::channel(1);
+            let reader_task = tokio::spawn(async move {
+                loop {
+                    let message = read_message(&mut reader).await;
+                    let failed = message.is_err();
+                    if message_sender.send(message).await.is_err() || failed {
+                        break;
+                    }
+                }
+            });
+
+            let mut session_check = tokio::time::interval(SESSION_CHECK_INTERVAL);
+            let reason = loop {
+                tokio::select! {
+                    message = messages.recv() => match message {
+                        Some(Ok(ControlMessage::Start)) => session.start(),
+                        Some(Ok(ControlMessage::Stop)) => {
+                            if capabilities.iter().any(|capability| capability == CAPABILITY_SERVER_STATISTICS) {
+                                if let Err(e) = write_message(&mut writer, &ControlMessage::Statistics(session.statistics())).await {
+                                    eprintln!("failed to send statistics to client '{}'; err = {:?}", peer_address, e);
+                                }
+                            }
+                            break "ended";
+                        }
+                        Some(Ok(message)) => {
+                            eprintln!("Unexpected message from client '{}': {:?}", peer_address, message);
+                            break "aborted";
+                        }
+                        Some(Err(e)) if e.kind() != std::io::ErrorKind::UnexpectedEof => {
+                            eprintln!("failed to read from client '{}'; err = {:?}", peer_address, e);
+                            break "aborted";
+                        }
+                        _ => break "disconnected"
+                    },
+                    _ = session_check.tick() => {
+                        if session.idle_time() >= self.config.session_idle_timeout {
+                            break "timed out";
+                        }
+                    }
+                    _ = connection_closing.changed() => break "closed on shutdown"
+                }
+            };
+            reader_task.abort();
+            self.sessions.close(&session);
+            session.log_end(&peer_address, reason);
+        }
+    }
+
+    /// Waits until the UDP workers terminate, never for TCP servers
+    async fn wait_for(server_udp: &mut Option<ServerHandle>) -> Result<()> {
+        match server_udp {
+            Some(server_udp) => server_udp.wait().await,
+            None => std::future::pending().await
+        }
+    }
+}
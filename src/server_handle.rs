@@ -0,0 +1,135 @@
+/*<copyright file="server_handle.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+// Servers run in the background once started and are controlled through their handle. A shutdown stops accepting
+// new sessions and lets active ones finish, sessions still active after the drain timeout are closed.
+
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use tokio::sync::{oneshot, watch};
+
+use crate::error::{RperfError, Result};
+
+pub struct ServerHandle {
+    local_address: SocketAddr,
+    shutdown: watch::Sender<bool>,
+    finished: Option<oneshot::Receiver<Result<()>>>
+}
+
+impl ServerHandle {
+    /// Creates the handle of a server bound to `local_address`. The server watches the returned receiver for
+    /// shutdown requests and reports how it terminated on the returned sender.
+    pub(crate) fn new(local_address: SocketAddr) -> (ServerHandle, watch::Receiver<bool>, oneshot::Sender<Result<()>>) {
+        let (shutdown, shutdown_requested) = watch::channel(false);
+        let (finished_sender, finished) = oneshot::channel();
+        let handle = ServerHandle {
+            local_address,
+            shutdown,
+            finished: Some(finished)
+        };
+
+        (handle, shutdown_requested, finished_sender)
+    }
+
+    /// Runs every worker on its own thread named `<name>-worker-<index>`. The first failing worker stops the others
+    /// and its error is reported by `wait` right away, the server terminates once all workers have stopped.
+    pub fn spawn_workers<F>(local_address: SocketAddr, name: &str, workers: Vec<F>) -> Result<ServerHandle>
+        where F: FnOnce(StopSignal) -> Result<()> + Send + 'static {
+        let (handle, shutdown_requested, finished) = ServerHandle::new(local_address);
+        let stop = StopSignal {
+            shutdown_requested,
+            worker_failed: Arc::new(AtomicBool::new(false))
+        };
+
+        let worker_count = workers.len();
+        let (results, worker_results) = mpsc::channel();
+        for (index, worker) in workers.into_iter().enumerate() {
+            let stop = stop.clone();
+            let results = results.clone();
+            thread::Builder::new().name(format!("{}-worker-{}", name, index)).spawn(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| worker(stop)))
+                    .unwrap_or_else(|_| Err(RperfError::Io(std::io::Error::other(format!("Worker {} panicked", index)))));
+                let _ = results.send(result);
+            })?;
+        }
+
+        thread::Builder::new().name(format!("{}-server", name)).spawn(move || {
+            let mut finished = Some(finished);
+            for result in worker_results.iter().take(worker_count) {
+                if let Err(e) = result {
+                    stop.worker_failed.store(true, Ordering::Relaxed);
+                    match finished.take() {
+                        Some(finished) => {
+                            let _ = finished.send(Err(e));
+                        }
+                        None => eprintln!("Worker failed while the server was stopping: {}", e)
+                    }
+                }
+            }
+            if let Some(finished) = finished {
+                let _ = finished.send(Ok(()));
+            }
+        })?;
+
+        Ok(handle)
+    }
+
+    /// Address the server is bound to, e.g. to find the port of a server started on port 0
+    pub fn local_address(&self) -> SocketAddr {
+        self.local_address
+    }
+
+    /// Waits until the server terminates on its own, which only happens on errors
+    pub async fn wait(&mut self) -> Result<()> {
+        let result = match &mut self.finished {
+            Some(finished) => finished.await.unwrap_or_else(|_| Err(RperfError::Io(std::io::Error::other("Server terminated unexpectedly")))),
+            None => Ok(())
+        };
+        self.finished = None;
+
+        result
+    }
+
+    /// Stops accepting new sessions and waits until the active ones are drained
+    pub async fn shutdown(mut self) -> Result<()> {
+        self.shutdown.send_replace(true);
+        self.wait().await
+    }
+}
+
+impl Drop for ServerHandle {
+    /// Dropping the handle shuts the server down in the background
+    fn drop(&mut self) {
+        self.shutdown.send_replace(true);
+    }
+}
+
+/// Tells the workers of a server to stop, either because the server is shut down or another worker failed
+#[derive(Clone)]
+pub struct StopSignal {
+    shutdown_requested: watch::Receiver<bool>,
+    worker_failed: Arc<AtomicBool>
+}
+
+impl StopSignal {
+    pub fn is_set(&self) -> bool {
+        *self.shutdown_requested.borrow() || self.worker_failed.load(Ordering::Relaxed)
+    }
+}
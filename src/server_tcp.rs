@@ -15,115 +15,91 @@ limitations under the License.
 </copyright>*/
 
 pub mod server {
-    use std::{thread, time, io};
+    use std::io;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
 
-    use tokio::net::{TcpListener};
-    use tokio::io::{AsyncReadExt, AsyncWriteExt};
-    use crate::messages::InitMessage;
-    use std::str::from_utf8;
-    use std::time::{SystemTime, UNIX_EPOCH};
-    use async_std::{task};
+    use tokio::net::TcpStream;
+    use tokio::sync::watch;
+    use crate::framing::{read_frame_async, write_frame_async};
+    use crate::config::ServerConfig;
+    use crate::packet::{PacketHeader, HEADER_LENGTH, encode_packet, system_time_ns};
+    use crate::session::Session;
 
     pub struct ServerTcp {
-        run: bool
+        config: ServerConfig,
+        #[cfg(feature = "prometheus")]
+        metrics: crate::metrics::ServerMetrics
     }
 
     impl ServerTcp {
-        pub fn new() -> ServerTcp {
+        pub fn from_config(config: ServerConfig, #[cfg(feature = "prometheus")] metrics: crate::metrics::ServerMetrics) -> ServerTcp {
             ServerTcp {
-                run: true
+                config,
+                #[cfg(feature = "prometheus")]
+                metrics
             }
         }
 
-        pub async fn start(&self, port: u16, symmetric_network_load: bool) -> Result<(), Box<dyn std::error::Error>> {
-            // Open TCP socket
-            let server_address = format!("{}:{}", "0.0.0.0", port);
-            let listener = TcpListener::bind(server_address).await?;
-            println!("Started TCP server on port '{}'", port);
-
-            while self.run {
-                let (mut socket, _) = listener.accept().await?;
-
-                task::spawn(async move {
-                    // Configure stream
-                    socket.set_nodelay(true).unwrap();
-
-                    // Wait for init message from client
-                    let mut buf_init = [0; 1500];
-                    let client_init_message: InitMessage;
-                    loop {
-                        let n = match socket.read(&mut buf_init).await {
-                            Ok(n) if n == 0 => return,
-                            Ok(n) => n,
-                            Err(e) => {
-                                eprintln!("failed to read from socket; err = {:?}", e);
-                                return;
-                            }
-                        };
-                        let client_init_message_json = from_utf8(&buf_init[..n]).unwrap();
-                        let client_init_message_result = serde_json::from_str(client_init_message_json);
-
-                        client_init_message = match client_init_message_result {
-                            Ok(v) => v,
-                            Err(e) => {
-                                eprintln!("Problem deserializing init message from client '{}': {}", socket.peer_addr().unwrap(), e);
-                                drop(socket);
-                                return;
-                            }
-                        };
-                        break;
+        /// Answers the pings of a session on a measurement connection until the client disconnects, the session is
+        /// closed or the server closes all connections
+        pub async fn serve_connection(&self, mut socket: TcpStream, peer_address: SocketAddr, session: Arc<Session>, mut connection_closing: watch::Receiver<bool>) {
+            println!("Client '{}' connected to session {}", peer_address, session.session_id);
+
+            let mut buf = Vec::new();
+            loop {
+                // Wait for packets and ...
+                let received = tokio::select! {
+                    received = read_frame_async(&mut socket, &mut buf) => received,
+                    _ = connection_closing.changed() => {
+                        println!("Closed connection of client '{}'", peer_address);
+                        return;
                     }
-                    println!("Client '{}' connected and wants to perform test with packet size '{} byte'", socket.peer_addr().unwrap(), client_init_message.packet_size);
-                    thread::sleep(time::Duration::from_millis(100));
-                    socket.write("OK".as_bytes()).await.unwrap();
-                    socket.flush().await.unwrap();
-
-                    let mut buf = vec![0; client_init_message.packet_size];
-                    loop {
-                        // Wait for packets and ...
-                        let _n = match socket.read_exact(&mut buf).await {
-                            // socket closed
-                            Ok(n) if n == 0 => return,
-                            Ok(n) => n,
-                            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
-                                println!("Client '{}' disconnected", socket.peer_addr().unwrap());
-                                return;
-                            }
-                            Err(e) => {
-                                eprintln!("failed to read from socket; err = {:?}", e);
-                                return;
-                            }
-                        };
-
-                        // ... send new packet with index of received packet back
-                        let mut payload: Vec<u8>;
-                        if symmetric_network_load.clone()
-                        {
-                            payload = vec![1u8; client_init_message.packet_size];
-                        } else {
-                            payload = vec![1u8; 16];
-                        }
-                        payload[0..=7].copy_from_slice(&buf[0..=7]);
-                        let current_system_time_unix_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-                        let current_system_time_unix_epoch_ms = current_system_time_unix_epoch.as_secs() as f64
-                            + current_system_time_unix_epoch.subsec_nanos() as f64 * 1e-9;
-                        payload[8..=15].copy_from_slice(&current_system_time_unix_epoch_ms.to_be_bytes());
-
-                        if let Err(e) = socket.write_all(&payload).await {
-                            eprintln!("failed to write to socket; err = {:?}", e);
-                            return;
-                        }
+                };
+                let server_rx_timestamp_ns = match received {
+                    Ok(_) if session.is_closed() => {
+                        println!("Closed connection of client '{}', session {} has ended", peer_address, session.session_id);
+                        return;
                     }
-                });
+                    Ok(_) => {
+                        #[cfg(feature = "prometheus")]
+                        self.metrics.packet_received();
+                        system_time_ns()
+                    }
+                    // socket closed
+                    Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                        println!("Client '{}' disconnected", peer_address);
+                        return;
+                    }
+                    Err(e) => {
+                        eprintln!("failed to read from socket; err = {:?}", e);
+                        return;
+                    }
+                };
+
+                // ... send pong with session and sequence of received packet back
+                let header = match PacketHeader::decode(&buf) {
+                    Ok(header) => header,
+                    Err(e) => {
+                        eprintln!("Rejected packet from client '{}': {}", peer_address, e);
+                        #[cfg(feature = "prometheus")]
+                        self.metrics.packet_rejected();
+                        session.packet_rejected();
+                        return;
+                    }
+                };
+                session.packet_received();
+                let pong_size = if self.config.symmetric_network_load { buf.len() } else { HEADER_LENGTH };
+                let payload = encode_packet(&header.to_pong(server_rx_timestamp_ns, system_time_ns()), pong_size);
+
+                if let Err(e) = write_frame_async(&mut socket, &payload).await {
+                    eprintln!("failed to write to socket; err = {:?}", e);
+                    return;
+                }
+                session.packet_sent();
+                #[cfg(feature = "prometheus")]
+                self.metrics.packet_sent();
             }
-
-            Ok(())
-        }
-
-        #[allow(dead_code)]
-        pub fn stop(&mut self)
-        {
-            self.run = false;
         }
     }
 }
@@ -15,58 +15,204 @@ limitations under the License.
 </copyright>*/
 
 pub mod server {
-    use std::net::UdpSocket;
-
-
-    use std::time::{SystemTime, UNIX_EPOCH};
+    use std::collections::HashMap;
+    use std::net::{SocketAddr, UdpSocket};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use std::{io, thread};
+    use core_affinity::CoreId;
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    use crate::config::ServerConfig;
+    use crate::error::{RperfError, Result};
+    use crate::packet::{PacketHeader, HEADER_LENGTH, encode_packet, system_time_ns};
+    use crate::rate_limited_log::RateLimitedLog;
+    use crate::server_handle::{ServerHandle, StopSignal};
+    use crate::session::{Session, SessionRegistry};
+
+    /// Interval in which workers check for shutdown and forget the sessions closed on the control connection
+    const SESSION_CHECK_INTERVAL: Duration = Duration::from_millis(100);
 
     pub struct ServerUdp {
-        run: bool
+        config: ServerConfig,
+        sessions: SessionRegistry
     }
 
     impl ServerUdp {
-        pub fn new() -> ServerUdp {
+        pub fn from_config(config: ServerConfig, sessions: SessionRegistry) -> ServerUdp {
             ServerUdp {
-                run: true
+                config,
+                sessions
             }
         }
 
-        pub fn start(&self, port: u16, symmetric_network_load: bool) -> std::io::Result<()> {
-            // Open UDP socket
-            let server_address = format!("{}:{}", "0.0.0.0", port);
-            let socket = UdpSocket::bind(server_address)?;
-            println!("Started UDP server on port '{}'", port);
+        /// Binds the sockets and answers pings on worker threads until the server is shut down
+        pub fn start(self) -> Result<ServerHandle> {
+            let worker_count = match self.config.udp_workers {
+                0 => thread::available_parallelism().map(|cpus| cpus.get()).unwrap_or(1),
+                udp_workers => udp_workers
+            };
+            let worker_cores = self.worker_cores(worker_count)?;
+            let sockets = self.bind_sockets(worker_count)?;
+            let local_address = sockets[0].local_addr()?;
+            if worker_count > 1 {
+                println!("Started UDP server on port '{}' with {} workers", local_address.port(), worker_count);
+            }
+            else {
+                println!("Started UDP server on port '{}'", local_address.port());
+            }
 
-            // Wait for packets
-            while self.run {
-                let mut buf = [0u8; 1500];
-                let (_amt, src) = socket.recv_from(&mut buf)?;
-
-                let mut payload: Vec<u8>;
-                if symmetric_network_load && _amt >= 16
-                {
-                    payload = vec![1u8; _amt];
-                } else {
-                    payload = vec![1u8; 16];
+            let server = Arc::new(self);
+            let workers = sockets.into_iter().zip(worker_cores).enumerate().map(|(index, (socket, core))| {
+                let server = server.clone();
+                let name = if worker_count > 1 { format!("UDP worker {}", index) } else { "UDP server".to_string() };
+
+                move |stop: StopSignal| {
+                    if let Some(core) = core {
+                        if !core_affinity::set_for_current(core) {
+                            eprintln!("Failed to pin {} to CPU {}", name, core.id);
+                        }
+                    }
+                    server.serve(&name, socket, stop)
                 }
+            }).collect();
 
-                payload[0..=7].copy_from_slice(&buf[0..=7]);
-                let current_system_time_unix_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-                let current_system_time_unix_epoch_ms = current_system_time_unix_epoch.as_secs() as f64
-                    + current_system_time_unix_epoch.subsec_nanos() as f64 * 1e-9;
-                payload[8..=15].copy_from_slice(&current_system_time_unix_epoch_ms.to_be_bytes());
+            ServerHandle::spawn_workers(local_address, "rperf-udp", workers)
+        }
+
+        /// Binds a socket per worker, several workers share the port with SO_REUSEPORT
+        fn bind_sockets(&self, worker_count: usize) -> Result<Vec<UdpSocket>> {
+            let mut server_address: SocketAddr = ([0, 0, 0, 0], self.config.port).into();
+            let mut sockets = Vec::with_capacity(worker_count);
+            for _ in 0..worker_count {
+                let socket = Socket::new(Domain::for_address(server_address), Type::DGRAM, Some(Protocol::UDP))?;
+                if worker_count > 1 {
+                    #[cfg(unix)]
+                    socket.set_reuse_port(true)?;
+                    #[cfg(not(unix))]
+                    return Err(RperfError::InvalidConfig("Multiple UDP workers require SO_REUSEPORT, which is only available on unix".to_string()));
+                }
+                socket.bind(&server_address.into())?;
+                let socket: UdpSocket = socket.into();
 
-                socket.send_to(&payload, &src)?;
+                // Workers of a server started on port 0 share the port assigned to the first one
+                server_address = socket.local_addr()?;
+                sockets.push(socket);
             }
 
-            Ok(())
+            Ok(sockets)
         }
 
-        #[allow(dead_code)]
-        pub fn stop(&mut self)
-        {
-            self.run = false;
+        fn worker_cores(&self, worker_count: usize) -> Result<Vec<Option<CoreId>>> {
+            if self.config.cpu_affinity.is_empty() {
+                return Ok(vec![None; worker_count]);
+            }
+
+            let core_ids = core_affinity::get_core_ids().ok_or_else(|| RperfError::InvalidConfig("CPU affinity is not supported on this platform".to_string()))?;
+            let cores = self.config.cpu_affinity.iter().map(|cpu| {
+                core_ids.iter().find(|core_id| core_id.id == *cpu).copied()
+                    .ok_or_else(|| RperfError::InvalidConfig(format!("CPU {} is not available", cpu)))
+            }).collect::<Result<Vec<CoreId>>>()?;
+
+            Ok((0..worker_count).map(|index| Some(cores[index % cores.len()])).collect())
         }
 
+        /// Answers pings with a blocking socket, so the receive timestamp is taken as soon as the worker wakes up
+        fn serve(&self, name: &str, socket: UdpSocket, stop: StopSignal) -> Result<()> {
+            socket.set_read_timeout(Some(SESSION_CHECK_INTERVAL))?;
+            // Sessions this worker answers, by id with the address their probe came from
+            let mut sessions: HashMap<u32, (SocketAddr, Arc<Session>)> = HashMap::new();
+            let mut last_session_check = Instant::now();
+            let mut buf = [0u8; 1500];
+            // Pongs that could not be sent, e.g. because a client became unreachable
+            let mut failed_sends_count = 0_u64;
+            // Stray or spoofed traffic must not flood the log
+            let mut rejections_log = RateLimitedLog::new("packets rejected");
+            let mut failed_sends_log = RateLimitedLog::new("sends failed");
+
+            // Wait for packets
+            loop {
+                let received = socket.recv_from(&mut buf);
+                let server_rx_timestamp_ns = system_time_ns();
+
+                if last_session_check.elapsed() >= SESSION_CHECK_INTERVAL {
+                    last_session_check = Instant::now();
+                    if stop.is_set() {
+                        println!("{} stopped, {} pongs could not be sent", name, failed_sends_count);
+                        return Ok(());
+                    }
+                    sessions.retain(|_, (_, session)| !session.is_closed());
+                }
+                let (amt, src) = match received {
+                    Ok(received) => received,
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => continue,
+                    Err(e) => return Err(e.into())
+                };
+                #[cfg(feature = "prometheus")]
+                self.sessions.metrics().packet_received();
+
+                // Drop everything that is not a ping of a rperf client
+                let header = match PacketHeader::decode(&buf[..amt]) {
+                    Ok(header) if !header.is_pong() => header,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        rejections_log.log(format_args!("Rejected packet from '{}': {}", src, e));
+                        #[cfg(feature = "prometheus")]
+                        self.sessions.metrics().packet_rejected();
+                        if let Some((_, session)) = sessions.values().find(|(peer_address, _)| *peer_address == src) {
+                            session.packet_rejected();
+                        }
+                        continue;
+                    }
+                };
+
+                // The first probe binds a session opened on a control connection to the address of the client,
+                // repeated probes from that address are answered again
+                if header.is_probe() {
+                    match self.sessions.get(header.session_id) {
+                        Some(session) if session.bind_peer(&src) => {
+                            session.touch();
+                            sessions.insert(header.session_id, (src, session));
+                            let payload = encode_packet(&header.to_pong(server_rx_timestamp_ns, system_time_ns()), HEADER_LENGTH);
+                            if let Err(e) = socket.send_to(&payload, src) {
+                                failed_sends_log.log(format_args!("Failed to answer probe of '{}': {}", src, e));
+                                failed_sends_count += 1;
+                            }
+                        }
+                        _ => {
+                            rejections_log.log(format_args!("Rejected probe from '{}': unknown session {}", src, header.session_id));
+                            #[cfg(feature = "prometheus")]
+                            self.sessions.metrics().packet_rejected();
+                        }
+                    }
+                    continue;
+                }
+
+                let session = match sessions.get(&header.session_id) {
+                    Some((peer_address, session)) if *peer_address == src && !session.is_closed() => session,
+                    _ => {
+                        rejections_log.log(format_args!("Rejected packet from '{}': unknown session {}", src, header.session_id));
+                        #[cfg(feature = "prometheus")]
+                        self.sessions.metrics().packet_rejected();
+                        continue;
+                    }
+                };
+                session.packet_received();
+
+                // Pongs of symmetric load are never larger than the ping, so the server cannot amplify traffic
+                let pong_size = if self.config.symmetric_network_load { amt.min(session.packet_size) } else { HEADER_LENGTH };
+                let payload = encode_packet(&header.to_pong(server_rx_timestamp_ns, system_time_ns()), pong_size);
+
+                // A client that cannot be reached must not stop the worker for all other sessions
+                if let Err(e) = socket.send_to(&payload, src) {
+                    failed_sends_log.log(format_args!("Failed to send pong to '{}': {}", src, e));
+                    failed_sends_count += 1;
+                    continue;
+                }
+                session.packet_sent();
+                #[cfg(feature = "prometheus")]
+                self.sessions.metrics().packet_sent();
+            }
+        }
     }
 }
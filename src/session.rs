@@ -0,0 +1,162 @@
+/*<copyright file="session.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+// Sessions are opened on the control connection of a client. The UDP workers and TCP connections answering the
+// pings of a session count them in the shared session, which the control connection reports at the end of the test.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::control::ServerStatistics;
+
+pub struct Session {
+    pub session_id: u32,
+    /// Address of the control connection, pings of the session are only accepted from this IP
+    pub client_ip: IpAddr,
+    pub packet_size: usize,
+    /// Address the pings of the session come from, bound by the first probe or measurement connection
+    peer_address: Mutex<Option<SocketAddr>>,
+    opened: Instant,
+    started: Mutex<Option<Instant>>,
+    /// Time of the last message or packet of the client since `opened`
+    last_seen_ns: AtomicU64,
+    received_packets_count: AtomicU64,
+    sent_packets_count: AtomicU64,
+    rejected_packets_count: AtomicU64,
+    closed: AtomicBool
+}
+
+impl Session {
+    fn new(session_id: u32, client_ip: IpAddr, packet_size: usize) -> Session {
+        Session {
+            session_id,
+            client_ip,
+            packet_size,
+            peer_address: Mutex::new(None),
+            opened: Instant::now(),
+            started: Mutex::new(None),
+            last_seen_ns: AtomicU64::new(0),
+            received_packets_count: AtomicU64::new(0),
+            sent_packets_count: AtomicU64::new(0),
+            rejected_packets_count: AtomicU64::new(0),
+            closed: AtomicBool::new(false)
+        }
+    }
+
+    /// Binds the session to the first address of the client it sees pings from, the session cannot be moved to
+    /// another address afterwards. Returns whether packets from `address` belong to this session.
+    pub fn bind_peer(&self, address: &SocketAddr) -> bool {
+        if address.ip() != self.client_ip || self.is_closed() {
+            return false;
+        }
+
+        *self.peer_address.lock().unwrap().get_or_insert(*address) == *address
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    pub fn start(&self) {
+        self.touch();
+        self.started.lock().unwrap().get_or_insert_with(Instant::now);
+    }
+
+    pub fn touch(&self) {
+        self.last_seen_ns.store(self.opened.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn idle_time(&self) -> Duration {
+        self.opened.elapsed().saturating_sub(Duration::from_nanos(self.last_seen_ns.load(Ordering::Relaxed)))
+    }
+
+    pub fn packet_received(&self) {
+        self.touch();
+        self.received_packets_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn packet_sent(&self) {
+        self.sent_packets_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn packet_rejected(&self) {
+        self.rejected_packets_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn statistics(&self) -> ServerStatistics {
+        ServerStatistics {
+            received_packets_count: self.received_packets_count.load(Ordering::Relaxed),
+            sent_packets_count: self.sent_packets_count.load(Ordering::Relaxed),
+            rejected_packets_count: self.rejected_packets_count.load(Ordering::Relaxed),
+            duration_seconds: self.started.lock().unwrap().map(|started| started.elapsed().as_secs_f64()).unwrap_or_default()
+        }
+    }
+
+    pub fn log_end(&self, peer_address: &SocketAddr, reason: &str) {
+        let statistics = self.statistics();
+        println!("Session {} of client '{}' {} after {:.3} s: {} packets received, {} pongs sent, {} packets rejected",
+                 self.session_id, peer_address, reason, self.opened.elapsed().as_secs_f64(),
+                 statistics.received_packets_count, statistics.sent_packets_count, statistics.rejected_packets_count);
+    }
+}
+
+/// Sessions of a server by their id
+#[derive(Clone)]
+pub struct SessionRegistry {
+    sessions: Arc<Mutex<HashMap<u32, Arc<Session>>>>,
+    #[cfg(feature = "prometheus")]
+    metrics: crate::metrics::ServerMetrics
+}
+
+impl SessionRegistry {
+    pub fn new(#[cfg(feature = "prometheus")] metrics: crate::metrics::ServerMetrics) -> SessionRegistry {
+        SessionRegistry {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            #[cfg(feature = "prometheus")]
+            metrics
+        }
+    }
+
+    /// Opens a new session, `None` if a session with the id already exists
+    pub fn open(&self, session_id: u32, client_ip: IpAddr, packet_size: usize) -> Option<Arc<Session>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.contains_key(&session_id) {
+            return None;
+        }
+        let session = Arc::new(Session::new(session_id, client_ip, packet_size));
+        sessions.insert(session_id, session.clone());
+
+        Some(session)
+    }
+
+    pub fn get(&self, session_id: u32) -> Option<Arc<Session>> {
+        self.sessions.lock().unwrap().get(&session_id).cloned()
+    }
+
+    /// Removes the session, workers and connections stop answering its pings
+    pub fn close(&self, session: &Session) {
+        session.closed.store(true, Ordering::Relaxed);
+        self.sessions.lock().unwrap().remove(&session.session_id);
+    }
+
+    #[cfg(feature = "prometheus")]
+    pub fn metrics(&self) -> &crate::metrics::ServerMetrics {
+        &self.metrics
+    }
+}
@@ -0,0 +1,480 @@
+/*<copyright file="statistics.rs" company="Fraunhofer Institute for Manufacturing Engineering and Automation IPA">
+Copyright 2021 Fraunhofer Institute for Manufacturing Engineering and Automation IPA
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+</copyright>*/
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+use crate::packet_result::{PacketResult, nanos_to_millis};
+
+/// Summary of a latency distribution, all values in milliseconds.
+/// Percentiles use the nearest-rank method, so every value is an actually measured latency.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LatencyStatistics {
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub median: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub p99_9: f64,
+    pub p99_99: f64
+}
+
+impl LatencyStatistics {
+    pub fn from_nanos(latencies_ns: &[u64]) -> Option<LatencyStatistics> {
+        if latencies_ns.is_empty() {
+            return None;
+        }
+
+        let mut sorted = latencies_ns.to_vec();
+        sorted.sort_unstable();
+
+        let count = sorted.len() as f64;
+        let mean_ns = sorted.iter().map(|latency_ns| *latency_ns as f64).sum::<f64>() / count;
+        let variance_ns = sorted.iter().map(|latency_ns| (*latency_ns as f64 - mean_ns).powi(2)).sum::<f64>() / count;
+
+        Some(LatencyStatistics {
+            count: sorted.len() as u64,
+            min: nanos_to_millis(sorted[0]),
+            max: nanos_to_millis(sorted[sorted.len() - 1]),
+            mean: mean_ns * 1e-6,
+            std_dev: variance_ns.sqrt() * 1e-6,
+            median: nanos_to_millis(percentile(&sorted, 50_f64)),
+            p90: nanos_to_millis(percentile(&sorted, 90_f64)),
+            p99: nanos_to_millis(percentile(&sorted, 99_f64)),
+            p99_9: nanos_to_millis(percentile(&sorted, 99.9)),
+            p99_99: nanos_to_millis(percentile(&sorted, 99.99))
+        })
+    }
+}
+
+impl LatencyStatistics {
+    pub fn from_histogram(histogram: &Histogram<u64>) -> Option<LatencyStatistics> {
+        if histogram.is_empty() {
+            return None;
+        }
+
+        Some(LatencyStatistics {
+            count: histogram.len(),
+            min: nanos_to_millis(histogram.min()),
+            max: nanos_to_millis(histogram.max()),
+            mean: histogram.mean() * 1e-6,
+            std_dev: histogram.stdev() * 1e-6,
+            median: nanos_to_millis(histogram.value_at_quantile(0.5)),
+            p90: nanos_to_millis(histogram.value_at_quantile(0.9)),
+            p99: nanos_to_millis(histogram.value_at_quantile(0.99)),
+            p99_9: nanos_to_millis(histogram.value_at_quantile(0.999)),
+            p99_99: nanos_to_millis(histogram.value_at_quantile(0.9999))
+        })
+    }
+}
+
+impl fmt::Display for LatencyStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "min {:.6} | median {:.6} | mean {:.6} | max {:.6} | p90 {:.6} | p99 {:.6} | p99.9 {:.6} | p99.99 {:.6} | std dev {:.6}",
+               self.min, self.median, self.mean, self.max, self.p90, self.p99, self.p99_9, self.p99_99, self.std_dev)
+    }
+}
+
+/// Delay variation of a sequence of packets, all values in milliseconds.
+///
+/// `interarrival_jitter` is the smoothed jitter of RFC 3550 (section 6.4.1), updated in order of arrival.
+/// IPDV (RFC 5481) is the delay difference of packets with consecutive sequence numbers, pairs around
+/// a lost packet are skipped. PDV (RFC 5481) is the delay of each packet above the minimal delay.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JitterStatistics {
+    pub count: u64,
+    pub interarrival_jitter: f64,
+    pub ipdv_min: f64,
+    pub ipdv_max: f64,
+    pub ipdv_mean_absolute: f64,
+    pub pdv_mean: f64,
+    pub pdv_p99: f64,
+    pub pdv_max: f64
+}
+
+impl JitterStatistics {
+    /// `delay` returns the arrival time and the delay of a packet in nanoseconds, packets without a delay are ignored.
+    /// Expects the packet results ordered by index.
+    pub fn from_packet_results<'a, I, F>(packet_results: I, delay: F) -> Option<JitterStatistics>
+        where I: IntoIterator<Item = &'a PacketResult>, F: Fn(&PacketResult) -> Option<(u64, u64)> {
+        let delays: Vec<(u64, u64, u64)> = packet_results.into_iter()
+            .filter_map(|packet_result| delay(packet_result).map(|(arrival_ns, delay_ns)| (packet_result.index, arrival_ns, delay_ns)))
+            .collect();
+        if delays.len() < 2 {
+            return None;
+        }
+
+        let ipdvs_ns: Vec<i64> = delays.windows(2)
+            .filter(|pair| pair[1].0 == pair[0].0 + 1)
+            .map(|pair| ipdv_ns(pair[0].2, pair[1].2))
+            .collect();
+
+        let mut arrival_order = delays.clone();
+        arrival_order.sort_by_key(|(_, arrival_ns, _)| *arrival_ns);
+        let interarrival_jitter_ns = arrival_order.windows(2)
+            .fold(0_f64, |jitter, pair| jitter + ((ipdv_ns(pair[0].2, pair[1].2) as f64).abs() - jitter) / 16_f64);
+
+        let min_delay_ns = delays.iter().map(|(_, _, delay_ns)| *delay_ns).min().unwrap();
+        let mut pdvs_ns: Vec<u64> = delays.iter().map(|(_, _, delay_ns)| delay_ns - min_delay_ns).collect();
+        pdvs_ns.sort_unstable();
+
+        Some(JitterStatistics {
+            count: delays.len() as u64,
+            interarrival_jitter: interarrival_jitter_ns * 1e-6,
+            ipdv_min: ipdvs_ns.iter().min().map_or(0_f64, |ipdv_ns| *ipdv_ns as f64 * 1e-6),
+            ipdv_max: ipdvs_ns.iter().max().map_or(0_f64, |ipdv_ns| *ipdv_ns as f64 * 1e-6),
+            ipdv_mean_absolute: if ipdvs_ns.is_empty() { 0_f64 } else { ipdvs_ns.iter().map(|ipdv_ns| ipdv_ns.unsigned_abs() as f64).sum::<f64>() / ipdvs_ns.len() as f64 * 1e-6 },
+            pdv_mean: pdvs_ns.iter().map(|pdv_ns| *pdv_ns as f64).sum::<f64>() / pdvs_ns.len() as f64 * 1e-6,
+            pdv_p99: nanos_to_millis(percentile(&pdvs_ns, 99_f64)),
+            pdv_max: nanos_to_millis(pdvs_ns[pdvs_ns.len() - 1])
+        })
+    }
+}
+
+impl fmt::Display for JitterStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interarrival jitter {:.6} | ipdv min {:.6} | ipdv max {:.6} | ipdv mean abs {:.6} | pdv mean {:.6} | pdv p99 {:.6} | pdv max {:.6}",
+               self.interarrival_jitter, self.ipdv_min, self.ipdv_max, self.ipdv_mean_absolute, self.pdv_mean, self.pdv_p99, self.pdv_max)
+    }
+}
+
+/// Reordering of packets as defined in RFC 4737, derived from the indices in order of arrival.
+///
+/// A packet is reordered if its index is lower than the next expected index, i.e. a packet with a higher
+/// index arrived before. The reordering extent is the distance in arrivals to the earliest packet with a
+/// higher index, a packet is n-reordered if the n packets arriving immediately before all have higher indices.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReorderingStatistics {
+    pub received_packets_count: u64,
+    pub reordered_packets_count: u64,
+    pub max_reordering_extent: u64,
+    pub mean_reordering_extent: f64,
+    /// Number of n-reordered packets by n, a packet that is n-reordered is also counted for all smaller n
+    pub n_reordering: BTreeMap<u64, u64>
+}
+
+impl ReorderingStatistics {
+    /// Expects every index only once, duplicates have to be removed before
+    pub fn from_arrival_order(indices: &[u64]) -> ReorderingStatistics {
+        let mut next_expected_index = 0;
+        let mut reordered_packets_count = 0;
+        let mut reordering_extent_sum = 0;
+        let mut max_reordering_extent = 0;
+        let mut n_reordering = BTreeMap::new();
+        // Highest index arrived so far for each position, used to find the earliest packet with a higher index
+        let mut max_indices: Vec<u64> = Vec::with_capacity(indices.len());
+
+        for (position, index) in indices.iter().enumerate() {
+            if *index >= next_expected_index {
+                next_expected_index = index + 1;
+            } else {
+                reordered_packets_count += 1;
+
+                let earliest_position = max_indices.partition_point(|max_index| max_index <= index);
+                let reordering_extent = (position - earliest_position) as u64;
+                reordering_extent_sum += reordering_extent;
+                max_reordering_extent = max_reordering_extent.max(reordering_extent);
+
+                let n = indices[..position].iter().rev().take_while(|previous_index| *previous_index > index).count() as u64;
+                for degree in 1..=n {
+                    *n_reordering.entry(degree).or_insert(0) += 1;
+                }
+            }
+            max_indices.push(max_indices.last().map_or(*index, |max_index: &u64| *max_index.max(index)));
+        }
+
+        ReorderingStatistics {
+            received_packets_count: indices.len() as u64,
+            reordered_packets_count,
+            max_reordering_extent,
+            mean_reordering_extent: if reordered_packets_count == 0 { 0_f64 } else { reordering_extent_sum as f64 / reordered_packets_count as f64 },
+            n_reordering
+        }
+    }
+
+    /// Share of the received packets that arrived reordered
+    pub fn reordered_ratio(&self) -> f64 {
+        if self.received_packets_count == 0 {
+            return 0_f64;
+        }
+
+        self.reordered_packets_count as f64 / self.received_packets_count as f64
+    }
+}
+
+impl fmt::Display for ReorderingStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "reordered {} ({:.3} %) | extent max {} | extent mean {:.3}",
+               self.reordered_packets_count, self.reordered_ratio() * 100_f64, self.max_reordering_extent, self.mean_reordering_extent)?;
+        for (n, count) in self.n_reordering.iter().take(3) {
+            write!(f, " | {}-reordering {:.3} %", n, *count as f64 / self.received_packets_count as f64 * 100_f64)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Pattern of the lost packets in a test.
+///
+/// A burst is a run of consecutively lost packets, a gap is a run of received packets between two bursts.
+/// Losses are modeled as a two-state Gilbert-Elliott chain where every packet in the bad state is lost:
+/// `p` is the probability to go from received to lost, `r` the probability to go from lost to received.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LossStatistics {
+    pub packets_count: u64,
+    pub lost_packets_count: u64,
+    pub burst_count: u64,
+    pub longest_burst: u64,
+    pub mean_burst_length: f64,
+    /// Number of bursts by burst length
+    pub burst_lengths: BTreeMap<u64, u64>,
+    /// Number of times n consecutive packets were lost by n, a burst of length l counts l - n + 1 times for n
+    pub consecutive_losses: BTreeMap<u64, u64>,
+    pub gap_count: u64,
+    pub min_gap: u64,
+    pub max_gap: u64,
+    pub mean_gap: f64,
+    pub gilbert_elliott_p: f64,
+    pub gilbert_elliott_r: f64
+}
+
+impl LossStatistics {
+    /// `lost` contains for every sent packet in order of the indices whether it was lost
+    pub fn from_loss_sequence(lost: &[bool]) -> LossStatistics {
+        let mut bursts: Vec<u64> = Vec::new();
+        let mut gaps: Vec<u64> = Vec::new();
+        let mut run_length = 0;
+        for (position, packet_lost) in lost.iter().enumerate() {
+            run_length += 1;
+            let run_ends = lost.get(position + 1) != Some(packet_lost);
+            if run_ends {
+                if *packet_lost {
+                    bursts.push(run_length);
+                } else if !bursts.is_empty() && position + 1 < lost.len() {
+                    gaps.push(run_length);
+                }
+                run_length = 0;
+            }
+        }
+
+        let mut burst_lengths = BTreeMap::new();
+        let mut consecutive_losses = BTreeMap::new();
+        for burst in &bursts {
+            *burst_lengths.entry(*burst).or_insert(0) += 1;
+            for n in 1..=*burst {
+                *consecutive_losses.entry(n).or_insert(0) += burst - n + 1;
+            }
+        }
+
+        let transitions = |from: bool, to: bool| lost.windows(2).filter(|pair| pair[0] == from && pair[1] == to).count() as f64;
+        let transition_probability = |from: bool| {
+            let count = transitions(from, true) + transitions(from, false);
+            if count == 0_f64 { 0_f64 } else { transitions(from, !from) / count }
+        };
+
+        LossStatistics {
+            packets_count: lost.len() as u64,
+            lost_packets_count: bursts.iter().sum(),
+            burst_count: bursts.len() as u64,
+            longest_burst: bursts.iter().max().copied().unwrap_or(0),
+            mean_burst_length: mean(&bursts),
+            burst_lengths,
+            consecutive_losses,
+            gap_count: gaps.len() as u64,
+            min_gap: gaps.iter().min().copied().unwrap_or(0),
+            max_gap: gaps.iter().max().copied().unwrap_or(0),
+            mean_gap: mean(&gaps),
+            gilbert_elliott_p: transition_probability(false),
+            gilbert_elliott_r: transition_probability(true)
+        }
+    }
+}
+
+impl fmt::Display for LossStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bursts {} | longest burst {} | mean burst length {:.3} | gaps {} | gap min {} | gap mean {:.3} | gap max {} | gilbert-elliott p {:.6} | gilbert-elliott r {:.6}",
+               self.burst_count, self.longest_burst, self.mean_burst_length, self.gap_count, self.min_gap, self.mean_gap, self.max_gap, self.gilbert_elliott_p, self.gilbert_elliott_r)
+    }
+}
+
+fn mean(values: &[u64]) -> f64 {
+    if values.is_empty() {
+        return 0_f64;
+    }
+
+    values.iter().sum::<u64>() as f64 / values.len() as f64
+}
+
+/// Packets that missed a latency budget. Lost packets count as misses.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeadlineStatistics {
+    pub deadline: Duration,
+    pub packets_count: u64,
+    pub missed_packets_count: u64,
+    /// Longest run of consecutive misses, not available in histogram mode
+    pub longest_miss_run: Option<u64>
+}
+
+impl DeadlineStatistics {
+    /// `latencies_ns` contains for every sent packet in order of the indices its latency, `None` if it was lost
+    pub fn from_latencies(deadline: Duration, latencies_ns: &[Option<u64>]) -> DeadlineStatistics {
+        let deadline_ns = deadline.as_nanos() as u64;
+        let mut missed_packets_count = 0;
+        let mut miss_run = 0;
+        let mut longest_miss_run = 0;
+        for latency_ns in latencies_ns {
+            let missed = match latency_ns {
+                Some(latency_ns) => *latency_ns > deadline_ns,
+                None => true
+            };
+            if missed {
+                missed_packets_count += 1;
+                miss_run += 1;
+                longest_miss_run = longest_miss_run.max(miss_run);
+            } else {
+                miss_run = 0;
+            }
+        }
+
+        DeadlineStatistics {
+            deadline,
+            packets_count: latencies_ns.len() as u64,
+            missed_packets_count,
+            longest_miss_run: Some(longest_miss_run)
+        }
+    }
+
+    pub fn from_histogram(deadline: Duration, histogram: &Histogram<u64>, lost_packets_count: u64) -> DeadlineStatistics {
+        let deadline_ns = deadline.as_nanos() as u64;
+        let late_packets_count = histogram.iter_recorded()
+            .filter(|value| histogram.lowest_equivalent(value.value_iterated_to()) > deadline_ns)
+            .map(|value| value.count_at_value())
+            .sum::<u64>();
+
+        DeadlineStatistics {
+            deadline,
+            packets_count: histogram.len() + lost_packets_count,
+            missed_packets_count: late_packets_count + lost_packets_count,
+            longest_miss_run: None
+        }
+    }
+
+    pub fn miss_ratio(&self) -> f64 {
+        if self.packets_count == 0 {
+            return 0_f64;
+        }
+
+        self.missed_packets_count as f64 / self.packets_count as f64
+    }
+}
+
+impl fmt::Display for DeadlineStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Deadline {:.3} ms: missed {} of {} ({:.3} %)",
+               self.deadline.as_secs_f64() * 1e3, self.missed_packets_count, self.packets_count, self.miss_ratio() * 100_f64)?;
+        if let Some(longest_miss_run) = self.longest_miss_run {
+            write!(f, " | longest miss run {}", longest_miss_run)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Mann-Whitney U test whether two samples come from the same distribution, using the normal approximation
+/// with tie correction (suitable for the sample sizes of latency tests)
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MannWhitneyU {
+    /// U statistic of the second sample
+    pub u: f64,
+    pub z: f64,
+    /// Two-sided p-value
+    pub p_value: f64,
+    /// Probability that a value of the second sample is larger than a value of the first sample, ties count half
+    pub probability_greater: f64
+}
+
+impl MannWhitneyU {
+    /// None if a sample is empty or all values are equal
+    pub fn from_samples(first: &[u64], second: &[u64]) -> Option<MannWhitneyU> {
+        if first.is_empty() || second.is_empty() {
+            return None;
+        }
+
+        let mut values: Vec<(u64, bool)> = first.iter().map(|value| (*value, false)).chain(second.iter().map(|value| (*value, true))).collect();
+        values.sort_unstable();
+
+        let mut second_rank_sum = 0_f64;
+        let mut tie_correction = 0_f64;
+        let mut start = 0;
+        while start < values.len() {
+            let end = start + values[start..].partition_point(|(value, _)| *value == values[start].0);
+            // Tied values get the mean of their ranks
+            let rank = (start + end + 1) as f64 / 2_f64;
+            second_rank_sum += rank * values[start..end].iter().filter(|(_, is_second)| *is_second).count() as f64;
+            let tied = (end - start) as f64;
+            tie_correction += tied.powi(3) - tied;
+            start = end;
+        }
+
+        let (first_count, second_count, count) = (first.len() as f64, second.len() as f64, values.len() as f64);
+        let u = second_rank_sum - second_count * (second_count + 1_f64) / 2_f64;
+        let mean = first_count * second_count / 2_f64;
+        let variance = first_count * second_count / 12_f64 * ((count + 1_f64) - tie_correction / (count * (count - 1_f64)));
+        if variance <= 0_f64 {
+            return None;
+        }
+
+        // Continuity correction
+        let difference = u - mean;
+        let z = (difference.abs() - 0.5_f64).max(0_f64) * difference.signum() / variance.sqrt();
+
+        Some(MannWhitneyU {
+            u,
+            z,
+            p_value: erfc(z.abs() / std::f64::consts::SQRT_2).min(1_f64),
+            probability_greater: u / (first_count * second_count)
+        })
+    }
+}
+
+/// Complementary error function with a fractional error below 1.2e-7 (Numerical Recipes, erfcc)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1_f64 / (1_f64 + 0.5 * z);
+    let polynomial = -1.265_512_23 + t * (1.000_023_68 + t * (0.374_091_96 + t * (0.096_784_18 + t * (-0.186_288_06
+        + t * (0.278_868_07 + t * (-1.135_203_98 + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let result = t * (-z * z + polynomial).exp();
+
+    if x >= 0_f64 { result } else { 2_f64 - result }
+}
+
+/// Difference of the delays of two packets, positive if the second packet was delayed longer
+pub fn ipdv_ns(previous_delay_ns: u64, delay_ns: u64) -> i64 {
+    delay_ns as i64 - previous_delay_ns as i64
+}
+
+/// Nearest-rank percentile of an ascending sorted, non-empty slice
+pub fn percentile(sorted: &[u64], percentile: f64) -> u64 {
+    // Small tolerance so that e.g. 99.9 % of 1000 values is rank 999 despite floating point rounding
+    let rank = (percentile / 100_f64 * sorted.len() as f64 - 1e-9).ceil() as usize;
+
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
@@ -15,8 +15,18 @@ limitations under the License.
 </copyright>*/
 
 use std::time::Duration;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+/// How the client keeps the results of a test
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecordingMode {
+    /// Keep every sent and received packet
+    PerPacket,
+    /// Only record latencies in histograms with the given number of significant digits (0 - 5)
+    Histogram { significant_digits: u8 }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TestParameters {
     pub server_ip: String,
     pub server_port: u16,
@@ -27,5 +37,12 @@ pub struct TestParameters {
     pub packets_per_second: u32,
     pub packet_size: usize,
     pub output_rtt: bool,
-    pub measure_owl: bool
+    pub measure_owl: bool,
+    pub recording_mode: RecordingMode,
+    /// Latency budgets every packet is evaluated against
+    pub deadlines: Vec<Duration>,
+    /// Share of packets allowed to miss a deadline before the test is considered failed
+    pub max_deadline_miss_ratio: Option<f64>,
+    /// Interval of the reports printed while the test is running
+    pub report_interval: Option<Duration>
 }
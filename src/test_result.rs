@@ -14,25 +14,49 @@ See the License for the specific language governing permissions and
 limitations under the License.
 </copyright>*/
 
-use std::collections::{LinkedList, HashMap};
-use crate::packet_result::PacketResult;
+use std::collections::{LinkedList, HashMap, HashSet, BTreeMap};
+use std::fs::File;
+use std::io::Write;
+use std::time::Duration;
+use crate::control::ServerStatistics;
+use crate::histogram::{LatencyHistograms, DEFAULT_SIGNIFICANT_DIGITS};
+use crate::html_report;
+use crate::influxdb;
+use crate::json_result::JsonResult;
+use crate::packet_csv;
+use crate::interval_report::IntervalReport;
+use crate::packet_result::{PacketResult, nanos_to_millis};
 use crate::test_parameters::TestParameters;
-use crate::packet::{SentPacket, ReceivedPacket};
-
-use std::ops::Sub;
+use crate::packet::{SentPacket, ReceivedPacket, system_time_ns};
+use crate::statistics::{LatencyStatistics, JitterStatistics, ReorderingStatistics, LossStatistics, DeadlineStatistics};
 
 #[derive(Clone)]
 pub struct TestResult {
     pub test_parameters: TestParameters,
     pub packet_results: LinkedList<PacketResult>,
-    pub sent_duration_millis: f64,
+    /// Time between sending the first and the last packet after the warm-up
+    pub sent_duration_seconds: f64,
     pub sent_packets_count: u64,
     pub received_packets_count: u64,
-    pub lost_packets_count: u64
+    pub lost_packets_count: u64,
+    /// Indices of the lost packets in ascending order, not available in histogram mode
+    pub lost_packet_indices: Vec<u64>,
+    /// Results of the received warm-up packets ordered by index, not part of any statistics
+    pub warmup_packet_results: Vec<PacketResult>,
+    pub lost_warmup_packet_indices: Vec<u64>,
+    /// Pongs received more than once, only the first arrival is evaluated
+    pub duplicate_packets_count: u64,
+    /// Not available in histogram mode
+    pub reordering: Option<ReorderingStatistics>,
+    /// Reports of the intervals while the test was running, empty if no report interval was configured
+    pub interval_reports: Vec<IntervalReport>,
+    pub histograms: Option<LatencyHistograms>,
+    /// Counters the server reported on the control connection at the end of the test
+    pub server_statistics: Option<ServerStatistics>
 }
 
 impl TestResult {
-    pub fn from_tx_rx_times(test_parameters: TestParameters, sent_packets: &Vec<SentPacket>, received_packets: &Vec<ReceivedPacket>, output_rtt: bool) -> TestResult {
+    pub fn from_tx_rx_times(test_parameters: TestParameters, sent_packets: &[SentPacket], received_packets: &[ReceivedPacket], output_rtt: bool) -> TestResult {
 
         let mut sent_packet_count = sent_packets.len();
         let mut lost_packets_count = 0;
@@ -40,131 +64,438 @@ impl TestResult {
 
         let mut packet_results: LinkedList<PacketResult> = LinkedList::new();
 
+        // Received packets are in order of arrival, so the first arrival of a packet is kept
+        let warmup_indices: HashSet<u64> = sent_packets.iter().filter(|sent_packet| sent_packet.is_warmup).map(|sent_packet| sent_packet.index).collect();
         let mut receive_packets_map: HashMap<u64, &ReceivedPacket> = HashMap::with_capacity(received_packets.len());
+        let mut arrival_order = Vec::with_capacity(received_packets.len());
+        let mut duplicate_packets_count = 0;
+        let mut duplicates_counts: HashMap<u64, u64> = HashMap::new();
         for received_packet in received_packets
         {
+            if receive_packets_map.contains_key(&received_packet.index) {
+                *duplicates_counts.entry(received_packet.index).or_insert(0) += 1;
+                if !warmup_indices.contains(&received_packet.index) {
+                    duplicate_packets_count += 1;
+                }
+                continue;
+            }
             receive_packets_map.insert(received_packet.index, received_packet);
+            if !warmup_indices.contains(&received_packet.index) {
+                arrival_order.push(received_packet.index);
+            }
         }
 
+        let mut lost_packet_indices = Vec::new();
+        let mut warmup_packet_results = Vec::new();
+        let mut lost_warmup_packet_indices = Vec::new();
         let mut invalid_packets_due_timestamps = 0;
         for sent_packet in sent_packets {
             if sent_packet.is_warmup
             {
                 sent_packet_count -= 1;
-                continue;
             }
-            else if receive_packets_map.contains_key(&sent_packet.index)
+
+            if let Some(received_packet) = receive_packets_map.get(&sent_packet.index)
             {
-                let received_packet = *receive_packets_map.get(&sent_packet.index).unwrap();
                 let received_time = received_packet.received_duration;
+                let round_trip_time_ns = received_time.saturating_sub(sent_packet.sent_duration).as_nanos() as u64;
+                // Both server timestamps are taken from the same clock, so the residence time is valid without synchronized clocks
+                let server_processing_time_ns = received_packet.server_tx_timestamp_ns.checked_sub(received_packet.server_rx_timestamp_ns);
 
-                let mut latency_ms: f64 = -1_f64;
-                let mut one_way_latency_client_to_server_ms: f64 = -1_f64;
-                let mut one_way_latency_server_to_client_ms: f64 = -1_f64;
+                let mut latency_ns = round_trip_time_ns;
+                let mut one_way_latency_client_to_server_ns = None;
+                let mut one_way_latency_server_to_client_ns = None;
                 if test_parameters.measure_owl {
-                    let one_way_latency_client_to_server = received_packet.server_timestamp.checked_sub(sent_packet.sent_timestamp);
-                    let one_way_latency_server_to_client = received_packet.received_timestamp.checked_sub(received_packet.server_timestamp);
+                    // T1 -> T2 (client send -> server receive) and T3 -> T4 (server send -> client receive)
+                    let one_way_latency_client_to_server = received_packet.server_rx_timestamp_ns.checked_sub(sent_packet.sent_timestamp_ns);
+                    let one_way_latency_server_to_client = received_packet.received_timestamp_ns.checked_sub(received_packet.server_tx_timestamp_ns);
                     if one_way_latency_client_to_server.is_some() && one_way_latency_server_to_client.is_some()
                     {
-                        one_way_latency_client_to_server_ms = (one_way_latency_client_to_server.unwrap().as_secs() as f64 + one_way_latency_client_to_server.unwrap().subsec_nanos() as f64 * 1e-9) * 1000_f64;
-                        one_way_latency_server_to_client_ms = (one_way_latency_server_to_client.unwrap().as_secs() as f64 + one_way_latency_server_to_client.unwrap().subsec_nanos() as f64 * 1e-9) * 1000_f64;
-                        latency_ms = one_way_latency_client_to_server_ms + one_way_latency_server_to_client_ms;
+                        one_way_latency_client_to_server_ns = one_way_latency_client_to_server;
+                        one_way_latency_server_to_client_ns = one_way_latency_server_to_client;
                     }
-                    else {
+                    // Warm-up packets are not evaluated, implausible timestamps during the warm-up are not reported
+                    else if !sent_packet.is_warmup {
                         if one_way_latency_client_to_server.is_none()
                         {
-                            let wrong_one_way_latency_client_to_server = sent_packet.sent_timestamp.checked_sub(received_packet.server_timestamp);
-                            let timestamps_difference = (wrong_one_way_latency_client_to_server.unwrap().as_secs() as f64 + wrong_one_way_latency_client_to_server.unwrap().subsec_nanos() as f64 * 1e-9) * 1000_f64;
-                            eprintln!("Timestamps of packet '{}' not plausible, server receive timestamp is before client sent timestamp (Difference: -{})", received_packet.index, timestamps_difference);
+                            let timestamps_difference = nanos_to_millis(sent_packet.sent_timestamp_ns - received_packet.server_rx_timestamp_ns);
+                            eprintln!("Timestamps of packet '{}' not plausible, server receive timestamp is before client sent timestamp (Difference: -{} ms)", received_packet.index, timestamps_difference);
                         }
                         if one_way_latency_server_to_client.is_none()
                         {
-                            let wrong_one_way_latency_server_to_client = received_packet.server_timestamp.checked_sub(received_packet.received_timestamp);
-                            let timestamps_difference = (wrong_one_way_latency_server_to_client.unwrap().as_secs() as f64 + wrong_one_way_latency_server_to_client.unwrap().subsec_nanos() as f64 * 1e-9) * 1000_f64;
-                            eprintln!("Timestamps of packet '{}' not plausible, client receive timestamp is before server receive timestamp (Difference: -{})", received_packet.index, timestamps_difference);
+                            let timestamps_difference = nanos_to_millis(received_packet.server_tx_timestamp_ns - received_packet.received_timestamp_ns);
+                            eprintln!("Timestamps of packet '{}' not plausible, client receive timestamp is before server send timestamp (Difference: -{} ms)", received_packet.index, timestamps_difference);
                         }
                         invalid_packets_due_timestamps += 1;
                     }
                 }
-                else {
-                    let round_trip_time = received_time.sub(sent_packet.sent_duration);
-                    let round_trip_time_ms = (round_trip_time.as_secs() as f64 + round_trip_time.subsec_nanos() as f64 * 1e-9) * 1000_f64;
-                    latency_ms = round_trip_time_ms;
-                }
 
                 if !output_rtt
                 {
-                    latency_ms = latency_ms / 2_f64;
+                    latency_ns /= 2;
                 }
 
                 let packet_result = PacketResult {
                     index: sent_packet.index,
-                    rx_time: sent_packet.sent_duration.as_secs() as f64 + sent_packet.sent_duration.subsec_nanos() as f64 * 1e-9,
-                    tx_time: received_time.as_secs() as f64 + received_time.subsec_nanos() as f64 * 1e-9,
-                    latency: latency_ms,
-                    latency_client_to_server: one_way_latency_client_to_server_ms,
-                    latency_server_to_client: one_way_latency_server_to_client_ms
+                    sent_time_ns: sent_packet.sent_duration.as_nanos() as u64,
+                    received_time_ns: received_time.as_nanos() as u64,
+                    sent_timestamp_ns: sent_packet.sent_timestamp_ns,
+                    received_timestamp_ns: received_packet.received_timestamp_ns,
+                    server_rx_timestamp_ns: received_packet.server_rx_timestamp_ns,
+                    server_tx_timestamp_ns: received_packet.server_tx_timestamp_ns,
+                    round_trip_time_ns,
+                    server_processing_time_ns,
+                    latency_ns,
+                    latency_client_to_server_ns: one_way_latency_client_to_server_ns,
+                    latency_server_to_client_ns: one_way_latency_server_to_client_ns,
+                    duplicates_count: duplicates_counts.get(&sent_packet.index).copied().unwrap_or(0)
                 };
-                packet_results.push_back(packet_result);
-
-                received_packets_count += 1;
+                if sent_packet.is_warmup {
+                    warmup_packet_results.push(packet_result);
+                } else {
+                    packet_results.push_back(packet_result);
+                    received_packets_count += 1;
+                }
+            }
+            else if sent_packet.is_warmup {
+                lost_warmup_packet_indices.push(sent_packet.index);
             }
             else {
                 lost_packets_count += 1;
+                lost_packet_indices.push(sent_packet.index);
             }
         }
 
         let mut vec: Vec<_> = packet_results.into_iter().collect();
-        vec.sort_by(|a, b| a.index.cmp(&b.index));
+        vec.sort_by_key(|a| a.index);
         let packet_results: LinkedList<_> = vec.into_iter().collect();
+        warmup_packet_results.sort_by_key(|packet_result| packet_result.index);
 
-        let sent_duration = packet_results.back().unwrap().tx_time.sub(packet_results.front().unwrap().tx_time);
+        // Time between sending the first and the last packet after the warm-up
+        let sent_times = sent_packets.iter().filter(|sent_packet| !sent_packet.is_warmup).map(|sent_packet| sent_packet.sent_duration);
+        let sent_duration = match (sent_times.clone().min(), sent_times.max()) {
+            (Some(first), Some(last)) => (last - first).as_secs_f64(),
+            _ => 0_f64
+        };
 
         if test_parameters.measure_owl
         {
             println!("Invalid packet count due timestamps: {}", invalid_packets_due_timestamps);
         }
 
-        let test_result = TestResult {
-            test_parameters: test_parameters,
-            packet_results: packet_results,
-            sent_duration_millis: sent_duration,
+        TestResult {
+            test_parameters,
+            packet_results,
+            sent_duration_seconds: sent_duration,
             sent_packets_count: sent_packet_count as u64,
             received_packets_count,
-            lost_packets_count
+            lost_packets_count,
+            lost_packet_indices,
+            warmup_packet_results,
+            lost_warmup_packet_indices,
+            duplicate_packets_count,
+            reordering: Some(ReorderingStatistics::from_arrival_order(&arrival_order)),
+            interval_reports: Vec::new(),
+            histograms: None,
+            server_statistics: None
+        }
+    }
+
+    /// Creates a result of a test recorded in histogram mode, without per packet results
+    pub fn from_histograms(test_parameters: TestParameters, histograms: LatencyHistograms, sent_packets_count: u64, received_packets_count: u64) -> TestResult {
+        TestResult {
+            sent_duration_seconds: test_parameters.test_duration_valid.as_secs_f64(),
+            test_parameters,
+            packet_results: LinkedList::new(),
+            sent_packets_count,
+            received_packets_count,
+            lost_packets_count: sent_packets_count.saturating_sub(received_packets_count),
+            lost_packet_indices: Vec::new(),
+            warmup_packet_results: Vec::new(),
+            lost_warmup_packet_indices: Vec::new(),
+            duplicate_packets_count: 0,
+            reordering: None,
+            interval_reports: Vec::new(),
+            histograms: Some(histograms),
+            server_statistics: None
+        }
+    }
+
+    /// Histograms of this result, built from the packet results if the test was not recorded in histogram mode
+    pub fn latency_histograms(&self) -> std::io::Result<LatencyHistograms> {
+        if let Some(histograms) = &self.histograms {
+            return Ok(histograms.clone());
+        }
+
+        let mut histograms = LatencyHistograms::new(DEFAULT_SIGNIFICANT_DIGITS)?;
+        for packet_result in &self.packet_results {
+            histograms.latency.saturating_record(packet_result.latency_ns);
+            if let Some(latency_ns) = packet_result.latency_client_to_server_ns {
+                histograms.latency_client_to_server.saturating_record(latency_ns);
+            }
+            if let Some(latency_ns) = packet_result.latency_server_to_client_ns {
+                histograms.latency_server_to_client.saturating_record(latency_ns);
+            }
+            if let Some(processing_time_ns) = packet_result.server_processing_time_ns {
+                histograms.server_processing_time.saturating_record(processing_time_ns);
+            }
+        }
+
+        Ok(histograms)
+    }
+
+    /// Combines the result of another run or client into this one. The merged result only keeps
+    /// histograms, per packet results, reordering statistics and interval reports are dropped since their indices
+    /// and times overlap. Like `ServerStatistics::combine`, the parts are assumed to run at the same time, so counters
+    /// are summed and the sent duration is the longer one.
+    pub fn merge(&mut self, other: &TestResult) -> std::io::Result<()> {
+        let mut histograms = self.latency_histograms()?;
+        histograms.merge(&other.latency_histograms()?)?;
+
+        self.histograms = Some(histograms);
+        self.packet_results.clear();
+        self.lost_packet_indices.clear();
+        self.warmup_packet_results.clear();
+        self.lost_warmup_packet_indices.clear();
+        self.reordering = None;
+        self.interval_reports.clear();
+        self.sent_duration_seconds = self.sent_duration_seconds.max(other.sent_duration_seconds);
+        self.sent_packets_count += other.sent_packets_count;
+        self.received_packets_count += other.received_packets_count;
+        self.lost_packets_count += other.lost_packets_count;
+        self.duplicate_packets_count += other.duplicate_packets_count;
+        self.server_statistics = match (&self.server_statistics, &other.server_statistics) {
+            (Some(server_statistics), Some(other_server_statistics)) => Some(server_statistics.combine(other_server_statistics)),
+            _ => None
         };
 
-        return test_result;
+        Ok(())
+    }
+
+    pub fn write_histogram_log(&self, path: &str) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+
+        self.latency_histograms()?.write_log(&mut file, self.test_parameters.test_duration_valid)
+    }
+
+    /// Result in the versioned JSON schema, per packet results are only included if requested
+    pub fn to_json(&self, include_packet_results: bool) -> JsonResult {
+        JsonResult::from_test_result(self, include_packet_results)
+    }
+
+    pub fn write_json(&self, path: &str, include_packet_results: bool) -> std::io::Result<()> {
+        self.to_json(include_packet_results).write_file(path)
+    }
+
+    /// Per packet results in the versioned CSV schema, see `packet_csv`
+    pub fn write_packet_csv<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        packet_csv::write(self, writer)
+    }
+
+    /// HTML report with plots of the per packet results
+    pub fn write_html_report(&self, path: &str) -> std::io::Result<()> {
+        html_report::write_file(&self.to_json(true), path)
+    }
+
+    /// Results in the InfluxDB line protocol, the summary is timestamped with the current time
+    pub fn to_line_protocol(&self, include_packet_results: bool) -> String {
+        influxdb::to_line_protocol(self, include_packet_results, system_time_ns())
+    }
+
+    pub fn write_line_protocol(&self, path: &str, include_packet_results: bool) -> std::io::Result<()> {
+        influxdb::write_file(&self.to_line_protocol(include_packet_results), path)
+    }
+
+    pub fn post_line_protocol(&self, url: &str, token: Option<&str>, include_packet_results: bool) -> std::io::Result<()> {
+        influxdb::post(&self.to_line_protocol(include_packet_results), url, token)
     }
 
     pub fn average_latency(&self) -> f64 {
-        let mut average_latency = 0_f64;
-        for packet_result in &self.packet_results {
-            average_latency += packet_result.latency;
+        if let Some(histograms) = &self.histograms {
+            return histograms.latency.mean() * 1e-6;
         }
-        average_latency = average_latency / self.packet_results.len() as f64;
 
-        return average_latency;
+        let latencies: Vec<u64> = self.packet_results.iter().map(|packet_result| packet_result.latency_ns).collect();
+
+        average_millis(&latencies)
     }
 
     pub fn average_latency_client_to_server(&self) -> f64 {
-        let mut average_latency_client_to_server = 0_f64;
-        for packet_result in &self.packet_results {
-            average_latency_client_to_server += packet_result.latency_client_to_server;
+        if let Some(histograms) = &self.histograms {
+            return histograms.latency_client_to_server.mean() * 1e-6;
         }
-        average_latency_client_to_server = average_latency_client_to_server / self.packet_results.len() as f64;
 
-        return average_latency_client_to_server;
+        let latencies: Vec<u64> = self.packet_results.iter().filter_map(|packet_result| packet_result.latency_client_to_server_ns).collect();
+
+        average_millis(&latencies)
     }
 
     pub fn average_latency_server_to_client(&self) -> f64 {
-        let mut average_latency_server_to_client = 0_f64;
-        for packet_result in &self.packet_results {
-            average_latency_server_to_client += packet_result.latency_server_to_client;
+        if let Some(histograms) = &self.histograms {
+            return histograms.latency_server_to_client.mean() * 1e-6;
+        }
+
+        let latencies: Vec<u64> = self.packet_results.iter().filter_map(|packet_result| packet_result.latency_server_to_client_ns).collect();
+
+        average_millis(&latencies)
+    }
+
+    pub fn latency_statistics(&self) -> Option<LatencyStatistics> {
+        if let Some(histograms) = &self.histograms {
+            return LatencyStatistics::from_histogram(&histograms.latency);
+        }
+
+        let latencies: Vec<u64> = self.packet_results.iter().map(|packet_result| packet_result.latency_ns).collect();
+
+        LatencyStatistics::from_nanos(&latencies)
+    }
+
+    pub fn latency_client_to_server_statistics(&self) -> Option<LatencyStatistics> {
+        if let Some(histograms) = &self.histograms {
+            return LatencyStatistics::from_histogram(&histograms.latency_client_to_server);
+        }
+
+        let latencies: Vec<u64> = self.packet_results.iter().filter_map(|packet_result| packet_result.latency_client_to_server_ns).collect();
+
+        LatencyStatistics::from_nanos(&latencies)
+    }
+
+    pub fn latency_server_to_client_statistics(&self) -> Option<LatencyStatistics> {
+        if let Some(histograms) = &self.histograms {
+            return LatencyStatistics::from_histogram(&histograms.latency_server_to_client);
+        }
+
+        let latencies: Vec<u64> = self.packet_results.iter().filter_map(|packet_result| packet_result.latency_server_to_client_ns).collect();
+
+        LatencyStatistics::from_nanos(&latencies)
+    }
+
+    pub fn server_processing_time_statistics(&self) -> Option<LatencyStatistics> {
+        if let Some(histograms) = &self.histograms {
+            return LatencyStatistics::from_histogram(&histograms.server_processing_time);
+        }
+
+        let processing_times: Vec<u64> = self.packet_results.iter().filter_map(|packet_result| packet_result.server_processing_time_ns).collect();
+
+        LatencyStatistics::from_nanos(&processing_times)
+    }
+
+    /// Longest time between two consecutively received pongs in milliseconds, not available in histogram mode
+    pub fn max_gap_millis(&self) -> Option<f64> {
+        if self.histograms.is_some() {
+            return None;
+        }
+
+        let mut receive_times: Vec<u64> = self.packet_results.iter().map(|packet_result| packet_result.received_time_ns).collect();
+        receive_times.sort_unstable();
+
+        let max_gap_ns = receive_times.windows(2).map(|pair| pair[1] - pair[0]).max().unwrap_or(0);
+
+        Some(nanos_to_millis(max_gap_ns))
+    }
+
+    /// Burst pattern of the lost packets, not available in histogram mode
+    pub fn loss_statistics(&self) -> Option<LossStatistics> {
+        if self.histograms.is_some() {
+            return None;
+        }
+
+        let lost: Vec<bool> = self.latencies_in_index_order().iter().map(|latency_ns| latency_ns.is_none()).collect();
+
+        Some(LossStatistics::from_loss_sequence(&lost))
+    }
+
+    /// Misses of every deadline of the test parameters, lost packets count as misses
+    pub fn deadline_statistics(&self) -> Vec<DeadlineStatistics> {
+        if let Some(histograms) = &self.histograms {
+            return self.test_parameters.deadlines.iter()
+                .map(|deadline| DeadlineStatistics::from_histogram(*deadline, &histograms.latency, self.lost_packets_count))
+                .collect();
+        }
+
+        let latencies_ns = self.latencies_in_index_order();
+        self.test_parameters.deadlines.iter()
+            .map(|deadline| DeadlineStatistics::from_latencies(*deadline, &latencies_ns))
+            .collect()
+    }
+
+    /// Whether any deadline was missed by a larger share of packets than allowed by the test parameters
+    pub fn deadline_miss_ratio_exceeded(&self) -> bool {
+        match self.test_parameters.max_deadline_miss_ratio {
+            Some(max_deadline_miss_ratio) => self.deadline_statistics().iter().any(|deadline| deadline.miss_ratio() > max_deadline_miss_ratio),
+            None => false
+        }
+    }
+
+    /// Latency of every sent packet in order of the indices, `None` for lost packets
+    fn latencies_in_index_order(&self) -> Vec<Option<u64>> {
+        let mut sequence: Vec<(u64, Option<u64>)> = self.packet_results.iter().map(|packet_result| (packet_result.index, Some(packet_result.latency_ns)))
+            .chain(self.lost_packet_indices.iter().map(|index| (*index, None)))
+            .collect();
+        sequence.sort_unstable_by_key(|(index, _)| *index);
+
+        sequence.into_iter().map(|(_, latency_ns)| latency_ns).collect()
+    }
+
+    /// Jitter of the round trip times in order of arrival at the client, not available in histogram mode
+    pub fn round_trip_time_jitter(&self) -> Option<JitterStatistics> {
+        JitterStatistics::from_packet_results(&self.packet_results, |packet_result| Some((packet_result.received_time_ns, packet_result.round_trip_time_ns)))
+    }
+
+    /// Jitter of the one-way latencies client -> server in order of arrival at the server
+    pub fn latency_client_to_server_jitter(&self) -> Option<JitterStatistics> {
+        JitterStatistics::from_packet_results(&self.packet_results, |packet_result| packet_result.latency_client_to_server_ns.map(|latency_ns| (packet_result.server_rx_timestamp_ns, latency_ns)))
+    }
+
+    /// Jitter of the one-way latencies server -> client in order of arrival at the client
+    pub fn latency_server_to_client_jitter(&self) -> Option<JitterStatistics> {
+        JitterStatistics::from_packet_results(&self.packet_results, |packet_result| packet_result.latency_server_to_client_ns.map(|latency_ns| (packet_result.received_time_ns, latency_ns)))
+    }
+
+    pub fn average_server_processing_time(&self) -> f64 {
+        if let Some(histograms) = &self.histograms {
+            return histograms.server_processing_time.mean() * 1e-6;
+        }
+
+        let processing_times: Vec<u64> = self.packet_results.iter().filter_map(|packet_result| packet_result.server_processing_time_ns).collect();
+
+        average_millis(&processing_times)
+    }
+
+    /// Average round trip time minus the residence time of the packets in the server
+    pub fn average_network_round_trip_time(&self) -> f64 {
+        if let Some(histograms) = &self.histograms {
+            let round_trip_time_factor = if self.test_parameters.output_rtt { 1_f64 } else { 2_f64 };
+            return (histograms.latency.mean() * round_trip_time_factor - histograms.server_processing_time.mean()) * 1e-6;
         }
-        average_latency_server_to_client = average_latency_server_to_client / self.packet_results.len() as f64;
 
-        return average_latency_server_to_client;
+        let round_trip_times: Vec<u64> = self.packet_results.iter().map(|packet_result| packet_result.network_round_trip_time_ns()).collect();
+
+        average_millis(&round_trip_times)
     }
 
+    /// Number of packets per server processing time bucket, keyed by the lower bound of the bucket in nanoseconds
+    pub fn server_processing_time_distribution(&self, bucket_width: Duration) -> BTreeMap<u64, u64> {
+        let bucket_width_ns = (bucket_width.as_nanos() as u64).max(1);
+        let mut distribution = BTreeMap::new();
+        if let Some(histograms) = &self.histograms {
+            for value in histograms.server_processing_time.iter_recorded() {
+                *distribution.entry(value.value_iterated_to() / bucket_width_ns * bucket_width_ns).or_insert(0) += value.count_at_value();
+            }
+            return distribution;
+        }
+
+        for processing_time_ns in self.packet_results.iter().filter_map(|packet_result| packet_result.server_processing_time_ns) {
+            *distribution.entry(processing_time_ns / bucket_width_ns * bucket_width_ns).or_insert(0) += 1;
+        }
+
+        distribution
+    }
+
+}
+
+fn average_millis(latencies_ns: &[u64]) -> f64 {
+    let sum_ns: u128 = latencies_ns.iter().map(|latency_ns| *latency_ns as u128).sum();
+
+    sum_ns as f64 / latencies_ns.len() as f64 * 1e-6
 }
@@ -0,0 +1,128 @@
+#[cfg(test)]
+mod comparison_tests {
+    use std::time::Duration;
+    use rperf::comparison::Comparison;
+    use rperf::json_result::JsonResult;
+    use rperf::packet::{SentPacket, ReceivedPacket};
+    use rperf::test_parameters::{TestParameters, RecordingMode};
+    use rperf::test_result::TestResult;
+
+    fn json_result(round_trip_time_ns: u64, include_packet_results: bool) -> JsonResult {
+        json_result_with_loss(round_trip_time_ns, include_packet_results, 0)
+    }
+
+    /// The first `lost_packets_count` of the 200 packets are lost
+    fn json_result_with_loss(round_trip_time_ns: u64, include_packet_results: bool, lost_packets_count: u64) -> JsonResult {
+        let test_parameters = TestParameters {
+            server_ip: "127.0.0.1".to_string(),
+            server_port: 5555,
+            protocol: "udp".to_string(),
+            test_duration_valid: Duration::from_secs(1),
+            test_duration_total: Duration::from_secs(1),
+            warmup_duration: Duration::from_secs(0),
+            packets_per_second: 200,
+            packet_size: 64,
+            output_rtt: true,
+            measure_owl: false,
+            recording_mode: RecordingMode::PerPacket,
+            deadlines: Vec::new(),
+            max_deadline_miss_ratio: None,
+            report_interval: None
+        };
+
+        let base_ns = 1_630_000_000_000_000_000_u64;
+        let sent_packets: Vec<_> = (0..200).map(|index| SentPacket {
+            index,
+            sent_duration: Duration::from_millis(index * 5),
+            sent_timestamp_ns: base_ns + index * 5_000_000,
+            is_warmup: false
+        }).collect();
+        let received_packets: Vec<_> = sent_packets.iter().filter(|sent_packet| sent_packet.index >= lost_packets_count).map(|sent_packet| ReceivedPacket {
+            index: sent_packet.index,
+            received_duration: sent_packet.sent_duration + Duration::from_nanos(round_trip_time_ns + sent_packet.index % 20 * 1_000),
+            sent_timestamp_ns: sent_packet.sent_timestamp_ns,
+            received_timestamp_ns: sent_packet.sent_timestamp_ns + round_trip_time_ns + sent_packet.index % 20 * 1_000,
+            server_rx_timestamp_ns: sent_packet.sent_timestamp_ns + 40_000,
+            server_tx_timestamp_ns: sent_packet.sent_timestamp_ns + 50_000
+        }).collect();
+
+        TestResult::from_tx_rx_times(test_parameters, &sent_packets, &received_packets, true).to_json(include_packet_results)
+    }
+
+    #[test]
+    fn detect_latency_regression() {
+        let comparison = Comparison::new(&[
+            ("before".to_string(), json_result(100_000, true)),
+            ("same".to_string(), json_result(100_000, true)),
+            ("after".to_string(), json_result(130_000, true))
+        ]).unwrap();
+
+        let same = &comparison.candidates[0];
+        assert!(same.deltas.iter().all(|delta| delta.relative_change_percent() == 0_f64));
+        assert!(same.mann_whitney.unwrap().p_value > 0.9);
+
+        let after = &comparison.candidates[1];
+        let mann_whitney = after.mann_whitney.unwrap();
+        assert!(mann_whitney.p_value < 1e-6);
+        assert_eq!(mann_whitney.probability_greater, 1_f64);
+
+        let regressions = comparison.regressions(10_f64, 0.05);
+        assert_eq!(regressions.len(), 2);
+        assert!(regressions.iter().all(|regression| regression.starts_with("after: Round Trip Time")));
+        assert!(comparison.regressions(30_f64, 0.05).is_empty());
+
+        let table = comparison.to_string();
+        assert!(table.contains("Round Trip Time Median [ms]"));
+        assert!(table.contains("(+0.00 %)"));
+        assert!(table.contains("after vs before: Mann-Whitney U 40000.0"));
+    }
+
+    #[test]
+    fn compare_without_packet_results() {
+        let comparison = Comparison::new(&[
+            ("before".to_string(), json_result(100_000, false)),
+            ("after".to_string(), json_result(130_000, true))
+        ]).unwrap();
+
+        assert!(comparison.candidates[0].mann_whitney.is_none());
+        assert_eq!(comparison.regressions(10_f64, 0.05).len(), 2);
+        assert!(comparison.to_string().contains("no significance test"));
+        assert!(Comparison::new(&[("before".to_string(), json_result(100_000, false))]).is_err());
+    }
+
+    #[test]
+    fn detect_loss_regression() {
+        let comparison = Comparison::new(&[
+            ("before".to_string(), json_result_with_loss(100_000, true, 10)),
+            ("same".to_string(), json_result_with_loss(100_000, true, 10)),
+            ("after".to_string(), json_result_with_loss(100_000, true, 20))
+        ]).unwrap();
+
+        assert_eq!(comparison.results[0].loss_percent(), 5_f64);
+        assert_eq!(comparison.candidates[0].loss.relative_change_percent(), 0_f64);
+        assert_eq!(comparison.candidates[1].loss.relative_change_percent(), 100_f64);
+
+        let regressions = comparison.regressions(10_f64, 0.05);
+        assert_eq!(regressions, vec!["after: Loss ratio 10.000 % is +100.00 % above baseline 5.000 % (tolerance 10 %)".to_string()]);
+        assert!(comparison.regressions(100_f64, 0.05).is_empty());
+        assert!(comparison.to_string().contains("10.000 (+100.00 %)"));
+    }
+
+    #[test]
+    fn detect_missing_latency_regression() {
+        let comparison = Comparison::new(&[
+            ("before".to_string(), json_result(100_000, true)),
+            ("after".to_string(), json_result_with_loss(100_000, true, 200))
+        ]).unwrap();
+
+        let after = &comparison.candidates[0];
+        assert!(!after.has_latency);
+        assert!(after.deltas.is_empty());
+        assert_eq!(after.loss.relative_change_percent(), f64::INFINITY);
+
+        let regressions = comparison.regressions(10_f64, 0.05);
+        assert_eq!(regressions.len(), 2);
+        assert!(regressions[0].starts_with("after: Loss ratio 100.000 %"));
+        assert_eq!(regressions[1], "after: no Round Trip Time statistics, no packet was received");
+    }
+}
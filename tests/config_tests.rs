@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod config_tests {
+    use std::time::Duration;
+    use rperf::config::{ClientConfig, ServerConfig};
+    use rperf::error::RperfError;
+    use rperf::packet::HEADER_LENGTH;
+    use rperf::test_parameters::RecordingMode;
+
+    #[test]
+    fn builder_keeps_defaults_of_unset_options() {
+        let config = ClientConfig::builder("10.0.0.2", 7000)
+            .protocol("TCP")
+            .packets_per_second(500)
+            .deadline(Duration::from_millis(1))
+            .deadline(Duration::from_millis(5))
+            .build()
+            .unwrap();
+
+        assert_eq!(config.server_ip, "10.0.0.2");
+        assert_eq!(config.server_port, 7000);
+        assert_eq!(config.protocol, "tcp");
+        assert_eq!(config.packets_per_second, 500);
+        assert_eq!(config.deadlines, vec![Duration::from_millis(1), Duration::from_millis(5)]);
+        assert_eq!(config.packet_size, ClientConfig::default().packet_size);
+        assert_eq!(config.recording_mode, RecordingMode::PerPacket);
+        assert_eq!(config.log_path, None);
+    }
+
+    #[test]
+    fn invalid_client_configs_are_rejected() {
+        let builder = || ClientConfig::builder("127.0.0.1", 7000);
+
+        assert!(matches!(builder().protocol("sctp").build(), Err(RperfError::InvalidConfig(_))));
+        assert!(matches!(builder().packet_size(HEADER_LENGTH - 1).build(), Err(RperfError::InvalidConfig(_))));
+        assert!(matches!(builder().packets_per_second(0).build(), Err(RperfError::InvalidConfig(_))));
+        assert!(matches!(builder().deadline(Duration::from_millis(1)).max_deadline_miss_ratio(1.5).build(), Err(RperfError::InvalidConfig(_))));
+        assert!(matches!(builder().max_deadline_miss_ratio(0.5).build(), Err(RperfError::InvalidConfig(_))));
+        assert!(matches!(builder().recording_mode(RecordingMode::Histogram { significant_digits: 6 }).build(), Err(RperfError::InvalidConfig(_))));
+        assert!(matches!(builder().report_interval(Duration::from_secs(0)).build(), Err(RperfError::InvalidConfig(_))));
+        assert!(matches!(builder().report_interval(Duration::from_micros(10)).build(), Err(RperfError::InvalidConfig(_))));
+        assert!(builder().packet_size(HEADER_LENGTH).deadline(Duration::from_millis(1)).max_deadline_miss_ratio(1.0).build().is_ok());
+    }
+
+    #[test]
+    fn deserialize_partial_configs() {
+        let client_config: ClientConfig = serde_json::from_str(r#"{
+            "server_ip": "192.168.1.10",
+            "protocol": "tcp",
+            "test_duration": {"secs": 60, "nanos": 0},
+            "recording_mode": {"Histogram": {"significant_digits": 2}}
+        }"#).unwrap();
+        assert_eq!(client_config.server_ip, "192.168.1.10");
+        assert_eq!(client_config.test_duration, Duration::from_secs(60));
+        assert_eq!(client_config.recording_mode, RecordingMode::Histogram { significant_digits: 2 });
+        assert_eq!(client_config.packets_per_second, ClientConfig::default().packets_per_second);
+        assert!(client_config.validate().is_ok());
+
+        let server_config: ServerConfig = serde_json::from_str(r#"{"port": 6000, "symmetric_network_load": true}"#).unwrap();
+        assert_eq!(server_config, ServerConfig::builder(6000).symmetric_network_load(true).build().unwrap());
+    }
+
+    #[test]
+    fn invalid_server_configs_are_rejected() {
+        assert!(matches!(ServerConfig::builder(6000).protocol("quic").build(), Err(RperfError::InvalidConfig(_))));
+        assert!(matches!(ServerConfig::builder(6000).session_idle_timeout(Duration::from_secs(0)).build(), Err(RperfError::InvalidConfig(_))));
+        assert_eq!(ServerConfig::builder(6000).protocol("UDP").build().unwrap().protocol, "udp");
+    }
+}
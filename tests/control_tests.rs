@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod control_tests {
+    use rperf::control::{ControlMessage, ServerStatistics, read_message, write_message};
+
+    #[test]
+    fn messages_are_tagged_json() {
+        assert_eq!(serde_json::to_string(&ControlMessage::Start).unwrap(), r#"{"type":"start"}"#);
+        assert_eq!(serde_json::to_string(&ControlMessage::Measurement { session_id: 7 }).unwrap(), r#"{"type":"measurement","session_id":7}"#);
+
+        let statistics: ControlMessage = serde_json::from_str(r#"{"type":"statistics","received_packets_count":10,"sent_packets_count":9,"rejected_packets_count":1,"duration_seconds":1.5}"#).unwrap();
+        assert_eq!(statistics, ControlMessage::Statistics(ServerStatistics {
+            received_packets_count: 10,
+            sent_packets_count: 9,
+            rejected_packets_count: 1,
+            duration_seconds: 1.5
+        }));
+    }
+
+    #[tokio::test]
+    async fn messages_round_trip_in_frames() {
+        let hello = ControlMessage::Hello {
+            version: 1,
+            session_id: 42,
+            protocol: "udp".to_string(),
+            packet_size: 64,
+            capabilities: vec!["server_statistics".to_string()]
+        };
+        let mut buf = Vec::new();
+        write_message(&mut buf, &hello).await.unwrap();
+        write_message(&mut buf, &ControlMessage::Stop).await.unwrap();
+
+        let mut reader = buf.as_slice();
+        assert_eq!(read_message(&mut reader).await.unwrap(), hello);
+        assert_eq!(read_message(&mut reader).await.unwrap(), ControlMessage::Stop);
+        assert!(read_message(&mut reader).await.is_err());
+    }
+
+    #[test]
+    fn combined_statistics_keep_the_duration() {
+        let statistics = ServerStatistics {
+            received_packets_count: 10,
+            sent_packets_count: 9,
+            rejected_packets_count: 1,
+            duration_seconds: 1.5
+        };
+        let other_statistics = ServerStatistics {
+            received_packets_count: 5,
+            sent_packets_count: 5,
+            rejected_packets_count: 0,
+            duration_seconds: 1.0
+        };
+
+        let combined = statistics.combine(&other_statistics);
+        assert_eq!(combined.received_packets_count, 15);
+        assert_eq!(combined.sent_packets_count, 14);
+        assert_eq!(combined.rejected_packets_count, 1);
+        assert_eq!(combined.duration_seconds, 1.5);
+    }
+}
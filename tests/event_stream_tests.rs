@@ -0,0 +1,133 @@
+#[cfg(test)]
+mod event_stream_tests {
+    use std::io::Write;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::time::Duration;
+    use rperf::event_stream::{EventSink, PacketEvent};
+    use rperf::interval_report::IntervalReport;
+    use rperf::packet::{SentPacket, ReceivedPacket};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Blocks in its first write until released
+    struct StalledWriter {
+        stalled: Option<(mpsc::Sender<()>, mpsc::Receiver<()>)>,
+        buffer: SharedBuffer
+    }
+
+    impl Write for StalledWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if let Some((stalled, release)) = self.stalled.take() {
+                stalled.send(()).unwrap();
+                release.recv().unwrap();
+            }
+            self.buffer.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn sent_packet(index: u64, sent_duration: Duration) -> SentPacket {
+        SentPacket {
+            index,
+            sent_duration,
+            sent_timestamp_ns: 1_000_000_000 + sent_duration.as_nanos() as u64,
+            is_warmup: false
+        }
+    }
+
+    fn received_packet(sent_packet: &SentPacket, round_trip_time: Duration) -> ReceivedPacket {
+        let round_trip_time_ns = round_trip_time.as_nanos() as u64;
+        ReceivedPacket {
+            index: sent_packet.index,
+            received_duration: sent_packet.sent_duration + round_trip_time,
+            sent_timestamp_ns: sent_packet.sent_timestamp_ns,
+            received_timestamp_ns: sent_packet.sent_timestamp_ns + round_trip_time_ns,
+            server_rx_timestamp_ns: sent_packet.sent_timestamp_ns + round_trip_time_ns / 2,
+            server_tx_timestamp_ns: sent_packet.sent_timestamp_ns + round_trip_time_ns / 2
+        }
+    }
+
+    #[test]
+    fn events_are_written_as_lines() {
+        let buffer = SharedBuffer::default();
+        let event_sink = EventSink::new(buffer.clone());
+
+        let first_packet = sent_packet(0, Duration::from_millis(500));
+        let second_packet = sent_packet(1, Duration::from_millis(700));
+        event_sink.packet_sent(&first_packet, true);
+        event_sink.packet_received(&received_packet(&first_packet, Duration::from_micros(100)), true, true);
+        event_sink.packet_sent(&second_packet, false);
+        event_sink.packet_lost(1);
+        let interval_report = IntervalReport {
+            start_time_ns: 0,
+            end_time_ns: 1_000_000_000,
+            sent_packets_count: 2,
+            received_packets_count: 1,
+            lost_packets_count: 0,
+            latency: None,
+            interarrival_jitter: 0_f64
+        };
+        event_sink.interval_report(&interval_report);
+        event_sink.finish().unwrap();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<PacketEvent> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[0], PacketEvent::Sent { index: 0, time_ns: 500_000_000, timestamp_ns: 1_500_000_000, warmup: true });
+        assert_eq!(events[1], PacketEvent::Received {
+            index: 0,
+            time_ns: 500_100_000,
+            timestamp_ns: 1_500_100_000,
+            round_trip_time_ns: 100_000,
+            server_processing_time_ns: Some(0),
+            latency_client_to_server_ns: Some(50_000),
+            latency_server_to_client_ns: Some(50_000),
+            warmup: true
+        });
+        assert!(matches!(events[2], PacketEvent::Sent { index: 1, warmup: false, .. }));
+        assert_eq!(events[3], PacketEvent::Lost { index: 1 });
+        assert_eq!(events[4], PacketEvent::Interval(interval_report));
+        assert!(output.starts_with("{\"event\":\"sent\","));
+        assert!(output.lines().last().unwrap().starts_with("{\"event\":\"interval\",\"start_time_ns\":0,"));
+    }
+
+    #[test]
+    fn events_are_dropped_while_the_writer_is_stalled() {
+        let (stalled_sender, stalled) = mpsc::channel();
+        let (release, release_receiver) = mpsc::channel();
+        let buffer = SharedBuffer::default();
+        let event_sink = EventSink::with_capacity(StalledWriter { stalled: Some((stalled_sender, release_receiver)), buffer: buffer.clone() }, 2);
+
+        event_sink.packet_sent(&sent_packet(0, Duration::ZERO), false);
+        stalled.recv().unwrap();
+        // Two events fit into the queue, the others are dropped
+        for index in 1..6 {
+            event_sink.packet_sent(&sent_packet(index, Duration::from_millis(index)), false);
+        }
+        assert_eq!(event_sink.dropped_events_count(), 3);
+        release.send(()).unwrap();
+        event_sink.finish().unwrap();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let indices: Vec<u64> = output.lines().map(|line| match serde_json::from_str(line).unwrap() {
+            PacketEvent::Sent { index, .. } => index,
+            event => panic!("Unexpected event {:?}", event)
+        }).collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+    }
+}
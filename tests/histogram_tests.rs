@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod histogram_tests {
+    use std::time::Duration;
+    use rperf::control::ServerStatistics;
+    use rperf::histogram::{LatencyHistograms, ReceivedIndices};
+    use rperf::packet::ReceivedPacket;
+    use rperf::test_parameters::{TestParameters, RecordingMode};
+    use rperf::test_result::TestResult;
+
+    fn received_packet(index: u64, round_trip_time_ns: u64) -> ReceivedPacket {
+        let sent_timestamp_ns = 1_630_000_000_000_000_000 + index * 1_000_000;
+        ReceivedPacket {
+            index,
+            received_duration: Duration::from_nanos(index * 1_000_000 + round_trip_time_ns),
+            sent_timestamp_ns,
+            received_timestamp_ns: sent_timestamp_ns + round_trip_time_ns,
+            server_rx_timestamp_ns: sent_timestamp_ns + round_trip_time_ns / 4,
+            server_tx_timestamp_ns: sent_timestamp_ns + round_trip_time_ns / 2,
+        }
+    }
+
+    fn test_parameters() -> TestParameters {
+        TestParameters {
+            server_ip: "127.0.0.1".to_string(),
+            server_port: 5555,
+            protocol: "udp".to_string(),
+            test_duration_valid: Duration::from_secs(1),
+            test_duration_total: Duration::from_secs(1),
+            warmup_duration: Duration::from_secs(0),
+            packets_per_second: 1000,
+            packet_size: 64,
+            output_rtt: true,
+            measure_owl: true,
+            recording_mode: RecordingMode::Histogram { significant_digits: 3 },
+            deadlines: vec![Duration::from_micros(150)],
+            max_deadline_miss_ratio: None,
+            report_interval: None
+        }
+    }
+
+    fn recorded_histograms(round_trip_times_ns: &[u64]) -> LatencyHistograms {
+        let mut histograms = LatencyHistograms::new(3).unwrap();
+        for (index, round_trip_time_ns) in round_trip_times_ns.iter().enumerate() {
+            histograms.record(&received_packet(index as u64, *round_trip_time_ns), true, true);
+        }
+
+        histograms
+    }
+
+    #[test]
+    fn record_and_export_histograms() {
+        let histograms = recorded_histograms(&[100_000, 200_000, 400_000]);
+        assert_eq!(histograms.latency.len(), 3);
+        assert_eq!(histograms.latency_client_to_server.len(), 3);
+        assert_eq!(histograms.server_processing_time.len(), 3);
+
+        let mut log = Vec::new();
+        histograms.write_log(&mut log, Duration::from_secs(1)).unwrap();
+        let imported = LatencyHistograms::read_log(&log).unwrap();
+
+        assert_eq!(imported.latency.len(), 3);
+        assert_eq!(imported.latency.max(), histograms.latency.max());
+        assert_eq!(imported.latency_server_to_client.min(), histograms.latency_server_to_client.min());
+    }
+
+    #[test]
+    fn merge_results_of_several_runs() {
+        let mut test_result = TestResult::from_histograms(test_parameters(), recorded_histograms(&[100_000, 200_000]), 3, 2);
+        let other_test_result = TestResult::from_histograms(test_parameters(), recorded_histograms(&[300_000, 400_000]), 2, 2);
+
+        test_result.merge(&other_test_result).unwrap();
+
+        assert_eq!(test_result.sent_packets_count, 5);
+        assert_eq!(test_result.received_packets_count, 4);
+        assert_eq!(test_result.lost_packets_count, 1);
+        assert!(test_result.packet_results.is_empty());
+
+        let statistics = test_result.latency_statistics().unwrap();
+        assert_eq!(statistics.count, 4);
+        assert!((statistics.min - 0.1).abs() < 0.001);
+        assert!((statistics.max - 0.4).abs() < 0.001);
+        assert!((test_result.average_latency() - 0.25).abs() < 0.001);
+
+        let deadline = test_result.deadline_statistics()[0];
+        assert_eq!(deadline.packets_count, 5);
+        assert_eq!(deadline.missed_packets_count, 4);
+        assert_eq!(deadline.longest_miss_run, None);
+    }
+
+    #[test]
+    fn merged_parts_run_at_the_same_time() {
+        let server_statistics = |duration_seconds| ServerStatistics {
+            received_packets_count: 2,
+            sent_packets_count: 2,
+            rejected_packets_count: 0,
+            duration_seconds
+        };
+        let mut test_result = TestResult::from_histograms(test_parameters(), recorded_histograms(&[100_000, 200_000]), 2, 2);
+        test_result.server_statistics = Some(server_statistics(1.0));
+        let mut other_test_result = TestResult::from_histograms(TestParameters {
+            test_duration_valid: Duration::from_secs(2),
+            ..test_parameters()
+        }, recorded_histograms(&[300_000, 400_000]), 2, 2);
+        other_test_result.server_statistics = Some(server_statistics(2.0));
+
+        test_result.merge(&other_test_result).unwrap();
+
+        assert_eq!(test_result.sent_packets_count, 4);
+        assert_eq!(test_result.sent_duration_seconds, 2_f64);
+        assert_eq!(test_result.server_statistics.unwrap().duration_seconds, 2_f64);
+    }
+
+    #[test]
+    fn duplicates_are_detected() {
+        let mut received_indices = ReceivedIndices::new(100);
+        assert!(received_indices.insert(0));
+        assert!(received_indices.insert(64));
+        assert!(received_indices.insert(99));
+        assert!(!received_indices.insert(64));
+        assert!(!received_indices.insert(0));
+        assert!(!received_indices.insert(128));
+        assert!(received_indices.contains(64));
+        assert!(!received_indices.contains(65));
+        assert!(!received_indices.contains(128));
+    }
+}
@@ -0,0 +1,120 @@
+#[cfg(test)]
+mod html_report_tests {
+    use std::time::Duration;
+    use rperf::histogram::LatencyHistograms;
+    use rperf::html_report::render;
+    use rperf::packet::{SentPacket, ReceivedPacket};
+    use rperf::test_parameters::{TestParameters, RecordingMode};
+    use rperf::test_result::TestResult;
+
+    fn test_parameters() -> TestParameters {
+        TestParameters {
+            server_ip: "127.0.0.1".to_string(),
+            server_port: 5555,
+            protocol: "udp".to_string(),
+            test_duration_valid: Duration::from_secs(5),
+            test_duration_total: Duration::from_secs(5),
+            warmup_duration: Duration::from_secs(0),
+            packets_per_second: 1000,
+            packet_size: 64,
+            output_rtt: false,
+            measure_owl: true,
+            recording_mode: RecordingMode::PerPacket,
+            deadlines: Vec::new(),
+            max_deadline_miss_ratio: None,
+            report_interval: None
+        }
+    }
+
+    fn sent_packets(count: u64, interval: Duration) -> Vec<SentPacket> {
+        let base_ns = 1_630_000_000_000_000_000_u64;
+        (0..count).map(|index| SentPacket {
+            index,
+            sent_duration: interval * index as u32,
+            sent_timestamp_ns: base_ns + index * interval.as_nanos() as u64,
+            is_warmup: false
+        }).collect()
+    }
+
+    fn received_packet(sent_packet: &SentPacket, round_trip_time_ns: u64) -> ReceivedPacket {
+        ReceivedPacket {
+            index: sent_packet.index,
+            received_duration: sent_packet.sent_duration + Duration::from_nanos(round_trip_time_ns),
+            sent_timestamp_ns: sent_packet.sent_timestamp_ns,
+            received_timestamp_ns: sent_packet.sent_timestamp_ns + round_trip_time_ns,
+            server_rx_timestamp_ns: sent_packet.sent_timestamp_ns + 40_000,
+            server_tx_timestamp_ns: sent_packet.sent_timestamp_ns + 50_000
+        }
+    }
+
+    fn test_result(measure_owl: bool) -> TestResult {
+        test_result_sent_every(measure_owl, Duration::from_millis(1))
+    }
+
+    /// 5000 packets, every 100th is lost
+    fn test_result_sent_every(measure_owl: bool, send_interval: Duration) -> TestResult {
+        let test_parameters = TestParameters {
+            measure_owl,
+            ..test_parameters()
+        };
+        let sent_packets = sent_packets(5000, send_interval);
+        let received_packets: Vec<_> = sent_packets.iter().filter(|sent_packet| sent_packet.index % 100 != 7)
+            .map(|sent_packet| received_packet(sent_packet, 100_000 + sent_packet.index % 50 * 1_000)).collect();
+
+        TestResult::from_tx_rx_times(test_parameters, &sent_packets, &received_packets, false)
+    }
+
+    #[test]
+    fn report_contains_all_charts() {
+        let html = render(&test_result(true).to_json(true));
+
+        assert_eq!(html.matches("<svg").count(), 5);
+        assert!(html.contains("Latency over time"));
+        assert!(html.contains("Latency CDF"));
+        assert!(html.contains("One way latency per direction"));
+        assert!(html.contains("Loss timeline (50 packets lost)"));
+        assert!(!html.contains("src=") && !html.contains("href=") && !html.contains("<script"));
+        assert!(html.ends_with("</html>\n"));
+
+        let html = render(&test_result(false).to_json(true));
+        assert_eq!(html.matches("<svg").count(), 4);
+        assert!(html.contains("One way latencies were not measured"));
+    }
+
+    #[test]
+    fn report_without_packet_results() {
+        let html = render(&test_result(false).to_json(false));
+
+        assert_eq!(html.matches("<svg").count(), 1);
+        assert!(html.contains("Latency percentiles"));
+        assert!(!html.contains("Latency client to server</text>"));
+        assert!(html.contains("<tr><th>Lost packets</th><td>50</td></tr>"));
+    }
+
+    #[test]
+    fn report_of_histograms() {
+        let test_parameters = TestParameters {
+            recording_mode: RecordingMode::Histogram { significant_digits: 3 },
+            ..test_parameters()
+        };
+        let mut histograms = LatencyHistograms::new(3).unwrap();
+        for sent_packet in &sent_packets(100, Duration::from_millis(1)) {
+            histograms.record(&received_packet(sent_packet, 100_000 + sent_packet.index * 1_000), true, true);
+        }
+        let html = render(&TestResult::from_histograms(test_parameters, histograms, 100, 100).to_json(true));
+
+        assert_eq!(html.matches("<svg").count(), 1);
+        assert!(html.contains("Latency percentiles"));
+        assert!(html.contains("Latency client to server</text>"));
+    }
+
+    #[test]
+    fn packets_are_placed_at_their_send_time() {
+        // Sent at half the configured rate, so the last packets are sent after 10 s
+        let html = render(&test_result_sent_every(false, Duration::from_millis(2)).to_json(true));
+        assert!(html.contains(">10.0</text>"));
+
+        let html = render(&test_result(false).to_json(true));
+        assert!(!html.contains(">10.0</text>"));
+    }
+}
@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod influxdb_tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+    use rperf::influxdb::{to_line_protocol, post};
+    use rperf::packet::{SentPacket, ReceivedPacket};
+    use rperf::test_parameters::{TestParameters, RecordingMode};
+    use rperf::test_result::TestResult;
+
+    fn test_result() -> TestResult {
+        let test_parameters = TestParameters {
+            server_ip: "10.0.0.1".to_string(),
+            server_port: 5555,
+            protocol: "udp".to_string(),
+            test_duration_valid: Duration::from_secs(1),
+            test_duration_total: Duration::from_secs(1),
+            warmup_duration: Duration::from_secs(0),
+            packets_per_second: 1000,
+            packet_size: 64,
+            output_rtt: true,
+            measure_owl: false,
+            recording_mode: RecordingMode::PerPacket,
+            deadlines: Vec::new(),
+            max_deadline_miss_ratio: None,
+            report_interval: None
+        };
+
+        let base_ns = 1_630_000_000_000_000_000_u64;
+        let sent_packets: Vec<_> = (0..3).map(|index| SentPacket {
+            index,
+            sent_duration: Duration::from_millis(index),
+            sent_timestamp_ns: base_ns + index * 1_000_000,
+            is_warmup: false
+        }).collect();
+        let received_packets: Vec<_> = sent_packets.iter().take(2).map(|sent_packet| ReceivedPacket {
+            index: sent_packet.index,
+            received_duration: sent_packet.sent_duration + Duration::from_micros(100),
+            sent_timestamp_ns: sent_packet.sent_timestamp_ns,
+            received_timestamp_ns: sent_packet.sent_timestamp_ns + 100_000,
+            server_rx_timestamp_ns: sent_packet.sent_timestamp_ns + 40_000,
+            server_tx_timestamp_ns: sent_packet.sent_timestamp_ns + 50_000
+        }).collect();
+
+        TestResult::from_tx_rx_times(test_parameters, &sent_packets, &received_packets, true)
+    }
+
+    #[test]
+    fn summary_and_packet_points() {
+        let lines = to_line_protocol(&test_result(), true, 1_630_000_001_000_000_000);
+        let lines: Vec<_> = lines.lines().collect();
+        assert_eq!(lines.len(), 3);
+
+        let tags = "protocol=udp,packet_size=64,packets_per_second=1000,server=10.0.0.1:5555";
+        assert!(lines[0].starts_with(&format!("rperf_summary,{} ", tags)));
+        assert!(lines[0].ends_with(" 1630000001000000000"));
+        assert!(lines[0].contains(",sent_packets_count=3i,received_packets_count=2i,lost_packets_count=1i,"));
+        assert!(lines[0].contains(",latency_max_ms="));
+        assert!(lines[0].contains(",server_processing_time_max_ms=0.01,"));
+        assert!(!lines[0].contains("latency_client_to_server"));
+
+        assert!(lines[1].starts_with(&format!("rperf_packet,{} index=0i,latency_ms=", tags)));
+        assert!(lines[1].ends_with(",server_processing_time_ms=0.01 1630000000000000000"));
+        assert!(lines[2].starts_with(&format!("rperf_packet,{} index=1i,", tags)));
+        assert_eq!(to_line_protocol(&test_result(), false, 0).lines().count(), 1);
+    }
+
+    #[test]
+    fn post_to_write_endpoint() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in ["204 No Content", "400 Bad Request"].iter() {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head = Vec::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(length) = line.strip_prefix("Content-Length: ") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                    head.push(line.trim_end().to_string());
+                }
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).unwrap();
+                write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+                requests.push((head, String::from_utf8(body).unwrap()));
+            }
+            requests
+        });
+
+        let url = format!("http://{}/api/v2/write?org=lab&bucket=rperf", address);
+        post("rperf_summary,protocol=udp sent_packets_count=3i 1\n", &url, Some("secret")).unwrap();
+        assert!(post("invalid\n", &url, None).is_err());
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].0[0], "POST /api/v2/write?org=lab&bucket=rperf HTTP/1.1");
+        assert!(requests[0].0.contains(&"Authorization: Token secret".to_string()));
+        assert_eq!(requests[0].1, "rperf_summary,protocol=udp sent_packets_count=3i 1\n");
+        assert!(!requests[1].0.iter().any(|line| line.starts_with("Authorization")));
+        assert!(post("", "https://localhost/write", None).is_err());
+    }
+}
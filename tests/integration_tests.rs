@@ -1,66 +1,259 @@
 #[cfg(test)]
 mod integration_tests {
+    use std::net::UdpSocket;
     use std::time::Duration;
-    use std::{thread, time};
-    use async_std::{task};
+    use rperf::config::{ClientConfig, ServerConfig};
+    use rperf::control::{ControlMessage, CAPABILITIES, CONTROL_PROTOCOL_VERSION, read_message, write_message};
+    use rperf::error::RperfError;
+    use rperf::packet::{PacketHeader, HEADER_LENGTH, FLAG_PROBE, encode_packet};
+    use rperf::test_parameters::RecordingMode;
 
-    #[async_std::test]
+    #[tokio::test]
     async fn test_udp() {
 
-        task::spawn(async {
-            rperf::start_server(7777, "udp", false).await.unwrap();
-        });
-
-        let result= rperf::start_test(
-            "127.0.0.1",
-            7777,
-            "udp",
-            Duration::from_secs(1),
-            1000,
-            64,
-            Duration::from_secs(1),
-            "",
-            true,
-            true).await;
-        assert!(result.is_ok())
+        let server = rperf::start_server(&ServerConfig::builder(0).protocol("udp").build().unwrap()).await.unwrap();
+
+        let config = ClientConfig::builder("127.0.0.1", server.local_address().port())
+            .protocol("udp")
+            .test_duration(Duration::from_secs(1))
+            .packets_per_second(1000)
+            .packet_size(64)
+            .warmup_duration(Duration::from_secs(1))
+            .output_rtt(true)
+            .measure_owl(true)
+            .build()
+            .unwrap();
+        let result= rperf::start_test(&config).await.unwrap();
+        // Warm-up pings are answered by the server but not part of the result
+        let server_statistics = result.server_statistics.unwrap();
+        assert!(server_statistics.sent_packets_count >= result.received_packets_count);
+        assert_eq!(server_statistics.rejected_packets_count, 0);
+        server.shutdown().await.unwrap();
     }
 
-    #[async_std::test]
+    #[tokio::test]
     async fn test_tcp() {
 
-        task::spawn(async {
-            rperf::start_server(7778, "tcp", false).await.unwrap();
-        });
-
-        thread::sleep(time::Duration::from_millis(100));
-
-        let result= rperf::start_test(
-            "127.0.0.1",
-            7778,
-            "tcp",
-            Duration::from_secs(1),
-            1000,
-            64,
-            Duration::from_secs(1),
-            "",
-            true,
-            true).await;
-        assert!(result.is_ok())
+        let server = rperf::start_server(&ServerConfig::builder(0).protocol("tcp").build().unwrap()).await.unwrap();
+
+        let config = ClientConfig::builder("127.0.0.1", server.local_address().port())
+            .protocol("tcp")
+            .test_duration(Duration::from_secs(1))
+            .packets_per_second(1000)
+            .packet_size(64)
+            .warmup_duration(Duration::from_secs(1))
+            .output_rtt(true)
+            .measure_owl(true)
+            .build()
+            .unwrap();
+        let result= rperf::start_test(&config).await;
+        assert!(result.is_ok());
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tcp_symmetric_load() {
+
+        let server = rperf::start_server(&ServerConfig::builder(0).protocol("tcp").symmetric_network_load(true).build().unwrap()).await.unwrap();
+
+        let config = ClientConfig::builder("127.0.0.1", server.local_address().port())
+            .protocol("tcp")
+            .test_duration(Duration::from_secs(1))
+            .packets_per_second(1000)
+            .packet_size(512)
+            .output_rtt(true)
+            .build()
+            .unwrap();
+        let result= rperf::start_test(&config).await.unwrap();
+        assert_eq!(result.received_packets_count, result.sent_packets_count);
+        assert_eq!(result.lost_packets_count, 0);
+        let server_statistics = result.server_statistics.unwrap();
+        assert_eq!(server_statistics.received_packets_count, result.sent_packets_count);
+        assert_eq!(server_statistics.sent_packets_count, result.received_packets_count);
+        server.shutdown().await.unwrap();
     }
 
-    #[async_std::test]
+    #[tokio::test]
+    async fn test_udp_histogram_recording() {
+
+        let server = rperf::start_server(&ServerConfig::builder(0).protocol("udp").build().unwrap()).await.unwrap();
+
+        let ndjson_path = std::env::temp_dir().join(format!("rperf_events_{}.ndjson", std::process::id()));
+        let config = ClientConfig::builder("127.0.0.1", server.local_address().port())
+            .protocol("udp")
+            .test_duration(Duration::from_secs(1))
+            .packets_per_second(1000)
+            .packet_size(64)
+            .ndjson_path(ndjson_path.to_str().unwrap())
+            .output_rtt(true)
+            .recording_mode(RecordingMode::Histogram { significant_digits: 3 })
+            .report_interval(Duration::from_millis(500))
+            .build()
+            .unwrap();
+        let result= rperf::start_test(&config).await.unwrap();
+        assert!(result.packet_results.is_empty());
+        assert!(result.received_packets_count > 0);
+        assert_eq!(result.latency_statistics().unwrap().count, result.received_packets_count);
+        assert!(result.interval_reports.len() >= 2);
+        assert_eq!(result.interval_reports.iter().map(|interval_report| interval_report.sent_packets_count).sum::<u64>(), 1000);
+
+        let events = std::fs::read_to_string(&ndjson_path).unwrap();
+        assert_eq!(events.lines().filter(|line| line.starts_with("{\"event\":\"sent\"")).count(), 1000);
+        assert!(events.lines().any(|line| line.starts_with("{\"event\":\"interval\"")));
+        std::fs::remove_file(&ndjson_path).unwrap();
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
     async fn unsupported_protocol() {
-        let exception_thrown = async_std::io::timeout(Duration::from_secs(1), async {
-            match rperf::start_server(5555, "ABC", false).await {
+        // Configs that bypass the builder are validated when the server is started
+        let mut config = ServerConfig::default();
+        config.protocol = "ABC".to_string();
+
+        let exception_thrown = tokio::time::timeout(Duration::from_secs(1), async {
+            match rperf::start_server(&config).await {
                 Ok(_) => {
-                    Ok(false)
+                    false
                 }
                 Err(_) => {
-                    Ok(true)
+                    true
                 }
             }
         }).await.unwrap();
 
         assert!(exception_thrown)
     }
+
+    #[tokio::test]
+    async fn unreachable_server_is_an_error() {
+        // Nothing listens on this port, the client must return instead of exiting the process
+        for protocol in ["udp", "tcp"] {
+            let config = ClientConfig::builder("127.0.0.1", 7781).protocol(protocol).build().unwrap();
+            match rperf::start_test(&config).await {
+                Err(RperfError::ConnectionRefused { server }) => assert_eq!(server, "127.0.0.1:7781"),
+                other => panic!("Unexpected result for {}: {:?}", protocol, other.map(|_| ()))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn shutdown_releases_port() {
+        for protocol in ["udp", "tcp"] {
+            let server = rperf::start_server(&ServerConfig::builder(0).protocol(protocol).build().unwrap()).await.unwrap();
+            let local_address = server.local_address();
+            assert_ne!(local_address.port(), 0);
+            server.shutdown().await.unwrap();
+
+            let config = ServerConfig::builder(local_address.port()).protocol(protocol).build().unwrap();
+            rperf::start_server(&config).await.unwrap().shutdown().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_udp_concurrent_sessions() {
+        // A single worker serves both sessions, two pinned workers share the port with SO_REUSEPORT
+        for udp_workers in [1, 2] {
+            let config = ServerConfig::builder(0).protocol("udp").symmetric_network_load(true).udp_workers(udp_workers).cpu_affinity(vec![0]).build().unwrap();
+            let server = rperf::start_server(&config).await.unwrap();
+            let port = server.local_address().port();
+
+            let clients: Vec<_> = [64, 512].iter().map(|packet_size| {
+                let config = ClientConfig::builder("127.0.0.1", port)
+                    .test_duration(Duration::from_secs(1))
+                    .packets_per_second(500)
+                    .packet_size(*packet_size)
+                    .build()
+                    .unwrap();
+                tokio::spawn(async move { rperf::start_test(&config).await.unwrap() })
+            }).collect();
+
+            for client in clients {
+                let result = client.await.unwrap();
+                assert_eq!(result.sent_packets_count, 500);
+                assert!(result.received_packets_count > 0);
+            }
+            server.shutdown().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn server_rejects_other_protocols() {
+        let server = rperf::start_server(&ServerConfig::builder(0).protocol("udp").build().unwrap()).await.unwrap();
+
+        let config = ClientConfig::builder("127.0.0.1", server.local_address().port()).protocol("tcp").build().unwrap();
+        assert!(matches!(rperf::start_test(&config).await, Err(RperfError::Handshake { .. })));
+        server.shutdown().await.unwrap();
+    }
+
+    fn hello(session_id: u32) -> ControlMessage {
+        ControlMessage::Hello {
+            version: CONTROL_PROTOCOL_VERSION,
+            session_id,
+            protocol: "udp".to_string(),
+            packet_size: 64,
+            capabilities: CAPABILITIES.iter().map(|capability| capability.to_string()).collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn udp_sessions_require_handshake() {
+        let config = ServerConfig::builder(0).protocol("udp").session_idle_timeout(Duration::from_millis(300)).build().unwrap();
+        let server = rperf::start_server(&config).await.unwrap();
+        let port = server.local_address().port();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(("127.0.0.1", port)).unwrap();
+        socket.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let mut buf = [0u8; 1500];
+        let ping = encode_packet(&PacketHeader::new(42, 1, 0), 64);
+        let probe = encode_packet(&PacketHeader::new(42, 0, FLAG_PROBE), HEADER_LENGTH);
+
+        // Pings and probes of sessions not opened on a control connection are not answered
+        socket.send(&ping).unwrap();
+        assert!(socket.recv(&mut buf).is_err());
+        socket.send(&probe).unwrap();
+        assert!(socket.recv(&mut buf).is_err());
+
+        let mut control = tokio::net::TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        write_message(&mut control, &hello(42)).await.unwrap();
+        assert!(matches!(read_message(&mut control).await.unwrap(), ControlMessage::Accept { .. }));
+        write_message(&mut control, &ControlMessage::Start).await.unwrap();
+        socket.send(&probe).unwrap();
+        let amt = socket.recv(&mut buf).unwrap();
+        let pong = PacketHeader::decode(&buf[..amt]).unwrap();
+        assert!(pong.is_pong() && pong.is_probe());
+        socket.send(&ping).unwrap();
+        let amt = socket.recv(&mut buf).unwrap();
+        assert_eq!(PacketHeader::decode(&buf[..amt]).unwrap().sequence, 1);
+
+        // Other ports of the same host cannot take over the session
+        let other_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        other_socket.connect(("127.0.0.1", port)).unwrap();
+        other_socket.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+        other_socket.send(&probe).unwrap();
+        assert!(other_socket.recv(&mut buf).is_err());
+        other_socket.send(&ping).unwrap();
+        assert!(other_socket.recv(&mut buf).is_err());
+        socket.send(&ping).unwrap();
+        assert!(socket.recv(&mut buf).is_ok());
+        // The worker counts a pong after sending it
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        write_message(&mut control, &ControlMessage::Stop).await.unwrap();
+        match read_message(&mut control).await.unwrap() {
+            ControlMessage::Statistics(server_statistics) => {
+                assert_eq!(server_statistics.received_packets_count, 2);
+                assert_eq!(server_statistics.sent_packets_count, 2);
+            }
+            message => panic!("Unexpected response to stop: {:?}", message)
+        }
+
+        // Idle sessions time out and their control connection is closed
+        let mut control = tokio::net::TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        write_message(&mut control, &hello(43)).await.unwrap();
+        assert!(matches!(read_message(&mut control).await.unwrap(), ControlMessage::Accept { .. }));
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(read_message(&mut control).await.is_err());
+
+        server.shutdown().await.unwrap();
+    }
 }
@@ -0,0 +1,117 @@
+#[cfg(test)]
+mod interval_report_tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use rperf::interval_report::{IntervalRecorder, SentPacketsCounter};
+    use rperf::packet::ReceivedPacket;
+
+    fn received_packet(index: u64, received_duration: Duration, round_trip_time_ns: u64) -> ReceivedPacket {
+        let sent_timestamp_ns = 1_630_000_000_000_000_000 + index * 1_000_000;
+        ReceivedPacket {
+            index,
+            received_duration,
+            sent_timestamp_ns,
+            received_timestamp_ns: sent_timestamp_ns + round_trip_time_ns,
+            server_rx_timestamp_ns: sent_timestamp_ns,
+            server_tx_timestamp_ns: sent_timestamp_ns
+        }
+    }
+
+    #[test]
+    fn intervals_are_closed_by_received_packets() {
+        let sent_packets_counter = Arc::new(SentPacketsCounter::new(Duration::from_secs(1)));
+        let mut interval_recorder = IntervalRecorder::new(false, true, 10, sent_packets_counter.clone(), None);
+
+        for sent_time_ms in [0, 300, 600] {
+            sent_packets_counter.packet_sent(Duration::from_millis(sent_time_ms));
+        }
+        interval_recorder.record(&received_packet(0, Duration::from_millis(100), 100_000));
+        interval_recorder.record(&received_packet(2, Duration::from_millis(300), 300_000));
+        // The sender is ahead of the receiver, its packets of the next intervals must not be counted in the first one
+        for sent_time_ms in [1_100, 1_400, 2_100] {
+            sent_packets_counter.packet_sent(Duration::from_millis(sent_time_ms));
+        }
+        // Packet 1 arrives late in the next interval
+        interval_recorder.record(&received_packet(1, Duration::from_millis(1_200), 200_000));
+        let reports = interval_recorder.finish(Duration::from_millis(2_500));
+
+        assert_eq!(reports.len(), 3);
+        assert_eq!((reports[0].start_time_ns, reports[0].end_time_ns), (0, 1_000_000_000));
+        assert_eq!(reports[0].sent_packets_count, 3);
+        assert_eq!(reports[0].received_packets_count, 2);
+        assert_eq!(reports[0].lost_packets_count, 1);
+        let latency = reports[0].latency.unwrap();
+        assert!((latency.min - 0.1).abs() < 1e-9);
+        assert!((latency.max - 0.3).abs() < 1e-9);
+        assert!((reports[0].interarrival_jitter - 0.0125).abs() < 1e-9);
+
+        assert_eq!(reports[1].sent_packets_count, 2);
+        assert_eq!(reports[1].received_packets_count, 1);
+        assert_eq!(reports[1].lost_packets_count, 0);
+
+        assert_eq!((reports[2].start_time_ns, reports[2].end_time_ns), (2_000_000_000, 2_500_000_000));
+        assert_eq!(reports[2].sent_packets_count, 1);
+        assert_eq!(reports[2].received_packets_count, 0);
+        assert!(reports[2].latency.is_none());
+    }
+
+    #[test]
+    fn duplicates_are_recorded_once() {
+        let sent_packets_counter = Arc::new(SentPacketsCounter::new(Duration::from_secs(1)));
+        let mut interval_recorder = IntervalRecorder::new(false, true, 10, sent_packets_counter.clone(), None);
+
+        for sent_time_ms in [0, 300, 600, 1_100] {
+            sent_packets_counter.packet_sent(Duration::from_millis(sent_time_ms));
+        }
+        interval_recorder.record(&received_packet(0, Duration::from_millis(100), 100_000));
+        interval_recorder.record(&received_packet(2, Duration::from_millis(700), 100_000));
+        interval_recorder.record(&received_packet(2, Duration::from_millis(800), 900_000));
+        // A duplicate of a packet of the previous interval is not a late arrival
+        interval_recorder.record(&received_packet(0, Duration::from_millis(1_200), 900_000));
+        interval_recorder.record(&received_packet(3, Duration::from_millis(1_300), 100_000));
+        let reports = interval_recorder.finish(Duration::from_millis(2_000));
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].received_packets_count, 2);
+        assert_eq!(reports[0].lost_packets_count, 1);
+        assert!((reports[0].latency.unwrap().max - 0.1).abs() < 1e-9);
+        assert_eq!(reports[1].received_packets_count, 1);
+        assert_eq!(reports[1].lost_packets_count, 0);
+        assert!((reports[1].latency.unwrap().max - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn intervals_without_packets_are_closed() {
+        let sent_packets_counter = Arc::new(SentPacketsCounter::new(Duration::from_secs(1)));
+        let mut interval_recorder = IntervalRecorder::new(false, true, 10, sent_packets_counter.clone(), None);
+
+        interval_recorder.record(&received_packet(0, Duration::from_millis(100), 100_000));
+        for sent_time_ms in [0, 1_000, 2_000] {
+            sent_packets_counter.packet_sent(Duration::from_millis(sent_time_ms));
+        }
+        // No packets are received after the first one, the intervals are closed anyway
+        interval_recorder.close_until(Duration::from_millis(2_010));
+        let reports = interval_recorder.finish(Duration::ZERO);
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].received_packets_count, 1);
+        assert_eq!((reports[1].start_time_ns, reports[1].end_time_ns), (1_000_000_000, 2_000_000_000));
+        assert_eq!(reports[1].sent_packets_count, 1);
+        assert_eq!(reports[1].received_packets_count, 0);
+    }
+
+    #[test]
+    fn intervals_are_closed_by_the_timer() {
+        let sent_packets_counter = Arc::new(SentPacketsCounter::new(Duration::from_millis(20)));
+        let mut interval_recorder = IntervalRecorder::new(false, true, 10, sent_packets_counter, None);
+
+        interval_recorder.start_timer(Instant::now()).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        // Ending at the start of the test only returns the intervals closed by the timer
+        let reports = interval_recorder.finish(Duration::ZERO);
+
+        assert!(reports.len() >= 3, "{} intervals closed", reports.len());
+        assert_eq!(reports[2].start_time_ns, 40_000_000);
+    }
+}
@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod json_result_tests {
+    use std::time::Duration;
+    use rperf::json_result::{JsonResult, JSON_SCHEMA_VERSION};
+    use rperf::packet::{SentPacket, ReceivedPacket};
+    use rperf::test_parameters::{TestParameters, RecordingMode};
+    use rperf::test_result::TestResult;
+
+    fn test_result() -> TestResult {
+        test_result_with_received_packets(2)
+    }
+
+    fn test_result_with_received_packets(received_packets_count: usize) -> TestResult {
+        let test_parameters = TestParameters {
+            server_ip: "127.0.0.1".to_string(),
+            server_port: 5555,
+            protocol: "udp".to_string(),
+            test_duration_valid: Duration::from_secs(1),
+            test_duration_total: Duration::from_secs(1),
+            warmup_duration: Duration::from_secs(0),
+            packets_per_second: 1000,
+            packet_size: 64,
+            output_rtt: true,
+            measure_owl: true,
+            recording_mode: RecordingMode::PerPacket,
+            deadlines: vec![Duration::from_millis(2)],
+            max_deadline_miss_ratio: Some(0.01),
+            report_interval: None
+        };
+
+        let base_ns = 1_630_000_000_000_000_000_u64;
+        let sent_packets: Vec<_> = (0..3).map(|index| SentPacket {
+            index,
+            sent_duration: Duration::from_millis(index),
+            sent_timestamp_ns: base_ns + index * 1_000_000,
+            is_warmup: false
+        }).collect();
+        let received_packets: Vec<_> = sent_packets.iter().take(received_packets_count).map(|sent_packet| ReceivedPacket {
+            index: sent_packet.index,
+            received_duration: sent_packet.sent_duration + Duration::from_micros(100),
+            sent_timestamp_ns: sent_packet.sent_timestamp_ns,
+            received_timestamp_ns: sent_packet.sent_timestamp_ns + 100_000,
+            server_rx_timestamp_ns: sent_packet.sent_timestamp_ns + 40_000,
+            server_tx_timestamp_ns: sent_packet.sent_timestamp_ns + 50_000
+        }).collect();
+
+        TestResult::from_tx_rx_times(test_parameters, &sent_packets, &received_packets, true)
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut json = Vec::new();
+        test_result().to_json(true).write(&mut json).unwrap();
+        let json_result: JsonResult = serde_json::from_slice(&json).unwrap();
+
+        assert_eq!(json_result.schema_version, JSON_SCHEMA_VERSION);
+        assert_eq!(json_result.test_parameters.deadlines, vec![Duration::from_millis(2)]);
+        assert_eq!(json_result.summary.sent_packets_count, 3);
+        assert_eq!(json_result.summary.lost_packets_count, 1);
+        assert_eq!(json_result.summary.average_latency_client_to_server, Some(0.04));
+        assert!((json_result.summary.average_server_processing_time.unwrap() - 0.01).abs() < 1e-12);
+        assert_eq!(json_result.summary.latency.unwrap().count, 2);
+        assert_eq!(json_result.summary.deadlines[0].missed_packets_count, 1);
+        assert!(json_result.summary.deadline_miss_ratio_exceeded);
+        assert_eq!(json_result.packet_results.unwrap().len(), 2);
+        assert_eq!(json_result.lost_packet_indices, Some(vec![2]));
+    }
+
+    #[test]
+    fn packet_results_are_optional() {
+        let json_result = test_result().to_json(false);
+
+        assert!(json_result.packet_results.is_none());
+        assert!(json_result.lost_packet_indices.is_none());
+    }
+
+    #[test]
+    fn results_without_received_packets_are_read_back() {
+        let mut json = Vec::new();
+        test_result_with_received_packets(0).to_json(false).write(&mut json).unwrap();
+        let json_result: JsonResult = serde_json::from_slice(&json).unwrap();
+
+        assert_eq!(json_result.summary.received_packets_count, 0);
+        assert_eq!(json_result.summary.average_latency, None);
+        assert_eq!(json_result.summary.average_latency_client_to_server, None);
+        assert_eq!(json_result.summary.average_server_processing_time, None);
+        assert_eq!(json_result.summary.average_network_round_trip_time, None);
+    }
+
+    #[test]
+    fn reject_other_schema_versions() {
+        let path = std::env::temp_dir().join(format!("rperf_json_result_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut json_result = test_result().to_json(false);
+        json_result.write_file(path).unwrap();
+        assert!(JsonResult::read_file(path).is_ok());
+
+        json_result.schema_version = JSON_SCHEMA_VERSION + 1;
+        json_result.write_file(path).unwrap();
+        assert!(JsonResult::read_file(path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "prometheus")]
+
+#[cfg(test)]
+mod metrics_tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::Duration;
+    use rperf::metrics::{registry, serve};
+    use rperf::packet::ReceivedPacket;
+
+    fn received_packet(index: u64, round_trip_time_ns: u64) -> ReceivedPacket {
+        let sent_timestamp_ns = 1_000_000_000 + index * 1_000_000;
+        ReceivedPacket {
+            index,
+            received_duration: Duration::from_millis(index),
+            sent_timestamp_ns,
+            received_timestamp_ns: sent_timestamp_ns + round_trip_time_ns,
+            server_rx_timestamp_ns: sent_timestamp_ns + round_trip_time_ns / 2,
+            server_tx_timestamp_ns: sent_timestamp_ns + round_trip_time_ns / 2
+        }
+    }
+
+    #[test]
+    fn render_client_and_server_metrics() {
+        let client = registry().client(0x1234_5678, "127.0.0.1:7700", "udp");
+        for _ in 0..4 {
+            client.packet_sent();
+        }
+        client.packet_received(&received_packet(0, 200_000), false);
+        client.packet_received(&received_packet(3, 2_000_000), false);
+
+        let server = registry().server("tcp", 7701);
+        {
+            let _connection = server.connection_opened();
+            server.packet_received();
+            server.packet_sent();
+        }
+        server.packet_rejected();
+
+        let metrics = registry().render();
+        let client_labels = "session_id=\"305419896\",server=\"127.0.0.1:7700\",protocol=\"udp\"";
+        assert!(metrics.contains(&format!("rperf_client_sent_packets_total{{{}}} 4\n", client_labels)));
+        assert!(metrics.contains(&format!("rperf_client_received_packets_total{{{}}} 2\n", client_labels)));
+        assert!(metrics.contains(&format!("rperf_client_lost_packets_total{{{}}} 2\n", client_labels)));
+        assert!(metrics.contains(&format!("rperf_client_latency_seconds_bucket{{{},direction=\"round_trip\",le=\"0.00025\"}} 1\n", client_labels)));
+        assert!(metrics.contains(&format!("rperf_client_latency_seconds_bucket{{{},direction=\"round_trip\",le=\"+Inf\"}} 2\n", client_labels)));
+        assert!(metrics.contains(&format!("rperf_client_latency_seconds_count{{{},direction=\"round_trip\"}} 2\n", client_labels)));
+        assert!(!metrics.contains(&format!("{},direction=\"client_to_server\"", client_labels)));
+
+        let server_labels = "protocol=\"tcp\",port=\"7701\"";
+        assert!(metrics.contains(&format!("rperf_server_connections_total{{{}}} 1\n", server_labels)));
+        assert!(metrics.contains(&format!("rperf_server_active_connections{{{}}} 0\n", server_labels)));
+        assert!(metrics.contains(&format!("rperf_server_received_packets_total{{{}}} 1\n", server_labels)));
+        assert!(metrics.contains(&format!("rperf_server_sent_packets_total{{{}}} 1\n", server_labels)));
+        assert!(metrics.contains(&format!("rperf_server_rejected_packets_total{{{}}} 1\n", server_labels)));
+    }
+
+    #[test]
+    fn serve_metrics_over_http() {
+        let _server = registry().server("udp", 7702);
+        let address = serve("127.0.0.1:0").unwrap();
+
+        let request = |path: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = request("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("rperf_server_received_packets_total{protocol=\"udp\",port=\"7702\"} 0\n"));
+        assert!(request("/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        // Connections without a request do not block the others as long as a request thread is free
+        let _stalled_streams: Vec<_> = (0..3).map(|_| TcpStream::connect(address).unwrap()).collect();
+        assert!(request("/metrics").starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn dropped_metrics_are_removed() {
+        let client = registry().client(0x0bad_cafe, "127.0.0.1:7703", "tcp");
+        let server = registry().server("tcp", 7703);
+        assert!(registry().render().contains("session_id=\"195939070\""));
+        assert!(registry().render().contains("port=\"7703\""));
+
+        drop(client);
+        drop(server);
+        assert!(!registry().render().contains("session_id=\"195939070\""));
+        assert!(!registry().render().contains("port=\"7703\""));
+    }
+}
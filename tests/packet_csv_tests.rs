@@ -0,0 +1,93 @@
+#[cfg(test)]
+mod packet_csv_tests {
+    use std::time::Duration;
+    use rperf::packet::{SentPacket, ReceivedPacket};
+    use rperf::packet_csv::{CSV_COLUMNS, CSV_SCHEMA_VERSION, write_with_comments};
+    use rperf::test_parameters::{TestParameters, RecordingMode};
+    use rperf::test_result::TestResult;
+
+    fn test_result() -> TestResult {
+        let test_parameters = TestParameters {
+            server_ip: "127.0.0.1".to_string(),
+            server_port: 5555,
+            protocol: "udp".to_string(),
+            test_duration_valid: Duration::from_secs(1),
+            test_duration_total: Duration::from_secs(2),
+            warmup_duration: Duration::from_secs(1),
+            packets_per_second: 1000,
+            packet_size: 64,
+            output_rtt: false,
+            measure_owl: true,
+            recording_mode: RecordingMode::PerPacket,
+            deadlines: Vec::new(),
+            max_deadline_miss_ratio: None,
+            report_interval: None
+        };
+
+        let base_ns = 1_630_000_000_000_000_000_u64;
+        let sent_packets: Vec<_> = (0..6).map(|index| SentPacket {
+            index,
+            sent_duration: Duration::from_millis(index),
+            sent_timestamp_ns: base_ns + index * 1_000_000,
+            is_warmup: index < 2
+        }).collect();
+        // Packets 1 (warm-up) and 4 are lost, packet 3 arrives twice
+        let received_packets: Vec<_> = [0, 2, 3, 3, 5].iter().map(|index| &sent_packets[*index]).map(|sent_packet| ReceivedPacket {
+            index: sent_packet.index,
+            received_duration: sent_packet.sent_duration + Duration::from_micros(100),
+            sent_timestamp_ns: sent_packet.sent_timestamp_ns,
+            received_timestamp_ns: sent_packet.sent_timestamp_ns + 100_000,
+            server_rx_timestamp_ns: sent_packet.sent_timestamp_ns + 40_000,
+            server_tx_timestamp_ns: sent_packet.sent_timestamp_ns + 50_000
+        }).collect();
+
+        TestResult::from_tx_rx_times(test_parameters, &sent_packets, &received_packets, false)
+    }
+
+    #[test]
+    fn rows_of_all_sent_packets() {
+        let mut csv = Vec::new();
+        test_result().write_packet_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+
+        assert_eq!(lines[0], format!("# rperf packet results, CSV schema version {}", CSV_SCHEMA_VERSION));
+        assert_eq!(lines[1], CSV_COLUMNS.join(","));
+        assert_eq!(lines.len(), 2 + 6);
+
+        let rows: Vec<Vec<&str>> = lines[2..].iter().map(|line| line.split(',').collect()).collect();
+        assert!(rows.iter().all(|row| row.len() == CSV_COLUMNS.len()));
+        assert_eq!(rows.iter().map(|row| row[0]).collect::<Vec<_>>(), vec!["0", "1", "2", "3", "4", "5"]);
+        assert_eq!(rows.iter().map(|row| row[1]).collect::<Vec<_>>(), vec!["1", "1", "0", "0", "0", "0"]);
+        assert_eq!(rows.iter().map(|row| row[2]).collect::<Vec<_>>(), vec!["0", "1", "0", "0", "1", "0"]);
+        assert_eq!(rows.iter().map(|row| row[3]).collect::<Vec<_>>(), vec!["0", "0", "0", "1", "0", "0"]);
+
+        let row = &rows[3];
+        assert_eq!(row[4], "1630000000003000000");
+        assert_eq!(row[5], "1630000000003100000");
+        assert_eq!(row[6], "1630000000003040000");
+        assert_eq!(row[7], "1630000000003050000");
+        assert_eq!(row[8], "0.003000000");
+        assert_eq!(row[9], "0.003100000");
+        assert_eq!(row[10], "0.100000");
+        assert_eq!(row[11], "0.010000");
+        assert_eq!(row[12], "0.050000");
+        assert_eq!(row[13], "0.040000");
+        assert_eq!(row[14], "0.050000");
+        assert_eq!(row[15], "0.000000");
+        assert!(rows[5][15].is_empty());
+        assert!(rows[4][4..].iter().all(|value| value.is_empty()));
+    }
+
+    #[test]
+    fn comments_follow_the_schema_version() {
+        let mut csv = Vec::new();
+        write_with_comments(&test_result(), &["Duplicate Packets: 1".to_string()], &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+
+        assert_eq!(lines[0], format!("# rperf packet results, CSV schema version {}", CSV_SCHEMA_VERSION));
+        assert_eq!(lines[1], "# Duplicate Packets: 1");
+        assert_eq!(lines[2], CSV_COLUMNS.join(","));
+    }
+}
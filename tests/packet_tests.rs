@@ -0,0 +1,47 @@
+#[cfg(test)]
+mod packet_tests {
+    use std::time::Duration;
+    use rperf::packet::{PacketHeader, TestClock, HEADER_LENGTH, PROTOCOL_VERSION, FLAG_PONG, encode_packet, system_time_ns};
+
+    #[test]
+    fn header_round_trip() {
+        let mut header = PacketHeader::new(0xDEAD_BEEF, 42, 0);
+        header.client_timestamp_ns = 1_630_000_000_123_456_789;
+        let pong = header.to_pong(1_630_000_000_223_456_789, 1_630_000_000_223_459_000);
+
+        let payload = encode_packet(&pong, 64);
+        assert_eq!(payload.len(), 64);
+
+        let decoded = PacketHeader::decode(&payload).unwrap();
+        assert_eq!(decoded, pong);
+        assert!(decoded.is_pong());
+        assert_eq!(decoded.flags, FLAG_PONG);
+        assert_eq!(decoded.version, PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn reject_foreign_packets() {
+        assert!(PacketHeader::decode("_client_".as_bytes()).is_err());
+        assert!(PacketHeader::decode(&[0u8; HEADER_LENGTH]).is_err());
+
+        let mut payload = encode_packet(&PacketHeader::new(1, 1, 0), HEADER_LENGTH);
+        payload[4] = PROTOCOL_VERSION + 1;
+        assert!(PacketHeader::decode(&payload).is_err());
+    }
+
+    #[test]
+    fn reject_truncated_packets() {
+        let payload = encode_packet(&PacketHeader::new(1, 1, 0), HEADER_LENGTH);
+        assert!(PacketHeader::decode(&payload[..HEADER_LENGTH - 1]).is_err());
+    }
+
+    #[test]
+    fn test_clock_timestamps_follow_the_monotonic_clock() {
+        let clock = TestClock::new();
+        let start_timestamp_ns = clock.timestamp_ns(Duration::ZERO);
+        assert!(start_timestamp_ns.abs_diff(system_time_ns()) < 1_000_000_000);
+
+        assert_eq!(clock.timestamp_ns(Duration::from_micros(1_500)) - clock.timestamp_ns(Duration::from_micros(500)), 1_000_000);
+        assert!(clock.elapsed() < Duration::from_secs(1));
+    }
+}
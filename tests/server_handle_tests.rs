@@ -0,0 +1,49 @@
+#[cfg(test)]
+mod server_handle_tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+    use rperf::error::RperfError;
+    use rperf::server_handle::{ServerHandle, StopSignal};
+
+    #[tokio::test]
+    async fn failing_worker_stops_the_others() {
+        let stopped_workers = Arc::new(AtomicUsize::new(0));
+        let workers: Vec<_> = (0..3).map(|index| {
+            let stopped_workers = stopped_workers.clone();
+            move |stop: StopSignal| {
+                if index == 1 {
+                    thread::sleep(Duration::from_millis(50));
+                    return Err(RperfError::InvalidConfig("worker failed".to_string()));
+                }
+                while !stop.is_set() {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                stopped_workers.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+        }).collect();
+
+        let mut server = ServerHandle::spawn_workers(([127, 0, 0, 1], 0).into(), "rperf-test", workers).unwrap();
+        // The failure is reported while the other workers are still stopping
+        let result = tokio::time::timeout(Duration::from_secs(1), server.wait()).await.unwrap();
+        assert!(matches!(result, Err(RperfError::InvalidConfig(_))));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(stopped_workers.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn workers_stop_on_shutdown() {
+        let workers: Vec<_> = (0..2).map(|_| |stop: StopSignal| {
+            while !stop.is_set() {
+                thread::sleep(Duration::from_millis(10));
+            }
+            Ok(())
+        }).collect();
+
+        let server = ServerHandle::spawn_workers(([127, 0, 0, 1], 0).into(), "rperf-test", workers).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), server.shutdown()).await.unwrap().unwrap();
+    }
+}
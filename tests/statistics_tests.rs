@@ -0,0 +1,132 @@
+#[cfg(test)]
+mod statistics_tests {
+    use rperf::packet_result::PacketResult;
+    use rperf::statistics::{LatencyStatistics, JitterStatistics, ReorderingStatistics, LossStatistics, MannWhitneyU, percentile};
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
+    }
+
+    fn packet_result(index: u64, round_trip_time_ns: u64) -> PacketResult {
+        PacketResult {
+            index,
+            sent_time_ns: index * 1_000_000,
+            received_time_ns: index * 1_000_000 + round_trip_time_ns,
+            sent_timestamp_ns: 0,
+            received_timestamp_ns: 0,
+            server_rx_timestamp_ns: 0,
+            server_tx_timestamp_ns: 0,
+            round_trip_time_ns,
+            server_processing_time_ns: None,
+            latency_ns: round_trip_time_ns,
+            latency_client_to_server_ns: None,
+            latency_server_to_client_ns: None,
+            duplicates_count: 0
+        }
+    }
+
+    #[test]
+    fn statistics_of_uniform_latencies() {
+        let latencies_ns: Vec<u64> = (1..=1000).map(|i| i * 1_000).collect();
+        let statistics = LatencyStatistics::from_nanos(&latencies_ns).unwrap();
+
+        assert_eq!(statistics.count, 1000);
+        assert_close(statistics.min, 0.001);
+        assert_close(statistics.max, 1.0);
+        assert_close(statistics.median, 0.5);
+        assert_close(statistics.p90, 0.9);
+        assert_close(statistics.p99, 0.99);
+        assert_close(statistics.p99_9, 0.999);
+        assert_close(statistics.p99_99, 1.0);
+        assert_close(statistics.mean, 0.5005);
+        assert!((statistics.std_dev - 0.288_674_990).abs() < 1e-6);
+    }
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let sorted = [10, 20, 30, 40];
+
+        assert_eq!(percentile(&sorted, 0_f64), 10);
+        assert_eq!(percentile(&sorted, 50_f64), 20);
+        assert_eq!(percentile(&sorted, 75.1), 40);
+        assert_eq!(percentile(&sorted, 100_f64), 40);
+        assert!(LatencyStatistics::from_nanos(&[]).is_none());
+    }
+
+    #[test]
+    fn jitter_of_round_trip_times() {
+        // Packet 3 is lost, so there is no IPDV between packet 2 and 4
+        let packet_results = vec![packet_result(0, 100_000), packet_result(1, 300_000), packet_result(2, 200_000), packet_result(4, 500_000)];
+        let jitter = JitterStatistics::from_packet_results(&packet_results, |packet_result| Some((packet_result.received_time_ns, packet_result.round_trip_time_ns))).unwrap();
+
+        assert_eq!(jitter.count, 4);
+        assert_close(jitter.interarrival_jitter, 0.035_595_703_125);
+        assert_close(jitter.ipdv_min, -0.1);
+        assert_close(jitter.ipdv_max, 0.2);
+        assert_close(jitter.ipdv_mean_absolute, 0.15);
+        assert_close(jitter.pdv_mean, 0.175);
+        assert_close(jitter.pdv_p99, 0.4);
+        assert_close(jitter.pdv_max, 0.4);
+
+        assert!(JitterStatistics::from_packet_results(&packet_results[..1], |packet_result| Some((packet_result.received_time_ns, packet_result.round_trip_time_ns))).is_none());
+    }
+
+    #[test]
+    fn reordering_per_rfc_4737() {
+        let reordering = ReorderingStatistics::from_arrival_order(&[0, 1, 3, 2, 4, 7, 5, 6, 8]);
+
+        assert_eq!(reordering.received_packets_count, 9);
+        assert_eq!(reordering.reordered_packets_count, 3);
+        assert_close(reordering.reordered_ratio(), 3_f64 / 9_f64);
+        assert_eq!(reordering.max_reordering_extent, 2);
+        assert_close(reordering.mean_reordering_extent, 4_f64 / 3_f64);
+        // Packet 6 is reordered, but arrives directly after packet 5
+        assert_eq!(reordering.n_reordering.get(&1), Some(&2));
+        assert_eq!(reordering.n_reordering.get(&2), None);
+
+        // Only packet 0 directly follows a packet with a higher index
+        let reordering = ReorderingStatistics::from_arrival_order(&[3, 0, 1, 2]);
+        assert_eq!(reordering.reordered_packets_count, 3);
+        assert_eq!(reordering.n_reordering.get(&1), Some(&1));
+        assert_eq!(reordering.n_reordering.get(&2), None);
+        assert_eq!(reordering.max_reordering_extent, 3);
+    }
+
+    #[test]
+    fn loss_bursts_and_gaps() {
+        let lost: Vec<bool> = "RLLRRLRRRLLLRR".chars().map(|packet| packet == 'L').collect();
+        let loss = LossStatistics::from_loss_sequence(&lost);
+
+        assert_eq!(loss.packets_count, 14);
+        assert_eq!(loss.lost_packets_count, 6);
+        assert_eq!(loss.burst_count, 3);
+        assert_eq!(loss.longest_burst, 3);
+        assert_close(loss.mean_burst_length, 2_f64);
+        assert_eq!(loss.burst_lengths.into_iter().collect::<Vec<_>>(), vec![(1, 1), (2, 1), (3, 1)]);
+        assert_eq!(loss.consecutive_losses.into_iter().collect::<Vec<_>>(), vec![(1, 6), (2, 3), (3, 1)]);
+        // Received runs before the first and after the last burst are no gaps
+        assert_eq!(loss.gap_count, 2);
+        assert_eq!(loss.min_gap, 2);
+        assert_eq!(loss.max_gap, 3);
+        assert_close(loss.mean_gap, 2.5);
+        assert_close(loss.gilbert_elliott_p, 3_f64 / 7_f64);
+        assert_close(loss.gilbert_elliott_r, 3_f64 / 6_f64);
+    }
+
+    #[test]
+    fn mann_whitney_u() {
+        let test = MannWhitneyU::from_samples(&[1, 2, 3, 4, 5], &[6, 7, 8, 9, 10]).unwrap();
+        assert_close(test.u, 25_f64);
+        assert_close(test.probability_greater, 1_f64);
+        assert!((test.z - 2.506_718).abs() < 1e-6);
+        assert!((test.p_value - 0.012_186).abs() < 1e-5);
+
+        // Ties get the mean of their ranks
+        let test = MannWhitneyU::from_samples(&[1, 2, 2, 3], &[2, 3, 3, 4]).unwrap();
+        assert_close(test.u, 13_f64);
+        assert!(test.p_value > 0.1);
+
+        assert!(MannWhitneyU::from_samples(&[5, 5], &[5, 5]).is_none());
+        assert!(MannWhitneyU::from_samples(&[], &[1]).is_none());
+    }
+}
@@ -0,0 +1,169 @@
+#[cfg(test)]
+mod test_result_tests {
+    use std::time::Duration;
+    use rperf::packet::{SentPacket, ReceivedPacket};
+    use rperf::test_parameters::{TestParameters, RecordingMode};
+    use rperf::test_result::TestResult;
+
+    fn test_parameters(measure_owl: bool) -> TestParameters {
+        TestParameters {
+            server_ip: "127.0.0.1".to_string(),
+            server_port: 5555,
+            protocol: "udp".to_string(),
+            test_duration_valid: Duration::from_secs(1),
+            test_duration_total: Duration::from_secs(1),
+            warmup_duration: Duration::from_secs(0),
+            packets_per_second: 1000,
+            packet_size: 64,
+            output_rtt: true,
+            measure_owl,
+            recording_mode: RecordingMode::PerPacket,
+            deadlines: vec![Duration::from_micros(1)],
+            max_deadline_miss_ratio: Some(0.5),
+            report_interval: None
+        }
+    }
+
+    fn sent_packet(index: u64, sent_timestamp_ns: u64) -> SentPacket {
+        SentPacket {
+            index,
+            sent_duration: Duration::from_millis(index),
+            sent_timestamp_ns,
+            is_warmup: false
+        }
+    }
+
+    fn received_packet(sent_packet: &SentPacket, server_rx_timestamp_ns: u64, server_tx_timestamp_ns: u64, received_timestamp_ns: u64) -> ReceivedPacket {
+        ReceivedPacket {
+            index: sent_packet.index,
+            received_duration: sent_packet.sent_duration + Duration::from_nanos(received_timestamp_ns - sent_packet.sent_timestamp_ns),
+            sent_timestamp_ns: sent_packet.sent_timestamp_ns,
+            received_timestamp_ns,
+            server_rx_timestamp_ns,
+            server_tx_timestamp_ns,
+        }
+    }
+
+    #[test]
+    fn one_way_latencies_are_exact() {
+        let base_ns = 1_630_000_000_000_000_000_u64;
+        let sent_packets = vec![sent_packet(0, base_ns), sent_packet(1, base_ns + 1_000_000)];
+        let received_packets = vec![
+            received_packet(&sent_packets[0], base_ns + 1_001, base_ns + 1_001, base_ns + 2_003),
+            received_packet(&sent_packets[1], base_ns + 1_000_017, base_ns + 1_000_022, base_ns + 1_000_030)
+        ];
+
+        let test_result = TestResult::from_tx_rx_times(test_parameters(true), &sent_packets, &received_packets, true);
+        let packet_results: Vec<_> = test_result.packet_results.iter().collect();
+
+        assert_eq!(packet_results[0].latency_client_to_server_ns, Some(1_001));
+        assert_eq!(packet_results[0].latency_server_to_client_ns, Some(1_002));
+        assert_eq!(packet_results[1].latency_client_to_server_ns, Some(17));
+        assert_eq!(packet_results[1].latency_server_to_client_ns, Some(8));
+        assert_eq!(packet_results[1].latency_ns, 30);
+    }
+
+    #[test]
+    fn send_and_receive_times_are_not_swapped() {
+        let base_ns = 1_630_000_000_000_000_000_u64;
+        let sent_packets = vec![sent_packet(0, base_ns), sent_packet(1, base_ns + 1_000_000), sent_packet(2, base_ns + 2_000_000)];
+        let received_packets = vec![
+            received_packet(&sent_packets[0], base_ns + 10_000, base_ns + 30_000, base_ns + 50_000),
+            received_packet(&sent_packets[1], base_ns + 1_010_000, base_ns + 1_020_000, base_ns + 1_040_000)
+        ];
+
+        let test_result = TestResult::from_tx_rx_times(test_parameters(false), &sent_packets, &received_packets, true);
+        let packet_result = test_result.packet_results.back().unwrap();
+
+        assert_eq!(packet_result.sent_time_ns, 1_000_000);
+        assert_eq!(packet_result.received_time_ns, 1_040_000);
+        assert_eq!(packet_result.sent_timestamp_ns, base_ns + 1_000_000);
+        assert_eq!(packet_result.received_timestamp_ns, base_ns + 1_040_000);
+        assert_eq!(test_result.sent_duration_seconds, 0.002);
+    }
+
+    #[test]
+    fn server_processing_time_is_separated() {
+        let base_ns = 1_630_000_000_000_000_000_u64;
+        let sent_packets = vec![sent_packet(0, base_ns), sent_packet(1, base_ns + 1_000_000)];
+        let received_packets = vec![
+            received_packet(&sent_packets[0], base_ns + 10_000, base_ns + 30_000, base_ns + 50_000),
+            received_packet(&sent_packets[1], base_ns + 1_010_000, base_ns + 1_020_000, base_ns + 1_040_000)
+        ];
+
+        let test_result = TestResult::from_tx_rx_times(test_parameters(false), &sent_packets, &received_packets, true);
+        let packet_result = test_result.packet_results.front().unwrap();
+
+        assert_eq!(packet_result.round_trip_time_ns, 50_000);
+        assert_eq!(packet_result.server_processing_time_ns, Some(20_000));
+        assert_eq!(packet_result.network_round_trip_time_ns(), 30_000);
+        assert!((test_result.average_server_processing_time() - 0.015).abs() < 1e-12);
+        assert!((test_result.average_network_round_trip_time() - 0.030).abs() < 1e-12);
+
+        let distribution = test_result.server_processing_time_distribution(Duration::from_micros(10));
+        assert_eq!(distribution.get(&10_000), Some(&1));
+        assert_eq!(distribution.get(&20_000), Some(&1));
+    }
+
+    #[test]
+    fn lost_packets_are_counted() {
+        let base_ns = 1_630_000_000_000_000_000_u64;
+        let sent_packets = vec![sent_packet(0, base_ns), sent_packet(1, base_ns + 1_000_000), sent_packet(2, base_ns + 2_000_000)];
+        let received_packets = vec![received_packet(&sent_packets[0], base_ns + 500, base_ns + 500, base_ns + 1_000)];
+
+        let test_result = TestResult::from_tx_rx_times(test_parameters(false), &sent_packets, &received_packets, true);
+
+        assert_eq!(test_result.sent_packets_count, 3);
+        assert_eq!(test_result.received_packets_count, 1);
+        assert_eq!(test_result.lost_packets_count, 2);
+        assert_eq!(test_result.lost_packet_indices, vec![1, 2]);
+        let loss = test_result.loss_statistics().unwrap();
+        assert_eq!(loss.burst_count, 1);
+        assert_eq!(loss.longest_burst, 2);
+        assert_eq!(test_result.packet_results.front().unwrap().latency_ns, 1_000);
+    }
+
+    #[test]
+    fn duplicates_are_counted_once() {
+        let base_ns = 1_630_000_000_000_000_000_u64;
+        let sent_packets = vec![sent_packet(0, base_ns), sent_packet(1, base_ns + 1_000_000), sent_packet(2, base_ns + 2_000_000)];
+        let received_packets = vec![
+            received_packet(&sent_packets[0], base_ns + 500, base_ns + 500, base_ns + 1_000),
+            received_packet(&sent_packets[2], base_ns + 2_000_500, base_ns + 2_000_500, base_ns + 2_001_000),
+            received_packet(&sent_packets[1], base_ns + 1_500_000, base_ns + 1_500_000, base_ns + 2_002_000),
+            received_packet(&sent_packets[2], base_ns + 2_000_500, base_ns + 2_000_500, base_ns + 2_009_000)
+        ];
+
+        let test_result = TestResult::from_tx_rx_times(test_parameters(false), &sent_packets, &received_packets, true);
+
+        assert_eq!(test_result.received_packets_count, 3);
+        assert_eq!(test_result.lost_packets_count, 0);
+        assert_eq!(test_result.duplicate_packets_count, 1);
+        assert_eq!(test_result.packet_results.back().unwrap().round_trip_time_ns, 1_000);
+
+        let reordering = test_result.reordering.unwrap();
+        assert_eq!(reordering.reordered_packets_count, 1);
+        assert_eq!(reordering.max_reordering_extent, 1);
+    }
+
+    #[test]
+    fn deadline_misses_include_lost_packets() {
+        let base_ns = 1_630_000_000_000_000_000_u64;
+        let sent_packets: Vec<_> = (0..4).map(|index| sent_packet(index, base_ns + index * 1_000_000)).collect();
+        let received_packets = vec![
+            received_packet(&sent_packets[0], base_ns + 500, base_ns + 500, base_ns + 1_000),
+            received_packet(&sent_packets[2], base_ns + 2_002_000, base_ns + 2_002_000, base_ns + 2_005_000),
+            received_packet(&sent_packets[3], base_ns + 3_000_200, base_ns + 3_000_200, base_ns + 3_000_500)
+        ];
+
+        let test_result = TestResult::from_tx_rx_times(test_parameters(false), &sent_packets, &received_packets, true);
+        let deadlines = test_result.deadline_statistics();
+
+        assert_eq!(deadlines.len(), 1);
+        assert_eq!(deadlines[0].packets_count, 4);
+        assert_eq!(deadlines[0].missed_packets_count, 2);
+        assert_eq!(deadlines[0].longest_miss_run, Some(2));
+        assert!((deadlines[0].miss_ratio() - 0.5).abs() < 1e-12);
+        assert!(!test_result.deadline_miss_ratio_exceeded());
+    }
+}